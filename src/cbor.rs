@@ -1,4 +1,9 @@
-#[derive(Clone, Debug)]
+use std::fmt;
+
+/// Maximum nesting of arrays, maps and tags accepted when decoding.
+const MAX_DECODE_DEPTH: usize = 256;
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Cbor {
     Uint(u64),
     Nint(u64),
@@ -10,6 +15,9 @@ pub enum Cbor {
     True,
     False,
     Null,
+    Undefined,
+    Simple(u8),
+    Float(f64),
 }
 
 impl Cbor {
@@ -60,10 +68,320 @@ impl Cbor {
             Cbor::True => encode_header(7, 21),
             Cbor::False => encode_header(7, 20),
             Cbor::Null => encode_header(7, 22),
+            Cbor::Undefined => encode_header(7, 23),
+            Cbor::Simple(value) => encode_header(7, *value as u64),
+            Cbor::Float(number) => {
+                let mut encoded = vec![7 << 5 | 27];
+                encoded.extend(number.to_be_bytes());
+
+                encoded
+            }
+        }
+    }
+
+    /// Decode a single, complete CBOR data item from `bytes`.
+    ///
+    /// Indefinite length strings are joined into a single string, and
+    /// indefinite length arrays and maps are read into their definite
+    /// counterparts. All floating point widths are read as `f64`.
+    ///
+    /// Decoding fails if the input is not well-formed, or if any bytes
+    /// remain after the data item.
+    pub fn deserialize(bytes: &[u8]) -> Result<Cbor, DecodeError> {
+        let mut decoder = Decoder { bytes, offset: 0 };
+
+        let item = decoder.item(0)?;
+
+        if decoder.offset != bytes.len() {
+            return Err(decoder.error(DecodeErrorKind::TrailingBytes));
+        }
+
+        Ok(item)
+    }
+
+    /// Decode the item wrapped in a byte string, or take the item as it is
+    /// if it is not a byte string; as only some SUIT members are wrapped.
+    pub fn decode_wrapped(&self) -> Option<Cbor> {
+        match self {
+            Cbor::Bstr(bytes) => Cbor::deserialize(bytes).ok(),
+            _ => Some(self.clone()),
         }
     }
 }
 
+/// Error produced when decoding malformed CBOR, along with the byte
+/// offset in the input at which the problem was found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeError {
+    pub offset: usize,
+    pub kind: DecodeErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeErrorKind {
+    UnexpectedEnd,
+    TrailingBytes,
+    ReservedAdditionalInformation(u8),
+    IndefiniteLengthNotAllowed(u8),
+    UnexpectedBreak,
+    InvalidChunk(u8),
+    InvalidUtf8,
+    InvalidSimpleValue(u8),
+    NegativeIntegerOverflow,
+    NestingTooDeep,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use DecodeErrorKind::*;
+
+        match self.kind {
+            UnexpectedEnd => write!(f, "unexpected end of input"),
+            TrailingBytes => write!(f, "trailing bytes after data item"),
+            ReservedAdditionalInformation(info) => {
+                write!(f, "reserved additional information value `{}`", info)
+            }
+            IndefiniteLengthNotAllowed(major_type) => write!(
+                f,
+                "indefinite length is not allowed for major type `{}`",
+                major_type
+            ),
+            UnexpectedBreak => write!(f, "break outside of an indefinite length item"),
+            InvalidChunk(major_type) => write!(
+                f,
+                "indefinite length string contains a chunk of major type `{}`",
+                major_type
+            ),
+            InvalidUtf8 => write!(f, "text string is not valid UTF-8"),
+            InvalidSimpleValue(value) => {
+                write!(f, "simple value `{}` uses the two-byte encoding", value)
+            }
+            NegativeIntegerOverflow => write!(f, "negative integer is too large"),
+            NestingTooDeep => write!(f, "data items are nested too deeply"),
+        }?;
+
+        write!(f, " at byte offset {}", self.offset)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Argument of a data item header; either a definite value,
+/// or the marker of an indefinite length.
+enum Argument {
+    Definite(u64),
+    Indefinite,
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn error(&self, kind: DecodeErrorKind) -> DecodeError {
+        DecodeError {
+            offset: self.offset,
+            kind,
+        }
+    }
+
+    fn take(&mut self, count: u64) -> Result<&'a [u8], DecodeError> {
+        let remaining = (self.bytes.len() - self.offset) as u64;
+
+        if count > remaining {
+            return Err(self.error(DecodeErrorKind::UnexpectedEnd));
+        }
+
+        let start = self.offset;
+        self.offset += count as usize;
+
+        Ok(&self.bytes[start..self.offset])
+    }
+
+    fn peek(&self) -> Result<u8, DecodeError> {
+        self.bytes
+            .get(self.offset)
+            .copied()
+            .ok_or_else(|| self.error(DecodeErrorKind::UnexpectedEnd))
+    }
+
+    fn is_break(&self) -> Result<bool, DecodeError> {
+        Ok(self.peek()? == 0xff)
+    }
+
+    /// Read a data item header, returning the major type, the additional
+    /// information and the argument.
+    fn header(&mut self) -> Result<(u8, u8, Argument), DecodeError> {
+        let start = self.offset;
+        let initial = self.take(1)?[0];
+
+        let major_type = initial >> 5;
+        let additional_information = initial & 0x1f;
+
+        let argument = match additional_information {
+            0..=23 => Argument::Definite(additional_information as u64),
+            24..=27 => {
+                let size = 1 << (additional_information - 24);
+                let bytes = self.take(size)?;
+
+                Argument::Definite(
+                    bytes
+                        .iter()
+                        .fold(0u64, |acc, &byte| (acc << 8) | byte as u64),
+                )
+            }
+            31 => Argument::Indefinite,
+            reserved => {
+                self.offset = start;
+                return Err(self.error(DecodeErrorKind::ReservedAdditionalInformation(reserved)));
+            }
+        };
+
+        Ok((major_type, additional_information, argument))
+    }
+
+    fn item(&mut self, depth: usize) -> Result<Cbor, DecodeError> {
+        if depth > MAX_DECODE_DEPTH {
+            return Err(self.error(DecodeErrorKind::NestingTooDeep));
+        }
+
+        let start = self.offset;
+        let (major_type, additional_information, argument) = self.header()?;
+
+        let fail = |kind| DecodeError {
+            offset: start,
+            kind,
+        };
+
+        match (major_type, argument) {
+            (0 | 1 | 6, Argument::Indefinite) => Err(fail(
+                DecodeErrorKind::IndefiniteLengthNotAllowed(major_type),
+            )),
+            (0, Argument::Definite(value)) => Ok(Cbor::Uint(value)),
+            (1, Argument::Definite(value)) => value
+                .checked_add(1)
+                .map(Cbor::Nint)
+                .ok_or_else(|| fail(DecodeErrorKind::NegativeIntegerOverflow)),
+            (2, argument) => Ok(Cbor::Bstr(self.string(2, argument)?)),
+            (3, argument) => {
+                let bytes = self.string(3, argument)?;
+
+                String::from_utf8(bytes)
+                    .map(Cbor::Tstr)
+                    .map_err(|_| fail(DecodeErrorKind::InvalidUtf8))
+            }
+            (4, Argument::Definite(count)) => {
+                let mut elements = Vec::new();
+
+                for _ in 0..count {
+                    elements.push(self.item(depth + 1)?);
+                }
+
+                Ok(Cbor::Array(elements))
+            }
+            (4, Argument::Indefinite) => {
+                let mut elements = Vec::new();
+
+                while !self.is_break()? {
+                    elements.push(self.item(depth + 1)?);
+                }
+                self.offset += 1;
+
+                Ok(Cbor::Array(elements))
+            }
+            (5, Argument::Definite(count)) => {
+                let mut elements = Vec::new();
+
+                for _ in 0..count {
+                    let key = self.item(depth + 1)?;
+                    let value = self.item(depth + 1)?;
+                    elements.push((key, value));
+                }
+
+                Ok(Cbor::Map(elements))
+            }
+            (5, Argument::Indefinite) => {
+                let mut elements = Vec::new();
+
+                while !self.is_break()? {
+                    let key = self.item(depth + 1)?;
+                    let value = self.item(depth + 1)?;
+                    elements.push((key, value));
+                }
+                self.offset += 1;
+
+                Ok(Cbor::Map(elements))
+            }
+            (6, Argument::Definite(number)) => {
+                Ok(Cbor::Tag(number, Box::new(self.item(depth + 1)?)))
+            }
+            (7, Argument::Indefinite) => Err(fail(DecodeErrorKind::UnexpectedBreak)),
+            (7, Argument::Definite(value)) => match additional_information {
+                20 => Ok(Cbor::False),
+                21 => Ok(Cbor::True),
+                22 => Ok(Cbor::Null),
+                23 => Ok(Cbor::Undefined),
+                0..=19 => Ok(Cbor::Simple(value as u8)),
+                24 if value < 32 => Err(fail(DecodeErrorKind::InvalidSimpleValue(value as u8))),
+                24 => Ok(Cbor::Simple(value as u8)),
+                25 => Ok(Cbor::Float(half_to_f64(value as u16))),
+                26 => Ok(Cbor::Float(f32::from_bits(value as u32) as f64)),
+                _ => Ok(Cbor::Float(f64::from_bits(value))),
+            },
+            _ => unreachable!("major type is a 3-bit value"),
+        }
+    }
+
+    /// Read the content of a byte or text string. Indefinite length strings
+    /// must consist of definite length chunks of the same major type.
+    fn string(&mut self, major_type: u8, argument: Argument) -> Result<Vec<u8>, DecodeError> {
+        match argument {
+            Argument::Definite(length) => Ok(self.take(length)?.to_vec()),
+            Argument::Indefinite => {
+                let mut content = Vec::new();
+
+                while !self.is_break()? {
+                    let start = self.offset;
+
+                    match self.header()? {
+                        (chunk_type, _, Argument::Definite(length)) if chunk_type == major_type => {
+                            content.extend(self.take(length)?)
+                        }
+                        (chunk_type, _, _) => {
+                            return Err(DecodeError {
+                                offset: start,
+                                kind: DecodeErrorKind::InvalidChunk(chunk_type),
+                            })
+                        }
+                    }
+                }
+                self.offset += 1;
+
+                Ok(content)
+            }
+        }
+    }
+}
+
+/// Convert an IEEE 754 half precision float to a double precision float.
+fn half_to_f64(half: u16) -> f64 {
+    let exponent = (half >> 10) & 0x1f;
+    let mantissa = (half & 0x3ff) as f64;
+
+    let magnitude = match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (mantissa + 1024.0) * 2f64.powi(exponent as i32 - 25),
+    };
+
+    match half & 0x8000 {
+        0 => magnitude,
+        _ => -magnitude,
+    }
+}
+
 impl From<Vec<u8>> for Cbor {
     fn from(bytes: Vec<u8>) -> Cbor {
         Cbor::Bstr(bytes)
@@ -113,3 +431,195 @@ fn encode_header(major_type: u8, argument: u64) -> Vec<u8> {
 
     encoded
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_hex(encoded: &str) -> Result<Cbor, DecodeError> {
+        Cbor::deserialize(&hex::decode(encoded).unwrap())
+    }
+
+    fn error_at(offset: usize, kind: DecodeErrorKind) -> Result<Cbor, DecodeError> {
+        Err(DecodeError { offset, kind })
+    }
+
+    #[test]
+    fn round_trips_encoded_items() {
+        let items = vec![
+            Cbor::Uint(0),
+            Cbor::Uint(23),
+            Cbor::Uint(24),
+            Cbor::Uint(0xff),
+            Cbor::Uint(0x100),
+            Cbor::Uint(0x1_0000),
+            Cbor::Uint(0xffff_ffff),
+            Cbor::Uint(0x1_0000_0000),
+            Cbor::Uint(u64::MAX),
+            Cbor::Nint(1),
+            Cbor::Nint(65532),
+            Cbor::Nint(u64::MAX),
+            Cbor::Bstr(Vec::new()),
+            Cbor::Bstr(vec![0xab; 300]),
+            Cbor::Tstr(String::from("suit")),
+            Cbor::Tstr(String::from("ünïcode")),
+            Cbor::Array(vec![Cbor::Uint(1), Cbor::Array(Vec::new())]),
+            Cbor::Map(vec![
+                (Cbor::Uint(1), Cbor::Tstr(String::from("a"))),
+                (Cbor::Nint(7), Cbor::Map(Vec::new())),
+            ]),
+            Cbor::Tag(107, Box::new(Cbor::Bstr(vec![1, 2, 3]))),
+            Cbor::True,
+            Cbor::False,
+            Cbor::Null,
+            Cbor::Undefined,
+            Cbor::Simple(16),
+            Cbor::Simple(255),
+            Cbor::Float(1.5),
+            Cbor::Float(-1e300),
+        ];
+
+        for item in items {
+            assert_eq!(Cbor::deserialize(&item.serialize()), Ok(item.clone()));
+        }
+    }
+
+    #[test]
+    fn encodes_the_shortest_argument() {
+        assert_eq!(Cbor::Uint(23).serialize(), hex::decode("17").unwrap());
+        assert_eq!(Cbor::Uint(24).serialize(), hex::decode("1818").unwrap());
+        assert_eq!(Cbor::Uint(1000).serialize(), hex::decode("1903e8").unwrap());
+        assert_eq!(
+            Cbor::Uint(1_000_000).serialize(),
+            hex::decode("1a000f4240").unwrap()
+        );
+        assert_eq!(Cbor::Nint(100).serialize(), hex::decode("3863").unwrap());
+        assert_eq!(
+            Cbor::Nint(65532).serialize(),
+            hex::decode("39fffb").unwrap()
+        );
+    }
+
+    #[test]
+    fn decodes_indefinite_lengths() {
+        assert_eq!(
+            decode_hex("5f42010243030405ff"),
+            Ok(Cbor::Bstr(vec![1, 2, 3, 4, 5]))
+        );
+        assert_eq!(
+            decode_hex("7f657374726561646d696e67ff"),
+            Ok(Cbor::Tstr(String::from("streaming")))
+        );
+        assert_eq!(
+            decode_hex("9f018202039f0405ffff"),
+            Ok(Cbor::Array(vec![
+                Cbor::Uint(1),
+                Cbor::Array(vec![Cbor::Uint(2), Cbor::Uint(3)]),
+                Cbor::Array(vec![Cbor::Uint(4), Cbor::Uint(5)]),
+            ]))
+        );
+        assert_eq!(
+            decode_hex("bf61610161629f0203ffff"),
+            Ok(Cbor::Map(vec![
+                (Cbor::Tstr(String::from("a")), Cbor::Uint(1)),
+                (
+                    Cbor::Tstr(String::from("b")),
+                    Cbor::Array(vec![Cbor::Uint(2), Cbor::Uint(3)])
+                ),
+            ]))
+        );
+    }
+
+    #[test]
+    fn decodes_tags_and_simple_values() {
+        assert_eq!(
+            decode_hex("d82076687474703a2f2f7777772e6578616d706c652e636f6d"),
+            Ok(Cbor::Tag(
+                32,
+                Box::new(Cbor::Tstr(String::from("http://www.example.com")))
+            ))
+        );
+        assert_eq!(decode_hex("f4"), Ok(Cbor::False));
+        assert_eq!(decode_hex("f5"), Ok(Cbor::True));
+        assert_eq!(decode_hex("f6"), Ok(Cbor::Null));
+        assert_eq!(decode_hex("f7"), Ok(Cbor::Undefined));
+        assert_eq!(decode_hex("f0"), Ok(Cbor::Simple(16)));
+        assert_eq!(decode_hex("f8ff"), Ok(Cbor::Simple(255)));
+        assert_eq!(decode_hex("f93c00"), Ok(Cbor::Float(1.0)));
+        assert_eq!(decode_hex("f9c400"), Ok(Cbor::Float(-4.0)));
+        assert_eq!(decode_hex("f90001"), Ok(Cbor::Float(5.960464477539063e-8)));
+        assert_eq!(decode_hex("f97c00"), Ok(Cbor::Float(f64::INFINITY)));
+        assert_eq!(decode_hex("fa47c35000"), Ok(Cbor::Float(100000.0)));
+        assert_eq!(decode_hex("fb3ff199999999999a"), Ok(Cbor::Float(1.1)));
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        use DecodeErrorKind::*;
+
+        assert_eq!(decode_hex(""), error_at(0, UnexpectedEnd));
+        assert_eq!(decode_hex("19"), error_at(1, UnexpectedEnd));
+        assert_eq!(decode_hex("1a0001"), error_at(1, UnexpectedEnd));
+        assert_eq!(decode_hex("43aabb"), error_at(1, UnexpectedEnd));
+        assert_eq!(
+            decode_hex("820102"),
+            Ok(Cbor::Array(vec![Cbor::Uint(1), Cbor::Uint(2)]))
+        );
+        assert_eq!(decode_hex("8301"), error_at(2, UnexpectedEnd));
+        assert_eq!(decode_hex("a101"), error_at(2, UnexpectedEnd));
+        assert_eq!(decode_hex("9f01"), error_at(2, UnexpectedEnd));
+        assert_eq!(decode_hex("5f4101"), error_at(3, UnexpectedEnd));
+        assert_eq!(decode_hex("d8"), error_at(1, UnexpectedEnd));
+    }
+
+    #[test]
+    fn rejects_ill_formed_input() {
+        use DecodeErrorKind::*;
+
+        assert_eq!(decode_hex("0001"), error_at(1, TrailingBytes));
+        assert_eq!(
+            decode_hex("821c"),
+            error_at(1, ReservedAdditionalInformation(28))
+        );
+        assert_eq!(decode_hex("1f"), error_at(0, IndefiniteLengthNotAllowed(0)));
+        assert_eq!(decode_hex("3f"), error_at(0, IndefiniteLengthNotAllowed(1)));
+        assert_eq!(
+            decode_hex("81df"),
+            error_at(1, IndefiniteLengthNotAllowed(6))
+        );
+        assert_eq!(decode_hex("ff"), error_at(0, UnexpectedBreak));
+        assert_eq!(decode_hex("8201ff"), error_at(2, UnexpectedBreak));
+        assert_eq!(decode_hex("5f41016102ff"), error_at(3, InvalidChunk(3)));
+        assert_eq!(decode_hex("5f5f4101ffff"), error_at(1, InvalidChunk(2)));
+        assert_eq!(decode_hex("8162c328"), error_at(1, InvalidUtf8));
+        assert_eq!(decode_hex("f818"), error_at(0, InvalidSimpleValue(24)));
+        assert_eq!(
+            decode_hex("3bffffffffffffffff"),
+            error_at(0, NegativeIntegerOverflow)
+        );
+    }
+
+    #[test]
+    fn limits_the_nesting_depth() {
+        let nested = |depth: usize| {
+            let mut bytes = vec![0x81; depth];
+            bytes.push(0x00);
+            bytes
+        };
+
+        assert!(Cbor::deserialize(&nested(MAX_DECODE_DEPTH)).is_ok());
+        assert_eq!(
+            Cbor::deserialize(&nested(MAX_DECODE_DEPTH + 1)),
+            error_at(MAX_DECODE_DEPTH + 1, DecodeErrorKind::NestingTooDeep)
+        );
+    }
+
+    #[test]
+    fn decodes_wrapped_items() {
+        let wrapped = Cbor::Bstr(Cbor::Uint(7).serialize());
+
+        assert_eq!(wrapped.decode_wrapped(), Some(Cbor::Uint(7)));
+        assert_eq!(Cbor::Uint(7).decode_wrapped(), Some(Cbor::Uint(7)));
+        assert_eq!(Cbor::Bstr(vec![0x18]).decode_wrapped(), None);
+    }
+}
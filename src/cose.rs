@@ -0,0 +1,555 @@
+//! COSE structures used to authenticate SUIT manifests.
+//!
+//! Only the detached-payload variants are produced and checked, as the
+//! SUIT authentication wrapper carries the digest of the manifest as the
+//! COSE payload, while the COSE structure itself leaves it out.
+
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use openssl::bn::BigNum;
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{HasPublic, Id, PKey, Private, Public};
+use openssl::sign::{Signer, Verifier};
+
+use crate::cbor::Cbor;
+use crate::suit_constant::{self, SuitConstant};
+
+/// Algorithms that may be used to sign a manifest digest.
+#[derive(Debug, Clone, Copy)]
+pub enum SigningAlgorithm {
+    Es256,
+    Es384,
+    EdDsa,
+}
+
+impl From<SigningAlgorithm> for Cbor {
+    fn from(algorithm: SigningAlgorithm) -> Self {
+        match algorithm {
+            SigningAlgorithm::Es256 => SuitConstant::CoseAlgEs256.into(),
+            SigningAlgorithm::Es384 => SuitConstant::CoseAlgEs384.into(),
+            SigningAlgorithm::EdDsa => SuitConstant::CoseAlgEdDsa.into(),
+        }
+    }
+}
+
+/// Private key used to produce COSE_Sign1 authentication blocks.
+/// The signing algorithm is given by the type of key.
+#[derive(Debug, Clone)]
+pub struct SigningKey {
+    algorithm: SigningAlgorithm,
+    key: PKey<Private>,
+}
+
+impl SigningKey {
+    /// Load a private key from a PEM file. Supported keys are
+    /// P-256 and P-384 elliptic curve keys, and Ed25519 keys.
+    pub fn from_pem_file(file: &PathBuf) -> Result<Self, String> {
+        let pem = std::fs::read(file).map_err(|_| format!("could not read file `{:?}`", file))?;
+
+        let key = PKey::private_key_from_pem(&pem)
+            .map_err(|_| format!("`{:?}` does not contain a PEM private key", file))?;
+
+        let algorithm = signing_algorithm(&key, file)?;
+
+        Ok(Self { algorithm, key })
+    }
+
+    /// Create a tagged COSE_Sign1 structure over a detached `payload`.
+    pub fn sign1(&self, payload: &[u8]) -> Cbor {
+        let protected = protected_header(self.algorithm.into());
+
+        let to_be_signed = Cbor::Array(vec![
+            String::from("Signature1").into(),
+            protected.clone().into(),
+            Vec::<u8>::new().into(),
+            payload.to_vec().into(),
+        ])
+        .serialize();
+
+        let signature = self.sign(&to_be_signed);
+
+        Cbor::Tag(
+            suit_constant::COSE_SIGN1_TAG,
+            Box::new(Cbor::Array(vec![
+                protected.into(),
+                Cbor::Map(Vec::new()),
+                Cbor::Null,
+                signature.into(),
+            ])),
+        )
+    }
+
+    /// Sign `message`, returning the signature in the form COSE expects.
+    /// ECDSA signatures are the concatenation of `r` and `s`, each padded
+    /// to the size of the curve, rather than the DER encoding from OpenSSL.
+    fn sign(&self, message: &[u8]) -> Vec<u8> {
+        let (digest, coordinate_size) = match self.algorithm {
+            SigningAlgorithm::Es256 => (MessageDigest::sha256(), 32),
+            SigningAlgorithm::Es384 => (MessageDigest::sha384(), 48),
+            SigningAlgorithm::EdDsa => {
+                let mut signer = Signer::new_without_digest(&self.key).unwrap();
+                return signer.sign_oneshot_to_vec(message).unwrap();
+            }
+        };
+
+        let mut signer = Signer::new(digest, &self.key).unwrap();
+        signer.update(message).unwrap();
+        let der = signer.sign_to_vec().unwrap();
+
+        let signature = EcdsaSig::from_der(&der).unwrap();
+
+        let mut raw = signature.r().to_vec_padded(coordinate_size).unwrap();
+        raw.extend(signature.s().to_vec_padded(coordinate_size).unwrap());
+
+        raw
+    }
+}
+
+/// Public key used to check COSE_Sign1 authentication blocks.
+#[derive(Debug, Clone)]
+pub struct VerifyingKey {
+    algorithm: SigningAlgorithm,
+    key: PKey<Public>,
+}
+
+impl VerifyingKey {
+    /// Load a public key from a PEM file; either a public key, or a
+    /// private key of which only the public part is used. The same keys
+    /// as for signing are supported.
+    pub fn from_pem_file(file: &PathBuf) -> Result<Self, String> {
+        let pem = std::fs::read(file).map_err(|_| format!("could not read file `{:?}`", file))?;
+
+        let key = match PKey::public_key_from_pem(&pem) {
+            Ok(key) => key,
+            Err(_) => {
+                let private = PKey::private_key_from_pem(&pem)
+                    .map_err(|_| format!("`{:?}` does not contain a PEM key", file))?;
+
+                PKey::public_key_from_der(&private.public_key_to_der().unwrap()).unwrap()
+            }
+        };
+
+        let algorithm = signing_algorithm(&key, file)?;
+
+        Ok(Self { algorithm, key })
+    }
+
+    /// Check a tagged COSE_Sign1 structure over a detached `payload`.
+    pub fn verify1(&self, sign1: &Cbor, payload: &[u8]) -> Result<(), String> {
+        let (protected, algorithm, signature) =
+            detached_structure(sign1, suit_constant::COSE_SIGN1_TAG)?;
+
+        if algorithm != Cbor::from(self.algorithm) {
+            return Err(String::from("the signature is made with another algorithm"));
+        }
+
+        let to_be_signed = Cbor::Array(vec![
+            String::from("Signature1").into(),
+            protected.to_vec().into(),
+            Vec::<u8>::new().into(),
+            payload.to_vec().into(),
+        ])
+        .serialize();
+
+        let is_valid = match self.algorithm {
+            SigningAlgorithm::EdDsa => Verifier::new_without_digest(&self.key)
+                .and_then(|mut verifier| verifier.verify_oneshot(signature, &to_be_signed))
+                .unwrap_or(false),
+            SigningAlgorithm::Es256 | SigningAlgorithm::Es384 => {
+                let (digest, coordinate_size) = match self.algorithm {
+                    SigningAlgorithm::Es384 => (MessageDigest::sha384(), 48),
+                    _ => (MessageDigest::sha256(), 32),
+                };
+
+                if signature.len() != 2 * coordinate_size {
+                    return Err(format!(
+                        "the signature is {} bytes long rather than {}",
+                        signature.len(),
+                        2 * coordinate_size
+                    ));
+                }
+
+                // COSE signatures are the concatenation of `r` and `s`,
+                // whereas OpenSSL expects their DER encoding.
+                let (r, s) = signature.split_at(coordinate_size);
+
+                let der = BigNum::from_slice(r)
+                    .and_then(|r| Ok((r, BigNum::from_slice(s)?)))
+                    .and_then(|(r, s)| EcdsaSig::from_private_components(r, s))
+                    .and_then(|signature| signature.to_der());
+
+                match der {
+                    Ok(der) => {
+                        let mut verifier = Verifier::new(digest, &self.key).unwrap();
+                        verifier.update(&to_be_signed).unwrap();
+                        verifier.verify(&der).unwrap_or(false)
+                    }
+                    Err(_) => false,
+                }
+            }
+        };
+
+        match is_valid {
+            true => Ok(()),
+            false => Err(String::from("the signature does not match")),
+        }
+    }
+}
+
+/// Algorithms that may be used to authenticate a manifest digest
+/// with a pre-shared symmetric key.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum MacAlgorithm {
+    Hmac256,
+    Hmac384,
+    Hmac512,
+}
+
+impl From<MacAlgorithm> for Cbor {
+    fn from(algorithm: MacAlgorithm) -> Self {
+        match algorithm {
+            MacAlgorithm::Hmac256 => SuitConstant::CoseAlgHmac256.into(),
+            MacAlgorithm::Hmac384 => SuitConstant::CoseAlgHmac384.into(),
+            MacAlgorithm::Hmac512 => SuitConstant::CoseAlgHmac512.into(),
+        }
+    }
+}
+
+/// Symmetric key used to produce COSE_Mac0 authentication blocks.
+#[derive(Debug, Clone)]
+pub struct MacKey {
+    algorithm: MacAlgorithm,
+    key: Vec<u8>,
+}
+
+impl MacKey {
+    /// Load a pre-shared key from a file containing the raw key bytes.
+    pub fn from_file(file: &PathBuf, algorithm: MacAlgorithm) -> Result<Self, String> {
+        let key = std::fs::read(file).map_err(|_| format!("could not read file `{:?}`", file))?;
+
+        if key.is_empty() {
+            return Err(format!("the key file `{:?}` is empty", file));
+        }
+
+        Ok(Self { algorithm, key })
+    }
+
+    /// Use the key with `algorithm` instead of the one it was loaded with.
+    pub fn with_algorithm(self, algorithm: MacAlgorithm) -> Self {
+        Self { algorithm, ..self }
+    }
+
+    /// Create a tagged COSE_Mac0 structure over a detached `payload`.
+    pub fn mac0(&self, payload: &[u8]) -> Cbor {
+        let protected = protected_header(self.algorithm.into());
+
+        let to_be_maced = Cbor::Array(vec![
+            String::from("MAC0").into(),
+            protected.clone().into(),
+            Vec::<u8>::new().into(),
+            payload.to_vec().into(),
+        ])
+        .serialize();
+
+        let tag = self.tag(&to_be_maced);
+
+        Cbor::Tag(
+            suit_constant::COSE_MAC0_TAG,
+            Box::new(Cbor::Array(vec![
+                protected.into(),
+                Cbor::Map(Vec::new()),
+                Cbor::Null,
+                tag.into(),
+            ])),
+        )
+    }
+
+    /// Check a tagged COSE_Mac0 structure over a detached `payload`.
+    pub fn verify_mac0(&self, mac0: &Cbor, payload: &[u8]) -> Result<(), String> {
+        let (protected, algorithm, tag) = detached_structure(mac0, suit_constant::COSE_MAC0_TAG)?;
+
+        if algorithm != Cbor::from(self.algorithm) {
+            return Err(String::from("the MAC is made with another algorithm"));
+        }
+
+        let to_be_maced = Cbor::Array(vec![
+            String::from("MAC0").into(),
+            protected.to_vec().into(),
+            Vec::<u8>::new().into(),
+            payload.to_vec().into(),
+        ])
+        .serialize();
+
+        let expected = self.tag(&to_be_maced);
+
+        match expected.len() == tag.len() && openssl::memcmp::eq(&expected, tag) {
+            true => Ok(()),
+            false => Err(String::from("the MAC does not match")),
+        }
+    }
+
+    fn tag(&self, message: &[u8]) -> Vec<u8> {
+        let digest = match self.algorithm {
+            MacAlgorithm::Hmac256 => MessageDigest::sha256(),
+            MacAlgorithm::Hmac384 => MessageDigest::sha384(),
+            MacAlgorithm::Hmac512 => MessageDigest::sha512(),
+        };
+
+        let key = PKey::hmac(&self.key).unwrap();
+
+        let mut signer = Signer::new(digest, &key).unwrap();
+        signer.update(message).unwrap();
+        signer.sign_to_vec().unwrap()
+    }
+}
+
+/// Determine the signing algorithm from the type of `key`, read from `file`.
+fn signing_algorithm<T: HasPublic>(
+    key: &PKey<T>,
+    file: &PathBuf,
+) -> Result<SigningAlgorithm, String> {
+    match key.id() {
+        Id::EC => {
+            let curve = key.ec_key().unwrap().group().curve_name();
+
+            match curve {
+                Some(Nid::X9_62_PRIME256V1) => Ok(SigningAlgorithm::Es256),
+                Some(Nid::SECP384R1) => Ok(SigningAlgorithm::Es384),
+                _ => Err(format!("unsupported elliptic curve in `{:?}`", file)),
+            }
+        }
+        Id::ED25519 => Ok(SigningAlgorithm::EdDsa),
+        _ => Err(format!("unsupported key type in `{:?}`", file)),
+    }
+}
+
+/// Take apart a tagged COSE structure with a detached payload, such as
+/// COSE_Sign1 or COSE_Mac0; returning its protected header bucket, the
+/// algorithm given in it, and the signature or tag.
+fn detached_structure(item: &Cbor, tag: u64) -> Result<(&[u8], Cbor, &[u8]), String> {
+    let malformed = || String::from("malformed COSE structure");
+
+    let elements = match item {
+        Cbor::Tag(number, content) if *number == tag => match content.as_ref() {
+            Cbor::Array(elements) => elements,
+            _ => return Err(malformed()),
+        },
+        Cbor::Tag(number, _) => return Err(format!("unexpected COSE tag `{}`", number)),
+        _ => return Err(malformed()),
+    };
+
+    let (protected, signature) = match elements.as_slice() {
+        [Cbor::Bstr(protected), Cbor::Map(_), Cbor::Null, Cbor::Bstr(signature)] => {
+            (protected, signature)
+        }
+        _ => return Err(malformed()),
+    };
+
+    let algorithm = match Cbor::deserialize(protected) {
+        Ok(Cbor::Map(entries)) => entries
+            .into_iter()
+            .find(|(key, _value)| *key == Cbor::from(SuitConstant::CoseHeaderAlgorithm))
+            .map(|(_key, value)| value),
+        _ => None,
+    };
+
+    match algorithm {
+        Some(algorithm) => Ok((protected, algorithm, signature)),
+        None => Err(String::from("the protected header gives no algorithm")),
+    }
+}
+
+/// Encode a protected header bucket containing only the algorithm.
+fn protected_header(algorithm: Cbor) -> Vec<u8> {
+    Cbor::Map(vec![(SuitConstant::CoseHeaderAlgorithm.into(), algorithm)]).serialize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use openssl::ec::{EcGroup, EcKey};
+
+    fn key_pair(curve: Nid) -> (SigningKey, VerifyingKey) {
+        let key =
+            PKey::from_ec_key(EcKey::generate(&EcGroup::from_curve_name(curve).unwrap()).unwrap())
+                .unwrap();
+        let public = PKey::public_key_from_der(&key.public_key_to_der().unwrap()).unwrap();
+
+        let algorithm = signing_algorithm(&key, &PathBuf::new()).unwrap();
+
+        (
+            SigningKey { algorithm, key },
+            VerifyingKey {
+                algorithm,
+                key: public,
+            },
+        )
+    }
+
+    fn ed25519_key_pair() -> (SigningKey, VerifyingKey) {
+        let key = PKey::generate_ed25519().unwrap();
+        let public = PKey::public_key_from_der(&key.public_key_to_der().unwrap()).unwrap();
+
+        (
+            SigningKey {
+                algorithm: SigningAlgorithm::EdDsa,
+                key,
+            },
+            VerifyingKey {
+                algorithm: SigningAlgorithm::EdDsa,
+                key: public,
+            },
+        )
+    }
+
+    /// Replace the signature or tag of a detached COSE structure.
+    fn with_signature(item: &Cbor, signature: Vec<u8>) -> Cbor {
+        match item {
+            Cbor::Tag(tag, content) => match content.as_ref() {
+                Cbor::Array(elements) => {
+                    let mut elements = elements.clone();
+                    elements[3] = signature.into();
+                    Cbor::Tag(*tag, Box::new(Cbor::Array(elements)))
+                }
+                _ => panic!("malformed COSE structure"),
+            },
+            _ => panic!("malformed COSE structure"),
+        }
+    }
+
+    fn signature(item: &Cbor) -> Vec<u8> {
+        match item {
+            Cbor::Tag(_, content) => match content.as_ref() {
+                Cbor::Array(elements) => match &elements[3] {
+                    Cbor::Bstr(signature) => signature.clone(),
+                    _ => panic!("malformed COSE structure"),
+                },
+                _ => panic!("malformed COSE structure"),
+            },
+            _ => panic!("malformed COSE structure"),
+        }
+    }
+
+    #[test]
+    fn verifies_signatures() {
+        for (signing, verifying) in [
+            key_pair(Nid::X9_62_PRIME256V1),
+            key_pair(Nid::SECP384R1),
+            ed25519_key_pair(),
+        ] {
+            let sign1 = signing.sign1(b"manifest digest");
+
+            assert_eq!(verifying.verify1(&sign1, b"manifest digest"), Ok(()));
+            assert_eq!(
+                verifying.verify1(&sign1, b"manifest digesT"),
+                Err(String::from("the signature does not match"))
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_signatures_of_other_keys() {
+        let (signing, _) = key_pair(Nid::X9_62_PRIME256V1);
+        let (_, other) = key_pair(Nid::X9_62_PRIME256V1);
+        let (_, other_curve) = key_pair(Nid::SECP384R1);
+
+        let sign1 = signing.sign1(b"manifest digest");
+
+        assert_eq!(
+            other.verify1(&sign1, b"manifest digest"),
+            Err(String::from("the signature does not match"))
+        );
+        assert_eq!(
+            other_curve.verify1(&sign1, b"manifest digest"),
+            Err(String::from("the signature is made with another algorithm"))
+        );
+    }
+
+    #[test]
+    fn rejects_signatures_of_the_wrong_length() {
+        let (signing, verifying) = key_pair(Nid::X9_62_PRIME256V1);
+
+        let sign1 = signing.sign1(b"manifest digest");
+
+        // Split in half, a zero byte in front of `s` leaves both `r` and
+        // `s` unchanged; the signature must still be rejected.
+        let mut padded = signature(&sign1);
+        padded.insert(32, 0);
+
+        assert_eq!(
+            verifying.verify1(&with_signature(&sign1, padded), b"manifest digest"),
+            Err(String::from(
+                "the signature is 65 bytes long rather than 64"
+            ))
+        );
+
+        let mut truncated = signature(&sign1);
+        truncated.pop();
+
+        assert_eq!(
+            verifying.verify1(&with_signature(&sign1, truncated), b"manifest digest"),
+            Err(String::from(
+                "the signature is 63 bytes long rather than 64"
+            ))
+        );
+    }
+
+    #[test]
+    fn verifies_macs() {
+        for algorithm in [
+            MacAlgorithm::Hmac256,
+            MacAlgorithm::Hmac384,
+            MacAlgorithm::Hmac512,
+        ] {
+            let key = MacKey {
+                algorithm,
+                key: vec![0x5a; 32],
+            };
+
+            let mac0 = key.mac0(b"manifest digest");
+
+            assert_eq!(key.verify_mac0(&mac0, b"manifest digest"), Ok(()));
+            assert_eq!(
+                key.verify_mac0(&mac0, b"manifest digesT"),
+                Err(String::from("the MAC does not match"))
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_macs_of_other_keys() {
+        let key = MacKey {
+            algorithm: MacAlgorithm::Hmac256,
+            key: vec![0x5a; 32],
+        };
+        let other = MacKey {
+            algorithm: MacAlgorithm::Hmac256,
+            key: vec![0xa5; 32],
+        };
+
+        let mac0 = key.mac0(b"manifest digest");
+
+        assert_eq!(
+            other.verify_mac0(&mac0, b"manifest digest"),
+            Err(String::from("the MAC does not match"))
+        );
+        assert_eq!(
+            key.clone()
+                .with_algorithm(MacAlgorithm::Hmac384)
+                .verify_mac0(&mac0, b"manifest digest"),
+            Err(String::from("the MAC is made with another algorithm"))
+        );
+
+        let mut truncated = signature(&mac0);
+        truncated.pop();
+
+        assert_eq!(
+            key.verify_mac0(&with_signature(&mac0, truncated), b"manifest digest"),
+            Err(String::from("the MAC does not match"))
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! Dependencies of a manifest on other manifests, such as those of other
+//! processors that are updated by separate authorities.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::cbor::{Cbor, DecodeError};
+use crate::digest::{Digest, DigestAlgorithm};
+use crate::suit_constant::{self, SuitConstant};
+
+/// A manifest that another manifest depends on, given by its complete
+/// envelope; which is integrated in the envelope of the dependent
+/// manifest, so that the signatures of its own authority are kept.
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    /// URI the integrated envelope is referenced by. The custom URI
+    /// scheme "d:" is used to denote integrated dependencies.
+    pub uri: String,
+
+    /// Digest of the manifest of the dependency.
+    pub digest: Digest,
+
+    /// The encoded envelope of the dependency.
+    pub envelope: Vec<u8>,
+}
+
+/// Error produced when the envelope of a dependency cannot be used.
+#[derive(Debug)]
+pub enum DependencyError {
+    /// The envelope file could not be read.
+    Unreadable(PathBuf),
+
+    /// The envelope is not valid CBOR.
+    Decode { file: PathBuf, error: DecodeError },
+
+    /// The envelope does not contain a manifest.
+    NoManifest(PathBuf),
+}
+
+impl fmt::Display for DependencyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DependencyError::Unreadable(file) => write!(f, "could not read file `{:?}`", file),
+            DependencyError::Decode { file, error } => write!(f, "{:?}: {}", file, error),
+            DependencyError::NoManifest(file) => {
+                write!(f, "{:?}: the envelope does not contain a manifest", file)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DependencyError {}
+
+impl Dependency {
+    /// Read the envelope of the dependency from `file`, to be integrated
+    /// as the dependency numbered `index`. The manifest digest is made
+    /// with `digest_algorithm`.
+    pub fn from_envelope_file(
+        file: &Path,
+        index: usize,
+        digest_algorithm: DigestAlgorithm,
+    ) -> Result<Self, DependencyError> {
+        let envelope =
+            std::fs::read(file).map_err(|_| DependencyError::Unreadable(file.to_path_buf()))?;
+
+        let decoded = Cbor::deserialize(&envelope).map_err(|error| DependencyError::Decode {
+            file: file.to_path_buf(),
+            error,
+        })?;
+
+        let entries = match decoded {
+            Cbor::Tag(suit_constant::SUIT_ENVELOPE_TAG, content) => match *content {
+                Cbor::Map(entries) => entries,
+                _ => return Err(DependencyError::NoManifest(file.to_path_buf())),
+            },
+            Cbor::Map(entries) => entries,
+            _ => return Err(DependencyError::NoManifest(file.to_path_buf())),
+        };
+
+        // The digest is computed over the same bytes as the digest in the
+        // authentication wrapper of the dependency.
+        let manifest = entries
+            .iter()
+            .find_map(|(key, value)| match value {
+                Cbor::Bstr(manifest) if *key == Cbor::from(SuitConstant::Manifest) => {
+                    Some(manifest)
+                }
+                _ => None,
+            })
+            .ok_or_else(|| DependencyError::NoManifest(file.to_path_buf()))?;
+
+        Ok(Self {
+            uri: format!("d:{}", index),
+            digest: digest_algorithm.apply(manifest),
+            envelope,
+        })
+    }
+}
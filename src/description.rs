@@ -0,0 +1,1299 @@
+//! Manifest descriptions; TOML files describing the components of a
+//! manifest, the files their payloads are read from, and the commands
+//! that are run on them.
+//!
+//! ```toml
+//! sequence-number = 3
+//! digest-algorithm = "sha256"
+//! vendor-domain = "example.com"
+//! model-name = "sensor-node"
+//!
+//! [text]
+//! manifest-description = "Sensor node firmware"
+//!
+//! [[component]]
+//! files = ["application.hex", { path = "assets.bin", address = 0x00080000 }]
+//! vendor-name = "Example Inc."
+//! description = "Application"
+//! version = "1.4.0"
+//! install = ["directive-fetch"]
+//! validate = ["condition-image-match"]
+//! run = [{ command = "directive-run", report = ["record-failure"] }]
+//! ```
+//!
+//! Nested sequences are given as `sequences` for the alternatives of
+//! `directive-try-each`, and as `sequence` for `directive-run-sequence`.
+//!
+//! Sequences that are not given for a component default to those of the
+//! `generate` command; the image of a component with files is fetched in
+//! `install` and matched in `validate`, and the first component is run.
+//! Parameters that commands depend on, such as the image digest for
+//! `condition-image-match`, are derived from the payload of the component.
+//!
+//! Payloads are detached from the envelope with `payload-uri`, a URI
+//! template where `{index}`, in its last segment, is replaced by the
+//! number of the payload.
+//! Components with files then default to being fetched and matched in
+//! `payload-fetch`, rather than being fetched in `install`.
+//!
+//! A component is installed into one of several slots, such as the A/B
+//! slots of a bootloader, with `slots`; either the number of slots that
+//! share the payload of the component, or a table per slot with its own
+//! `files`. The payload is fetched into the first slot that the device
+//! accepts, before the rest of the `install` sequence, and is matched in
+//! that slot before the rest of the `validate` sequence.
+//!
+//! A component is instead installed from a staging component with
+//! `staging`, the index of the component that its payload is fetched into
+//! first, and `install-method`; either `copy` (the default) or `swap`.
+//!
+//! The vendor and class identifiers are given either as UUIDs with
+//! `vendor-id` and `class-id`, or derived from `vendor-domain` and
+//! `model-name`. Manifests are restricted to single devices by listing
+//! their UUIDs in `device-ids`, in which case one manifest is made per
+//! device.
+//!
+//! Envelopes of manifests that the manifest depends on, such as those of
+//! other processors signed by their own authorities, are listed in
+//! `dependencies`. They are integrated in the envelope, and processed in
+//! each command sequence before the components of the manifest.
+//!
+//! The members listed in `sever`, of `payload-fetch`, `install` and
+//! `text`, are severed from the manifest and placed in the envelope; only
+//! the text if not given.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use toml::value::{Table, Value};
+
+use crate::digest::DigestAlgorithm;
+use crate::encryption::Encryptor;
+use crate::manifest::{
+    Command, ComponentIdentifier, InstallMethod, ManifestBuilder, Parameter, ReportingPolicy,
+    SequenceBuilder, SeverableMember, Text, TextComponent,
+};
+use crate::payload::{self, FileFormat, InputFile, Payload, PayloadBuilder, PayloadError};
+use crate::suit_constant::{KeySpace, SuitConstant};
+use crate::uuid::Uuid;
+
+/// Description of a manifest, along with the payloads it refers to.
+#[derive(Debug)]
+pub struct Description {
+    pub sequence_number: u64,
+    pub reference_uri: Option<String>,
+    pub digest_algorithm: DigestAlgorithm,
+
+    pub vendor_identifier: Option<Uuid>,
+    pub class_identifier: Option<Uuid>,
+
+    /// Members to sever from the manifest; the default if not given.
+    pub severed: Option<Vec<SeverableMember>>,
+
+    /// Devices to make a manifest for each; if empty, a single manifest
+    /// is made for all devices.
+    pub device_identifiers: Vec<Uuid>,
+
+    /// Envelopes of the manifests that the manifest depends on, which are
+    /// integrated in its envelope.
+    pub dependencies: Vec<PathBuf>,
+
+    /// The value that an unwritten byte has in memory.
+    pub fill_value: u8,
+    pub compress: bool,
+
+    /// Allow that files overwrite each other's addresses, also when they
+    /// belong to different components.
+    pub allow_overwrites: bool,
+
+    /// URI template that devices fetch the payloads from, instead of them
+    /// being integrated in the envelope.
+    pub payload_uri: Option<String>,
+
+    pub manifest_description: Option<String>,
+    pub update_description: Option<String>,
+
+    pub components: Vec<ComponentDescription>,
+}
+
+/// Description of a single component, and the commands run on it in
+/// each of the command sequences.
+#[derive(Debug)]
+pub struct ComponentDescription {
+    /// Address identifying the component; the start address of its
+    /// payload if not given.
+    pub address: Option<u32>,
+
+    /// Firmware files that together form the payload of the component.
+    pub files: Vec<InputFile>,
+
+    /// Firmware files for each of the slots of the component, of which
+    /// the component is installed into the first that the device accepts.
+    /// Slots without files take the payload of the component itself.
+    pub slots: Vec<Vec<InputFile>>,
+
+    /// Index of the component that the payload is fetched into, before it
+    /// is installed into this component with `install_method`.
+    pub staging: Option<usize>,
+    pub install_method: InstallMethod,
+
+    /// Text describing the component.
+    pub text: TextComponent,
+
+    pub payload_fetch: Vec<CommandDescription>,
+    pub install: Vec<CommandDescription>,
+    pub validate: Vec<CommandDescription>,
+    pub load: Vec<CommandDescription>,
+    pub run: Vec<CommandDescription>,
+}
+
+/// A command, whose parameters are derived from the component it is run on.
+#[derive(Debug, Clone)]
+pub struct CommandDescription {
+    pub command: SuitConstant,
+    pub reporting_policy: ReportingPolicy,
+
+    /// Nested sequences; the alternatives of `directive-try-each`, or the
+    /// single sequence of `directive-run-sequence`.
+    pub sequences: Vec<Vec<CommandDescription>>,
+}
+
+/// Error produced when a manifest description cannot be read or built.
+#[derive(Debug)]
+pub enum DescriptionError {
+    /// The description file could not be read.
+    Unreadable(PathBuf),
+
+    /// The description is not valid TOML.
+    Syntax(toml::de::Error),
+
+    /// A key in the description has a missing or invalid value.
+    Invalid { key: String, reason: String },
+
+    /// The payload of a component could not be built.
+    Payload(PayloadError),
+}
+
+impl fmt::Display for DescriptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DescriptionError::Unreadable(file) => write!(f, "could not read file `{:?}`", file),
+            DescriptionError::Syntax(error) => write!(f, "{}", error),
+            DescriptionError::Invalid { key, reason } => write!(f, "`{}`: {}", key, reason),
+            DescriptionError::Payload(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for DescriptionError {}
+
+impl From<PayloadError> for DescriptionError {
+    fn from(error: PayloadError) -> Self {
+        DescriptionError::Payload(error)
+    }
+}
+
+impl Description {
+    /// Read a description from a TOML file. Relative paths of firmware
+    /// files are relative to the directory of the description.
+    pub fn from_file(file: &Path) -> Result<Self, DescriptionError> {
+        let content = std::fs::read_to_string(file)
+            .map_err(|_| DescriptionError::Unreadable(file.to_path_buf()))?;
+
+        let base_directory = file.parent().unwrap_or_else(|| Path::new(""));
+
+        Self::from_toml(&content, base_directory)
+    }
+
+    /// Read a description from TOML `content`, where relative paths of
+    /// firmware files are relative to `base_directory`.
+    pub fn from_toml(content: &str, base_directory: &Path) -> Result<Self, DescriptionError> {
+        let root: Value = content.parse().map_err(DescriptionError::Syntax)?;
+        let root = Entry::root(&root)?;
+
+        root.check_keys(&[
+            "sequence-number",
+            "reference-uri",
+            "digest-algorithm",
+            "vendor-domain",
+            "vendor-id",
+            "model-name",
+            "class-id",
+            "device-ids",
+            "dependencies",
+            "sever",
+            "fill",
+            "compress",
+            "allow-overwrites",
+            "payload-uri",
+            "text",
+            "component",
+        ])?;
+
+        let digest_algorithm = match root.string("digest-algorithm")? {
+            Some(name) => DigestAlgorithm::from_str(name, true)
+                .map_err(|_| root.invalid("digest-algorithm", "unknown digest algorithm"))?,
+            None => DigestAlgorithm::Sha256,
+        };
+
+        let vendor_identifier = match (root.string("vendor-domain")?, root.uuid("vendor-id")?) {
+            (Some(_), Some(_)) => {
+                return Err(root.invalid("vendor-id", "cannot be given along with `vendor-domain`"))
+            }
+            (Some(domain), None) => Some(Uuid::vendor_identifier(domain)),
+            (None, uuid) => uuid,
+        };
+
+        let class_identifier = match (root.string("model-name")?, root.uuid("class-id")?) {
+            (Some(_), Some(_)) => {
+                return Err(root.invalid("class-id", "cannot be given along with `model-name`"))
+            }
+            (Some(model_name), None) => match &vendor_identifier {
+                Some(vendor) => Some(Uuid::class_identifier(vendor, model_name)),
+                None => {
+                    return Err(
+                        root.invalid("model-name", "needs either `vendor-domain` or `vendor-id`")
+                    )
+                }
+            },
+            (None, uuid) => uuid,
+        };
+
+        let severed = match root.get("sever") {
+            Some(_) => Some(
+                root.array("sever")?
+                    .iter()
+                    .map(|entry| match entry.value.as_str() {
+                        Some(name) => SeverableMember::from_str(name, true)
+                            .map_err(|_| invalid(entry.path.clone(), "not a severable member")),
+                        None => Err(entry.mismatch("a member name")),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            None => None,
+        };
+
+        let device_identifiers = root
+            .array("device-ids")?
+            .iter()
+            .map(Entry::as_uuid)
+            .collect::<Result<_, _>>()?;
+
+        let dependencies = root
+            .array("dependencies")?
+            .iter()
+            .map(|entry| match entry.value.as_str() {
+                Some(path) => Ok(base_directory.join(path)),
+                None => Err(entry.mismatch("an envelope file path")),
+            })
+            .collect::<Result<_, _>>()?;
+
+        let (manifest_description, update_description) = match root.table("text")? {
+            Some(text) => {
+                text.check_keys(&["manifest-description", "update-description"])?;
+
+                (
+                    text.string("manifest-description")?.map(String::from),
+                    text.string("update-description")?.map(String::from),
+                )
+            }
+            None => (None, None),
+        };
+
+        let payload_uri = root.string("payload-uri")?.map(String::from);
+
+        let components = root
+            .tables("component")?
+            .iter()
+            .enumerate()
+            .map(|(index, component)| {
+                ComponentDescription::from_toml(
+                    component,
+                    index == 0,
+                    payload_uri.is_some(),
+                    base_directory,
+                )
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            sequence_number: root.integer("sequence-number")?.unwrap_or(0),
+            reference_uri: root.string("reference-uri")?.map(String::from),
+            digest_algorithm,
+
+            vendor_identifier,
+            class_identifier,
+            severed,
+            device_identifiers,
+            dependencies,
+
+            fill_value: root.integer("fill")?.unwrap_or(0xff),
+            compress: root.boolean("compress")?.unwrap_or(true),
+            allow_overwrites: root.boolean("allow-overwrites")?.unwrap_or(false),
+            payload_uri,
+
+            manifest_description,
+            update_description,
+
+            components,
+        })
+    }
+
+    /// Read the payloads of all components, and prepare the manifest
+    /// describing them. The device identifier is left to be set for each
+    /// of the `device_identifiers`. Payloads are encrypted with `encryptor`
+    /// if it is given.
+    pub fn build(
+        &self,
+        encryptor: Option<&Encryptor>,
+    ) -> Result<(ManifestBuilder, Vec<Payload>), DescriptionError> {
+        let mut payloads = Vec::new();
+        let mut component_payloads = Vec::new();
+        let mut slot_payloads = Vec::new();
+
+        let mut read_payload = |files: &[InputFile]| -> Result<Option<usize>, DescriptionError> {
+            if files.is_empty() {
+                return Ok(None);
+            }
+
+            let payload = PayloadBuilder::new()
+                .files(files)
+                .fill_value(self.fill_value)
+                .allow_overwrites(self.allow_overwrites)
+                .compress(self.compress)
+                .split(false)
+                .first_index(payloads.len());
+
+            let payload = match &self.payload_uri {
+                Some(template) => payload.remote_uri(template.clone()),
+                None => payload,
+            };
+
+            let payload = match encryptor {
+                Some(encryptor) => payload.encrypt(encryptor.clone()),
+                None => payload,
+            };
+
+            let (mut payload, _entry_point) = payload.build()?;
+
+            let index = payloads.len();
+            payloads.append(&mut payload);
+
+            Ok(Some(index))
+        };
+
+        for (index, component) in self.components.iter().enumerate() {
+            let payload = read_payload(&component.files)?;
+
+            let slots = component
+                .slots
+                .iter()
+                .map(|files| match read_payload(files)?.or(payload) {
+                    Some(payload) => Ok(payload),
+                    None => Err(invalid(
+                        format!("component[{}].slots", index),
+                        "slots without files need a component with files",
+                    )),
+                })
+                .collect::<Result<Vec<usize>, _>>()?;
+
+            component_payloads.push(payload.or_else(|| slots.first().copied()));
+            slot_payloads.push(slots);
+        }
+
+        if !self.allow_overwrites {
+            let groups: Vec<Vec<&Payload>> = component_payloads
+                .iter()
+                .zip(&slot_payloads)
+                .map(|(payload, slots)| {
+                    let mut indices: Vec<usize> = payload.iter().chain(slots).copied().collect();
+                    indices.sort_unstable();
+                    indices.dedup();
+
+                    indices.iter().map(|index| &payloads[*index]).collect()
+                })
+                .collect();
+
+            if let Some((address, first, second)) = payload::find_overlap(&groups) {
+                return Err(invalid(
+                    format!("component[{}]", second),
+                    format!(
+                        "the payload overlaps that of `component[{}]` at address `{:#x}`",
+                        first, address
+                    ),
+                ));
+            }
+        }
+
+        let identifiers: Vec<ComponentIdentifier> = self
+            .components
+            .iter()
+            .zip(&component_payloads)
+            .enumerate()
+            .map(
+                |(index, (component, payload))| match (component.address, payload) {
+                    (Some(address), _) => Ok(ComponentIdentifier(address)),
+                    (None, Some(payload)) => {
+                        Ok(ComponentIdentifier(payloads[*payload].start_address))
+                    }
+                    (None, None) => Err(invalid(
+                        format!("component[{}]", index),
+                        "a component without files needs an address",
+                    )),
+                },
+            )
+            .collect::<Result<_, _>>()?;
+
+        // Components with slots are installed into one of them, and staged
+        // components are installed from their staging component, before any
+        // other commands in their install sequence. Either leaves the
+        // component itself as the current component. Components with slots
+        // are likewise matched against the payload of their slot before any
+        // other commands in their validate sequence.
+        let mut installs = Vec::new();
+        let mut validates = Vec::new();
+
+        for (index, component) in self.components.iter().enumerate() {
+            let install = SequenceBuilder::new();
+            let validate = SequenceBuilder::new();
+
+            let (install, validate) = match (&slot_payloads[index][..], component.staging) {
+                ([], None) => (install, validate),
+                (slots, None) => {
+                    let slots: Vec<&Payload> = slots.iter().map(|slot| &payloads[*slot]).collect();
+
+                    (
+                        install
+                            .set_component_index(index)
+                            .install_to_slot(&slots, self.digest_algorithm),
+                        validate
+                            .set_component_index(index)
+                            .match_slot(&slots, self.digest_algorithm),
+                    )
+                }
+                (_, Some(staging)) => {
+                    let key = format!("component[{}].staging", index);
+
+                    if staging >= self.components.len() || staging == index {
+                        return Err(invalid(key, "not the index of another component"));
+                    }
+
+                    let payload = component_payloads[index]
+                        .ok_or_else(|| invalid(key, "a staged component needs files"))?;
+
+                    let install = install.install_staged(
+                        index,
+                        staging,
+                        &payloads[payload],
+                        component.install_method,
+                        self.digest_algorithm,
+                    );
+
+                    (install, validate)
+                }
+            };
+
+            installs.push(install.build());
+            validates.push(validate.build());
+        }
+
+        let no_preludes = vec![Vec::new(); self.components.len()];
+
+        let sequence = |name: &str,
+                        commands: fn(&ComponentDescription) -> &[CommandDescription],
+                        preludes: &[Vec<Command>]| {
+            let mut sequence = SequenceBuilder::new();
+            let mut is_empty = true;
+
+            for (index, component) in self.components.iter().enumerate() {
+                if commands(component).is_empty() && preludes[index].is_empty() {
+                    continue;
+                }
+
+                let payload = component_payloads[index].map(|payload| &payloads[payload]);
+
+                is_empty = false;
+
+                match preludes[index].is_empty() {
+                    true => sequence = sequence.set_component_index(index),
+                    false => {
+                        for command in preludes[index].iter().cloned() {
+                            sequence = sequence.command(command);
+                        }
+                    }
+                }
+
+                for (command_index, command) in commands(component).iter().enumerate() {
+                    for command in self.expand(command, payload).map_err(|reason| {
+                        invalid(
+                            format!("component[{}].{}[{}]", index, name, command_index),
+                            reason,
+                        )
+                    })? {
+                        sequence = sequence.command(command);
+                    }
+                }
+            }
+
+            Ok::<_, DescriptionError>(match is_empty {
+                true => None,
+                false => Some(sequence.build()),
+            })
+        };
+
+        let mut manifest =
+            ManifestBuilder::new(identifiers.clone()).sequence_number(self.sequence_number);
+
+        if let Some(uri) = &self.reference_uri {
+            manifest = manifest.reference_uri(uri.clone());
+        }
+
+        if let Some(members) = &self.severed {
+            manifest = manifest.sever(members.clone());
+        }
+
+        if let Some(uuid) = self.vendor_identifier {
+            manifest = manifest.vendor_identifier(uuid);
+        }
+
+        if let Some(uuid) = self.class_identifier {
+            manifest = manifest.class_identifier(uuid);
+        }
+
+        let sequences = [
+            sequence(
+                "payload-fetch",
+                |component| &component.payload_fetch,
+                &no_preludes,
+            )?,
+            sequence("install", |component| &component.install, &installs)?,
+            sequence("validate", |component| &component.validate, &validates)?,
+            sequence("load", |component| &component.load, &no_preludes)?,
+            sequence("run", |component| &component.run, &no_preludes)?,
+        ];
+
+        let [payload_fetch, install, validate, load, run] = sequences;
+
+        if let Some(sequence) = payload_fetch {
+            manifest = manifest.payload_fetch(sequence);
+        }
+        if let Some(sequence) = install {
+            manifest = manifest.install(sequence);
+        }
+        if let Some(sequence) = validate {
+            manifest = manifest.validate(sequence);
+        }
+        if let Some(sequence) = load {
+            manifest = manifest.load(sequence);
+        }
+        if let Some(sequence) = run {
+            manifest = manifest.run(sequence);
+        }
+
+        let text_components: Vec<(ComponentIdentifier, TextComponent)> = self
+            .components
+            .iter()
+            .zip(identifiers)
+            .filter(|(component, _identifier)| !component.text.is_empty())
+            .map(|(component, identifier)| (identifier, component.text.clone()))
+            .collect();
+
+        if self.manifest_description.is_some()
+            || self.update_description.is_some()
+            || !text_components.is_empty()
+        {
+            manifest = manifest.text(Text {
+                manifest_description: self.manifest_description.clone(),
+                update_description: self.update_description.clone(),
+                components: text_components,
+            });
+        }
+
+        Ok((manifest, payloads))
+    }
+
+    /// Expand a described command into the commands that set its
+    /// parameters from the `payload` of its component, followed by
+    /// the command itself.
+    fn expand(
+        &self,
+        command: &CommandDescription,
+        payload: Option<&Payload>,
+    ) -> Result<Vec<Command>, String> {
+        let policy = command.reporting_policy;
+
+        let require_payload = || {
+            payload
+                .ok_or_else(|| format!("`{}` needs a component with files", command.command.name()))
+        };
+
+        Ok(match command.command {
+            SuitConstant::ConditionImageMatch => {
+                let payload = require_payload()?;
+
+                vec![
+                    Command::DirectiveOverrideParameters(vec![
+                        Parameter::ImageDigest(self.digest_algorithm.apply(&payload.bytes)),
+                        Parameter::ImageSize(payload.size),
+                    ]),
+                    Command::ConditionImageMatch(policy),
+                ]
+            }
+            SuitConstant::ConditionAbort => vec![Command::ConditionAbort(policy)],
+            SuitConstant::DirectiveFetch => vec![
+                Command::DirectiveOverrideParameters(Parameter::for_fetching(require_payload()?)),
+                Command::DirectiveFetch(policy),
+            ],
+            SuitConstant::DirectiveRun => vec![Command::DirectiveRun(policy)],
+            SuitConstant::DirectiveTryEach => vec![Command::DirectiveTryEach(
+                command
+                    .sequences
+                    .iter()
+                    .map(|sequence| self.expand_sequence(sequence, payload))
+                    .collect::<Result<_, _>>()?,
+            )],
+            SuitConstant::DirectiveRunSequence => match command.sequences.as_slice() {
+                [sequence] => vec![Command::DirectiveRunSequence(
+                    self.expand_sequence(sequence, payload)?,
+                )],
+                _ => {
+                    return Err(String::from(
+                        "`directive-run-sequence` needs exactly one sequence",
+                    ))
+                }
+            },
+            constant => {
+                return Err(format!(
+                    "`{}` cannot be used in a manifest description",
+                    constant.name()
+                ))
+            }
+        })
+    }
+
+    /// Expand each of the described commands in a nested `sequence`.
+    fn expand_sequence(
+        &self,
+        sequence: &[CommandDescription],
+        payload: Option<&Payload>,
+    ) -> Result<Vec<Command>, String> {
+        let mut commands = Vec::new();
+
+        for command in sequence {
+            commands.append(&mut self.expand(command, payload)?);
+        }
+
+        Ok(commands)
+    }
+}
+
+impl ComponentDescription {
+    fn from_toml(
+        component: &Entry,
+        is_first: bool,
+        is_detached: bool,
+        base_directory: &Path,
+    ) -> Result<Self, DescriptionError> {
+        component.check_keys(&[
+            "address",
+            "files",
+            "slots",
+            "staging",
+            "install-method",
+            "vendor-name",
+            "model-name",
+            "vendor-domain",
+            "model-info",
+            "description",
+            "version",
+            "payload-fetch",
+            "install",
+            "validate",
+            "load",
+            "run",
+        ])?;
+
+        let files = component
+            .array("files")?
+            .iter()
+            .map(|file| input_file(file, base_directory))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let slots = match component.get("slots") {
+            None => Vec::new(),
+            Some(entry) if entry.value.is_integer() => {
+                let count: usize = component.integer("slots")?.unwrap();
+                vec![Vec::new(); count]
+            }
+            Some(entry) => entry
+                .elements()?
+                .iter()
+                .map(|slot| {
+                    slot.check_keys(&["files"])?;
+                    slot.array("files")?
+                        .iter()
+                        .map(|file| input_file(file, base_directory))
+                        .collect()
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+        };
+
+        if component.get("slots").is_some() && slots.len() < 2 {
+            return Err(component.invalid("slots", "at least 2 slots are needed"));
+        }
+
+        let staging = component.integer("staging")?;
+
+        if staging.is_some() && !slots.is_empty() {
+            return Err(component.invalid("staging", "slotted components cannot be staged"));
+        }
+
+        let install_method = match component.string("install-method")? {
+            Some(name) => InstallMethod::from_str(name, true)
+                .map_err(|_| component.invalid("install-method", "unknown install method"))?,
+            None => InstallMethod::Copy,
+        };
+
+        // Slotted components are fetched as part of being installed into a
+        // slot, and staged components are installed from their staging
+        // component.
+        let has_files = !files.is_empty() && slots.is_empty();
+        let is_fetched = has_files && staging.is_none();
+
+        // Detached payloads are fetched into the component ahead of
+        // installation, rather than installed from the envelope.
+        let payload_fetch = match is_fetched && is_detached {
+            true => vec![
+                CommandDescription::new(SuitConstant::DirectiveFetch),
+                CommandDescription::new(SuitConstant::ConditionImageMatch),
+            ],
+            false => Vec::new(),
+        };
+        let is_installed = is_fetched && !is_detached;
+
+        let default_sequence = |command, is_default| match is_default {
+            true => vec![CommandDescription::new(command)],
+            false => Vec::new(),
+        };
+
+        let sequence = |name, default| -> Result<_, DescriptionError> {
+            match component.get(name) {
+                Some(_) => component
+                    .array(name)?
+                    .iter()
+                    .map(CommandDescription::from_toml)
+                    .collect(),
+                None => Ok(default),
+            }
+        };
+
+        Ok(Self {
+            address: component.integer("address")?,
+            files,
+            slots,
+            staging,
+            install_method,
+
+            text: TextComponent {
+                vendor_name: component.string("vendor-name")?.map(String::from),
+                model_name: component.string("model-name")?.map(String::from),
+                vendor_domain: component.string("vendor-domain")?.map(String::from),
+                model_info: component.string("model-info")?.map(String::from),
+                component_description: component.string("description")?.map(String::from),
+                component_version: component.string("version")?.map(String::from),
+            },
+
+            payload_fetch: sequence("payload-fetch", payload_fetch)?,
+            install: sequence(
+                "install",
+                default_sequence(SuitConstant::DirectiveFetch, is_installed),
+            )?,
+            validate: sequence(
+                "validate",
+                default_sequence(SuitConstant::ConditionImageMatch, has_files),
+            )?,
+            load: sequence("load", Vec::new())?,
+            run: sequence(
+                "run",
+                default_sequence(SuitConstant::DirectiveRun, is_first),
+            )?,
+        })
+    }
+}
+
+impl CommandDescription {
+    /// Describe `command` with its default reporting policy; nothing is
+    /// reported for running a component, and everything for other commands.
+    pub fn new(command: SuitConstant) -> Self {
+        let reporting_policy = match command {
+            SuitConstant::DirectiveRun => ReportingPolicy::none(),
+            _ => ReportingPolicy::all(),
+        };
+
+        Self {
+            command,
+            reporting_policy,
+            sequences: Vec::new(),
+        }
+    }
+
+    /// Read a command given either by its name, or as a table with its
+    /// name, the events it reports, and its nested sequences.
+    fn from_toml(entry: &Entry) -> Result<Self, DescriptionError> {
+        let name = match entry.value {
+            Value::String(name) => name.as_str(),
+            Value::Table(_) => {
+                entry.check_keys(&["command", "report", "sequence", "sequences"])?;
+
+                entry
+                    .string("command")?
+                    .ok_or_else(|| entry.invalid("command", "missing command name"))?
+            }
+            _ => return Err(entry.mismatch("a command name or table")),
+        };
+
+        let command = SuitConstant::members(KeySpace::Command)
+            .iter()
+            .copied()
+            .find(|constant| constant.name() == name)
+            .ok_or_else(|| invalid(entry.path.clone(), format!("unknown command `{}`", name)))?;
+
+        let mut description = Self::new(command);
+
+        if let Value::Table(_) = entry.value {
+            if entry.get("report").is_some() {
+                description.reporting_policy = ReportingPolicy::none();
+            }
+
+            for event in entry.array("report")? {
+                let policy = &mut description.reporting_policy;
+
+                let flag = match event.value.as_str() {
+                    Some("record-success") => &mut policy.record_success,
+                    Some("record-failure") => &mut policy.record_failure,
+                    Some("sysinfo-success") => &mut policy.sysinfo_success,
+                    Some("sysinfo-failure") => &mut policy.sysinfo_failure,
+                    _ => return Err(event.mismatch("a reporting event")),
+                };
+
+                *flag = true;
+            }
+        }
+
+        let sequence = |entry: &Entry| {
+            entry
+                .elements()?
+                .iter()
+                .map(CommandDescription::from_toml)
+                .collect::<Result<Vec<_>, _>>()
+        };
+
+        let nested_key = match command {
+            SuitConstant::DirectiveTryEach => "sequences",
+            SuitConstant::DirectiveRunSequence => "sequence",
+            _ => "",
+        };
+
+        for key in ["sequence", "sequences"] {
+            if key != nested_key && entry.get(key).is_some() {
+                return Err(entry.invalid(key, &format!("not used by `{}`", name)));
+            }
+        }
+
+        description.sequences = match command {
+            SuitConstant::DirectiveTryEach => entry
+                .array("sequences")?
+                .iter()
+                .map(sequence)
+                .collect::<Result<_, _>>()?,
+            SuitConstant::DirectiveRunSequence => match entry.get("sequence") {
+                Some(nested) => vec![sequence(&nested)?],
+                None => Vec::new(),
+            },
+            _ => Vec::new(),
+        };
+
+        match command {
+            SuitConstant::DirectiveTryEach if description.sequences.len() < 2 => Err(invalid(
+                entry.path.clone(),
+                "`directive-try-each` needs at least two `sequences`",
+            )),
+            SuitConstant::DirectiveRunSequence if description.sequences.is_empty() => Err(invalid(
+                entry.path.clone(),
+                "`directive-run-sequence` needs a `sequence`",
+            )),
+            _ => Ok(description),
+        }
+    }
+}
+
+/// Read a firmware file given either by its path, or as a table with
+/// the path of a binary file and its base address.
+fn input_file(entry: &Entry, base_directory: &Path) -> Result<InputFile, DescriptionError> {
+    let (path, base_address) = match entry.value {
+        Value::String(path) => (path.as_str(), None),
+        Value::Table(_) => {
+            entry.check_keys(&["path", "address"])?;
+
+            let path = entry
+                .string("path")?
+                .ok_or_else(|| entry.invalid("path", "missing file path"))?;
+
+            (path, entry.integer("address")?)
+        }
+        _ => return Err(entry.mismatch("a file path or table")),
+    };
+
+    let path = base_directory.join(path);
+
+    let format = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(FileFormat::from_extension);
+
+    match (format, base_address) {
+        (None, _) => Err(invalid(entry.path.clone(), "unsupported file format")),
+        (Some(FileFormat::Binary), None) => Err(invalid(
+            entry.path.clone(),
+            "binary files need a base address; e.g. `{ path = \"image.bin\", address = 0x00040000 }`",
+        )),
+        (Some(FileFormat::Binary), Some(_)) | (Some(_), None) => {
+            Ok(InputFile { path, base_address })
+        }
+        (Some(_), Some(_)) => Err(invalid(
+            entry.path.clone(),
+            "a base address can only be given for binary files",
+        )),
+    }
+}
+
+fn invalid<R: Into<String>>(key: String, reason: R) -> DescriptionError {
+    DescriptionError::Invalid {
+        key,
+        reason: reason.into(),
+    }
+}
+
+/// A value in the description, along with the path of keys leading to
+/// it for error messages.
+struct Entry<'a> {
+    value: &'a Value,
+    path: String,
+}
+
+impl<'a> Entry<'a> {
+    fn root(value: &'a Value) -> Result<Self, DescriptionError> {
+        let entry = Self {
+            value,
+            path: String::new(),
+        };
+
+        entry.as_table()?;
+
+        Ok(entry)
+    }
+
+    fn key_path(&self, key: &str) -> String {
+        match self.path.is_empty() {
+            true => key.to_string(),
+            false => format!("{}.{}", self.path, key),
+        }
+    }
+
+    fn invalid(&self, key: &str, reason: &str) -> DescriptionError {
+        invalid(self.key_path(key), reason)
+    }
+
+    fn mismatch(&self, expected: &str) -> DescriptionError {
+        invalid(
+            self.path.clone(),
+            format!("expected {}, found {}", expected, self.value.type_str()),
+        )
+    }
+
+    fn as_table(&self) -> Result<&'a Table, DescriptionError> {
+        self.value
+            .as_table()
+            .ok_or_else(|| self.mismatch("a table"))
+    }
+
+    /// Reject keys that are not `allowed`, which are most likely typos.
+    fn check_keys(&self, allowed: &[&str]) -> Result<(), DescriptionError> {
+        match self
+            .as_table()?
+            .keys()
+            .find(|key| !allowed.contains(&key.as_str()))
+        {
+            Some(key) => Err(self.invalid(key, "unknown key")),
+            None => Ok(()),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<Entry<'a>> {
+        self.value.get(key).map(|value| Entry {
+            value,
+            path: self.key_path(key),
+        })
+    }
+
+    fn string(&self, key: &str) -> Result<Option<&'a str>, DescriptionError> {
+        self.get(key)
+            .map(|entry| {
+                entry
+                    .value
+                    .as_str()
+                    .ok_or_else(|| entry.mismatch("a string"))
+            })
+            .transpose()
+    }
+
+    fn boolean(&self, key: &str) -> Result<Option<bool>, DescriptionError> {
+        self.get(key)
+            .map(|entry| {
+                entry
+                    .value
+                    .as_bool()
+                    .ok_or_else(|| entry.mismatch("a boolean"))
+            })
+            .transpose()
+    }
+
+    fn integer<T: TryFrom<i64>>(&self, key: &str) -> Result<Option<T>, DescriptionError> {
+        self.get(key)
+            .map(|entry| {
+                let integer = entry
+                    .value
+                    .as_integer()
+                    .ok_or_else(|| entry.mismatch("an integer"))?;
+
+                T::try_from(integer).map_err(|_| {
+                    invalid(entry.path.clone(), format!("`{}` is out of range", integer))
+                })
+            })
+            .transpose()
+    }
+
+    fn uuid(&self, key: &str) -> Result<Option<Uuid>, DescriptionError> {
+        self.get(key).map(|entry| entry.as_uuid()).transpose()
+    }
+
+    fn as_uuid(&self) -> Result<Uuid, DescriptionError> {
+        let text = self
+            .value
+            .as_str()
+            .ok_or_else(|| self.mismatch("a UUID string"))?;
+
+        text.parse()
+            .map_err(|reason| invalid(self.path.clone(), reason))
+    }
+
+    fn table(&self, key: &str) -> Result<Option<Entry<'a>>, DescriptionError> {
+        self.get(key)
+            .map(|entry| entry.as_table().map(|_| entry))
+            .transpose()
+    }
+
+    /// The elements of the array at `key`; empty if there is none.
+    fn array(&self, key: &str) -> Result<Vec<Entry<'a>>, DescriptionError> {
+        match self.get(key) {
+            Some(entry) => entry.elements(),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn elements(&self) -> Result<Vec<Entry<'a>>, DescriptionError> {
+        let elements = self
+            .value
+            .as_array()
+            .ok_or_else(|| self.mismatch("an array"))?;
+
+        Ok(elements
+            .iter()
+            .enumerate()
+            .map(|(index, value)| Entry {
+                value,
+                path: format!("{}[{}]", self.path, index),
+            })
+            .collect())
+    }
+
+    /// The tables in the array of tables at `key`.
+    fn tables(&self, key: &str) -> Result<Vec<Entry<'a>>, DescriptionError> {
+        self.array(key)?
+            .into_iter()
+            .map(|entry| entry.as_table().map(|_| entry))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn description(content: &str) -> Description {
+        Description::from_toml(content, Path::new("firmware")).unwrap()
+    }
+
+    fn error(content: &str) -> String {
+        Description::from_toml(content, Path::new("firmware"))
+            .unwrap_err()
+            .to_string()
+    }
+
+    fn commands(sequence: &[CommandDescription]) -> Vec<SuitConstant> {
+        sequence.iter().map(|command| command.command).collect()
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        assert_eq!(error("sequence-numbr = 1"), "`sequence-numbr`: unknown key");
+        assert_eq!(
+            error("[text]\nmanifest = \"\""),
+            "`text.manifest`: unknown key"
+        );
+        assert_eq!(
+            error("[[component]]\naddress = 0\n[[component]]\ninstal = []"),
+            "`component[1].instal`: unknown key"
+        );
+        assert_eq!(
+            error("[[component]]\nrun = [{ command = \"directive-run\", reports = [] }]"),
+            "`component[0].run[0].reports`: unknown key"
+        );
+    }
+
+    #[test]
+    fn rejects_mistyped_keys() {
+        assert_eq!(
+            error("sequence-number = \"3\""),
+            "`sequence-number`: expected an integer, found string"
+        );
+        assert_eq!(
+            error("digest-algorithm = \"md5\""),
+            "`digest-algorithm`: unknown digest algorithm"
+        );
+        assert_eq!(
+            error("[[component]]\nfiles = [3]"),
+            "`component[0].files[0]`: expected a file path or table, found integer"
+        );
+        assert_eq!(
+            error("[[component]]\naddress = -1"),
+            "`component[0].address`: `-1` is out of range"
+        );
+        assert_eq!(
+            error("[[component]]\nrun = [\"directive-walk\"]"),
+            "`component[0].run[0]`: unknown command `directive-walk`"
+        );
+        assert_eq!(
+            error("[[component]]\nrun = [{ command = \"directive-run\", report = [\"record\"] }]"),
+            "`component[0].run[0].report[0]`: expected a reporting event, found string"
+        );
+    }
+
+    #[test]
+    fn defaults_the_sequences_of_components() {
+        let description = description(
+            "[[component]]\nfiles = [\"application.hex\"]\n\
+             [[component]]\naddress = 0x00080000",
+        );
+
+        let [first, second] = &description.components[..] else {
+            panic!("expected two components");
+        };
+
+        assert!(first.payload_fetch.is_empty());
+        assert_eq!(commands(&first.install), [SuitConstant::DirectiveFetch]);
+        assert_eq!(
+            commands(&first.validate),
+            [SuitConstant::ConditionImageMatch]
+        );
+        assert!(first.load.is_empty());
+        assert_eq!(commands(&first.run), [SuitConstant::DirectiveRun]);
+
+        // Only the first component is run, and components without files
+        // have nothing to fetch or match.
+        assert!(second.install.is_empty());
+        assert!(second.validate.is_empty());
+        assert!(second.run.is_empty());
+    }
+
+    #[test]
+    fn reads_the_sequences_of_components() {
+        let description = description(
+            "[[component]]\n\
+             files = [\"application.hex\"]\n\
+             install = []\n\
+             load = [\"directive-fetch\", \"condition-image-match\"]\n\
+             run = [{ command = \"directive-run\", report = [\"record-failure\"] }]",
+        );
+
+        let component = &description.components[0];
+
+        assert!(component.install.is_empty());
+        assert_eq!(
+            commands(&component.validate),
+            [SuitConstant::ConditionImageMatch]
+        );
+        assert_eq!(
+            commands(&component.load),
+            [
+                SuitConstant::DirectiveFetch,
+                SuitConstant::ConditionImageMatch
+            ]
+        );
+
+        assert_eq!(commands(&component.run), [SuitConstant::DirectiveRun]);
+
+        let policy = component.run[0].reporting_policy;
+        assert!(policy.record_failure);
+        assert!(!policy.record_success && !policy.sysinfo_success && !policy.sysinfo_failure);
+    }
+
+    #[test]
+    fn reads_text_fields() {
+        let description = description(
+            "[text]\n\
+             manifest-description = \"Sensor node firmware\"\n\
+             [[component]]\n\
+             files = [\"application.hex\"]\n\
+             vendor-name = \"Example Inc.\"\n\
+             description = \"Application\"\n\
+             version = \"1.4.0\"",
+        );
+
+        assert_eq!(
+            description.manifest_description.as_deref(),
+            Some("Sensor node firmware")
+        );
+        assert_eq!(description.update_description, None);
+
+        let text = &description.components[0].text;
+
+        assert_eq!(text.vendor_name.as_deref(), Some("Example Inc."));
+        assert_eq!(text.component_description.as_deref(), Some("Application"));
+        assert_eq!(text.component_version.as_deref(), Some("1.4.0"));
+        assert_eq!(text.model_name, None);
+    }
+
+    #[test]
+    fn resolves_file_paths_against_the_description() {
+        let description = description(
+            "[[component]]\n\
+             files = [\"application.hex\", { path = \"assets/data.bin\", address = 0x00080000 }]",
+        );
+
+        let files = &description.components[0].files;
+
+        assert_eq!(files[0].path, Path::new("firmware/application.hex"));
+        assert_eq!(files[0].base_address, None);
+
+        assert_eq!(files[1].path, Path::new("firmware/assets/data.bin"));
+        assert_eq!(files[1].base_address, Some(0x0008_0000));
+
+        assert_eq!(
+            error("[[component]]\nfiles = [\"assets/data.bin\"]"),
+            "`component[0].files[0]`: binary files need a base address; \
+             e.g. `{ path = \"image.bin\", address = 0x00040000 }`"
+        );
+    }
+}
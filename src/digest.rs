@@ -0,0 +1,93 @@
+//! Digests of manifests and payloads, as SUIT_Digest structures.
+
+use clap::ValueEnum;
+
+use crate::cbor::Cbor;
+use crate::suit_constant::SuitConstant;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum DigestAlgorithm {
+    Sha256,
+    Sha384,
+    Sha512,
+    Shake128,
+    Shake256,
+}
+
+impl DigestAlgorithm {
+    pub fn apply(&self, input: &[u8]) -> Digest {
+        use openssl::hash::{hash, MessageDigest};
+        use DigestAlgorithm::*;
+
+        let hasher = match self {
+            Sha256 => MessageDigest::sha256(),
+            Sha384 => MessageDigest::sha384(),
+            Sha512 => MessageDigest::sha512(),
+            Shake128 => MessageDigest::shake_128(),
+            Shake256 => MessageDigest::shake_256(),
+        };
+
+        Digest {
+            algorithm: *self,
+            bytes: hash(hasher, input).unwrap().to_vec(),
+        }
+    }
+}
+
+impl DigestAlgorithm {
+    /// Find the digest algorithm that the COSE algorithm identifier
+    /// `algorithm` denotes.
+    pub fn from_cose(algorithm: &Cbor) -> Option<Self> {
+        use DigestAlgorithm::*;
+
+        [Sha256, Sha384, Sha512, Shake128, Shake256]
+            .into_iter()
+            .find(|candidate| Cbor::from(*candidate) == *algorithm)
+    }
+}
+
+impl From<DigestAlgorithm> for Cbor {
+    fn from(algorithm: DigestAlgorithm) -> Self {
+        match algorithm {
+            DigestAlgorithm::Sha256 => SuitConstant::CoseAlgSha256.into(),
+            DigestAlgorithm::Shake128 => SuitConstant::CoseAlgShake128.into(),
+            DigestAlgorithm::Sha384 => SuitConstant::CoseAlgSha384.into(),
+            DigestAlgorithm::Sha512 => SuitConstant::CoseAlgSha512.into(),
+            DigestAlgorithm::Shake256 => SuitConstant::CoseAlgShake256.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Digest {
+    pub algorithm: DigestAlgorithm,
+    pub bytes: Vec<u8>,
+}
+
+impl Digest {
+    /// Read a SUIT digest, which may be wrapped in a byte string. Returns
+    /// `None` if it is malformed or made with an unsupported algorithm.
+    pub fn from_cbor(item: &Cbor) -> Option<Self> {
+        match item.decode_wrapped()? {
+            Cbor::Array(elements) => match elements.as_slice() {
+                [algorithm, Cbor::Bstr(bytes)] => Some(Self {
+                    algorithm: DigestAlgorithm::from_cose(algorithm)?,
+                    bytes: bytes.clone(),
+                }),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Whether the digest is that of `bytes`.
+    pub fn matches(&self, bytes: &[u8]) -> bool {
+        self.algorithm.apply(bytes).bytes == self.bytes
+    }
+}
+
+impl From<Digest> for Cbor {
+    fn from(digest: Digest) -> Cbor {
+        Cbor::Array(vec![digest.algorithm.into(), digest.bytes.into()])
+    }
+}
@@ -0,0 +1,600 @@
+//! Payload encryption, following the SUIT firmware encryption scheme.
+//!
+//! Each payload is encrypted with a random content encryption key (CEK),
+//! and the resulting COSE_Encrypt structure is given to the device as the
+//! encryption info parameter. The ciphertext is detached from the COSE
+//! structure; it is the payload that is integrated in the envelope or
+//! fetched from its URI. The CEK is distributed to the devices in one
+//! COSE recipient per key; either wrapped with a shared AES key (AES-KW),
+//! or with a key agreed with the public key of a device (ECDH-ES+AES-KW).
+//!
+//! SUIT has no separate decryption command; devices decrypt the payload
+//! while fetching or copying it whenever the encryption info is set.
+
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use openssl::aes::{self, AesKey};
+use openssl::bn::BigNumContext;
+use openssl::derive::Deriver;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, Public};
+use openssl::sign::Signer;
+use openssl::symm::{self, Cipher};
+
+use crate::cbor::Cbor;
+use crate::suit_constant::{self, SuitConstant};
+
+/// Identifies the use of the derived key, as the `other` member of the
+/// supplementary public information in the KDF context.
+const KDF_CONTEXT_OTHER: &str = "SUIT Payload Encryption";
+
+/// Algorithms that may be used to encrypt payloads.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ContentAlgorithm {
+    #[clap(name = "a128gcm")]
+    A128Gcm,
+    #[clap(name = "a256gcm")]
+    A256Gcm,
+    #[clap(name = "a128ctr")]
+    A128Ctr,
+    #[clap(name = "a256ctr")]
+    A256Ctr,
+}
+
+impl ContentAlgorithm {
+    fn cipher(&self) -> Cipher {
+        match self {
+            ContentAlgorithm::A128Gcm => Cipher::aes_128_gcm(),
+            ContentAlgorithm::A256Gcm => Cipher::aes_256_gcm(),
+            ContentAlgorithm::A128Ctr => Cipher::aes_128_ctr(),
+            ContentAlgorithm::A256Ctr => Cipher::aes_256_ctr(),
+        }
+    }
+
+    fn is_authenticated(&self) -> bool {
+        matches!(self, ContentAlgorithm::A128Gcm | ContentAlgorithm::A256Gcm)
+    }
+}
+
+impl From<ContentAlgorithm> for Cbor {
+    fn from(algorithm: ContentAlgorithm) -> Self {
+        match algorithm {
+            ContentAlgorithm::A128Gcm => SuitConstant::CoseAlgA128Gcm.into(),
+            ContentAlgorithm::A256Gcm => SuitConstant::CoseAlgA256Gcm.into(),
+            ContentAlgorithm::A128Ctr => SuitConstant::CoseAlgA128Ctr.into(),
+            ContentAlgorithm::A256Ctr => SuitConstant::CoseAlgA256Ctr.into(),
+        }
+    }
+}
+
+/// Key that the content encryption key is distributed with.
+#[derive(Debug, Clone)]
+pub enum RecipientKey {
+    /// AES key shared with the devices, that the CEK is wrapped with.
+    KeyWrap(Vec<u8>),
+
+    /// Public key of a device, that the key to wrap the CEK with is
+    /// agreed with through an ephemeral key.
+    KeyAgreement(PKey<Public>),
+}
+
+impl RecipientKey {
+    /// Load a shared AES key from a file containing the raw 128 or 256
+    /// bit key.
+    pub fn key_wrap_from_file(file: &PathBuf) -> Result<Self, String> {
+        let key = std::fs::read(file).map_err(|_| format!("could not read file `{:?}`", file))?;
+
+        match key.len() {
+            16 | 32 => Ok(RecipientKey::KeyWrap(key)),
+            length => Err(format!(
+                "the key file `{:?}` holds {} bytes, rather than a 128 or 256 bit AES key",
+                file, length
+            )),
+        }
+    }
+
+    /// Load the public key of a device from a PEM file. Supported keys
+    /// are P-256 and P-384 elliptic curve keys.
+    pub fn key_agreement_from_pem_file(file: &PathBuf) -> Result<Self, String> {
+        let pem = std::fs::read(file).map_err(|_| format!("could not read file `{:?}`", file))?;
+
+        let key = PKey::public_key_from_pem(&pem)
+            .map_err(|_| format!("`{:?}` does not contain a PEM public key", file))?;
+
+        match key.id() {
+            Id::EC => match key.ec_key().unwrap().group().curve_name() {
+                Some(Nid::X9_62_PRIME256V1 | Nid::SECP384R1) => Ok(RecipientKey::KeyAgreement(key)),
+                _ => Err(format!("unsupported elliptic curve in `{:?}`", file)),
+            },
+            _ => Err(format!("unsupported key type in `{:?}`", file)),
+        }
+    }
+
+    /// Create the COSE recipient that distributes `cek` to this key.
+    fn recipient(&self, cek: &[u8]) -> Cbor {
+        match self {
+            RecipientKey::KeyWrap(kek) => {
+                let algorithm = match kek.len() {
+                    16 => SuitConstant::CoseAlgA128Kw,
+                    _ => SuitConstant::CoseAlgA256Kw,
+                };
+
+                // The protected header of AES-KW recipients is empty, as
+                // key wrapping offers no way to authenticate it.
+                Cbor::Array(vec![
+                    Vec::<u8>::new().into(),
+                    Cbor::Map(vec![(
+                        SuitConstant::CoseHeaderAlgorithm.into(),
+                        algorithm.into(),
+                    )]),
+                    wrap_key(kek, cek).into(),
+                ])
+            }
+            RecipientKey::KeyAgreement(public_key) => {
+                let (algorithm, wrap_algorithm) = match cek.len() {
+                    16 => (
+                        SuitConstant::CoseAlgEcdhEsA128Kw,
+                        SuitConstant::CoseAlgA128Kw,
+                    ),
+                    _ => (
+                        SuitConstant::CoseAlgEcdhEsA256Kw,
+                        SuitConstant::CoseAlgA256Kw,
+                    ),
+                };
+
+                let protected = protected_header(algorithm.into());
+
+                let group = public_key.ec_key().unwrap().group().curve_name().unwrap();
+                let group = EcGroup::from_curve_name(group).unwrap();
+
+                let ephemeral = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+
+                let mut deriver = Deriver::new(&ephemeral).unwrap();
+                deriver.set_peer(public_key).unwrap();
+                let shared_secret = deriver.derive_to_vec().unwrap();
+
+                let context = Cbor::Array(vec![
+                    wrap_algorithm.into(),
+                    Cbor::Array(vec![Cbor::Null, Cbor::Null, Cbor::Null]),
+                    Cbor::Array(vec![Cbor::Null, Cbor::Null, Cbor::Null]),
+                    Cbor::Array(vec![
+                        ((cek.len() * 8) as u64).into(),
+                        protected.clone().into(),
+                        KDF_CONTEXT_OTHER.as_bytes().to_vec().into(),
+                    ]),
+                ])
+                .serialize();
+
+                let kek = hkdf_sha256(&shared_secret, &context, cek.len());
+
+                Cbor::Array(vec![
+                    protected.into(),
+                    Cbor::Map(vec![(
+                        SuitConstant::CoseHeaderEphemeralKey.into(),
+                        cose_key(&ephemeral.ec_key().unwrap()),
+                    )]),
+                    wrap_key(&kek, cek).into(),
+                ])
+            }
+        }
+    }
+}
+
+/// A payload encrypted for a set of recipients.
+#[derive(Debug, Clone)]
+pub struct EncryptedPayload {
+    /// The encrypted payload; including the authentication tag for
+    /// authenticated algorithms.
+    pub ciphertext: Vec<u8>,
+
+    /// The tagged COSE_Encrypt structure, with the ciphertext detached.
+    pub encryption_info: Cbor,
+}
+
+/// Encrypts payloads with a fresh content encryption key each, which is
+/// distributed to all of the recipient keys.
+#[derive(Debug, Clone)]
+pub struct Encryptor {
+    algorithm: ContentAlgorithm,
+    recipients: Vec<RecipientKey>,
+}
+
+impl Encryptor {
+    pub fn new(algorithm: ContentAlgorithm) -> Self {
+        Self {
+            algorithm,
+            recipients: Vec::new(),
+        }
+    }
+
+    /// Distribute the content encryption key to `key`.
+    pub fn recipient(mut self, key: RecipientKey) -> Self {
+        self.recipients.push(key);
+        self
+    }
+
+    /// Encrypt `plaintext` with a random content encryption key.
+    pub fn encrypt(&self, plaintext: &[u8]) -> EncryptedPayload {
+        let cipher = self.algorithm.cipher();
+
+        let mut cek = vec![0; cipher.key_len()];
+        let mut iv = vec![0; cipher.iv_len().unwrap()];
+        openssl::rand::rand_bytes(&mut cek).unwrap();
+        openssl::rand::rand_bytes(&mut iv).unwrap();
+
+        let iv_header = (SuitConstant::CoseHeaderIv.into(), iv.clone().into());
+
+        // Without authentication, there is no additional authenticated
+        // data to protect the header with, so the algorithm is left
+        // unprotected instead.
+        let (protected, unprotected, ciphertext) = match self.algorithm.is_authenticated() {
+            true => {
+                let protected = protected_header(self.algorithm.into());
+
+                let enc_structure = Cbor::Array(vec![
+                    String::from("Encrypt").into(),
+                    protected.clone().into(),
+                    Vec::<u8>::new().into(),
+                ])
+                .serialize();
+
+                let mut tag = vec![0; 16];
+                let mut ciphertext = symm::encrypt_aead(
+                    cipher,
+                    &cek,
+                    Some(&iv),
+                    &enc_structure,
+                    plaintext,
+                    &mut tag,
+                )
+                .unwrap();
+                ciphertext.extend(tag);
+
+                (protected, vec![iv_header], ciphertext)
+            }
+            false => {
+                let algorithm = (
+                    SuitConstant::CoseHeaderAlgorithm.into(),
+                    self.algorithm.into(),
+                );
+
+                let ciphertext = symm::encrypt(cipher, &cek, Some(&iv), plaintext).unwrap();
+
+                (Vec::new(), vec![algorithm, iv_header], ciphertext)
+            }
+        };
+
+        let recipients = self
+            .recipients
+            .iter()
+            .map(|key| key.recipient(&cek))
+            .collect();
+
+        let encryption_info = Cbor::Tag(
+            suit_constant::COSE_ENCRYPT_TAG,
+            Box::new(Cbor::Array(vec![
+                protected.into(),
+                Cbor::Map(unprotected),
+                Cbor::Null,
+                Cbor::Array(recipients),
+            ])),
+        );
+
+        EncryptedPayload {
+            ciphertext,
+            encryption_info,
+        }
+    }
+}
+
+/// Wrap `key` with the AES key encryption key `kek` (RFC 3394).
+fn wrap_key(kek: &[u8], key: &[u8]) -> Vec<u8> {
+    let kek = AesKey::new_encrypt(kek).unwrap();
+
+    let mut wrapped = vec![0; key.len() + 8];
+    aes::wrap_key(&kek, None, &mut wrapped, key).unwrap();
+
+    wrapped
+}
+
+/// Derive a key of `length` bytes from `secret` with HKDF-SHA-256
+/// (RFC 5869), without salt.
+fn hkdf_sha256(secret: &[u8], info: &[u8], length: usize) -> Vec<u8> {
+    let hmac = |key: &[u8], message: &[u8]| {
+        let key = PKey::hmac(key).unwrap();
+        let mut signer = Signer::new(MessageDigest::sha256(), &key).unwrap();
+        signer.update(message).unwrap();
+        signer.sign_to_vec().unwrap()
+    };
+
+    let pseudorandom_key = hmac(&[0; 32], secret);
+
+    let mut okm = Vec::new();
+    let mut block = Vec::new();
+
+    for counter in 1..=length.div_ceil(32) as u8 {
+        let mut message = block;
+        message.extend_from_slice(info);
+        message.push(counter);
+
+        block = hmac(&pseudorandom_key, &message);
+        okm.extend_from_slice(&block);
+    }
+
+    okm.truncate(length);
+    okm
+}
+
+/// Encode the public part of an elliptic curve key as a COSE_Key.
+fn cose_key<T: openssl::pkey::HasPublic>(key: &EcKey<T>) -> Cbor {
+    let group = key.group();
+
+    let (curve, coordinate_size) = match group.curve_name() {
+        Some(Nid::SECP384R1) => (SuitConstant::CoseCurveP384, 48),
+        _ => (SuitConstant::CoseCurveP256, 32),
+    };
+
+    let mut x = openssl::bn::BigNum::new().unwrap();
+    let mut y = openssl::bn::BigNum::new().unwrap();
+    let mut context = BigNumContext::new().unwrap();
+
+    key.public_key()
+        .affine_coordinates(group, &mut x, &mut y, &mut context)
+        .unwrap();
+
+    Cbor::Map(vec![
+        (
+            SuitConstant::CoseKeyType.into(),
+            SuitConstant::CoseKeyTypeEc2.into(),
+        ),
+        (SuitConstant::CoseKeyCurve.into(), curve.into()),
+        (
+            SuitConstant::CoseKeyX.into(),
+            x.to_vec_padded(coordinate_size).unwrap().into(),
+        ),
+        (
+            SuitConstant::CoseKeyY.into(),
+            y.to_vec_padded(coordinate_size).unwrap().into(),
+        ),
+    ])
+}
+
+/// Encode a protected header bucket containing only the algorithm.
+fn protected_header(algorithm: Cbor) -> Vec<u8> {
+    Cbor::Map(vec![(SuitConstant::CoseHeaderAlgorithm.into(), algorithm)]).serialize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::suit_constant::KeySpace;
+
+    fn decode(digits: &str) -> Vec<u8> {
+        hex::decode(digits).unwrap()
+    }
+
+    /// Split a tagged COSE_Encrypt structure into its protected header,
+    /// unprotected header and recipients.
+    fn cose_encrypt(encryption_info: &Cbor) -> (Vec<u8>, Vec<(Cbor, Cbor)>, Vec<Cbor>) {
+        match encryption_info {
+            Cbor::Tag(suit_constant::COSE_ENCRYPT_TAG, structure) => match structure.as_ref() {
+                Cbor::Array(members) => match members.as_slice() {
+                    [Cbor::Bstr(protected), Cbor::Map(unprotected), Cbor::Null, Cbor::Array(recipients)] => {
+                        (protected.clone(), unprotected.clone(), recipients.clone())
+                    }
+                    _ => panic!("malformed COSE_Encrypt members"),
+                },
+                _ => panic!("COSE_Encrypt is not an array"),
+            },
+            _ => panic!("not a tagged COSE_Encrypt"),
+        }
+    }
+
+    fn header(header: &[(Cbor, Cbor)], label: SuitConstant) -> Cbor {
+        header
+            .iter()
+            .find(|(key, _value)| *key == Cbor::from(label))
+            .map(|(_key, value)| value.clone())
+            .unwrap()
+    }
+
+    fn unwrap_key(kek: &[u8], wrapped: &[u8]) -> Vec<u8> {
+        let kek = AesKey::new_decrypt(kek).unwrap();
+
+        let mut key = vec![0; wrapped.len() - 8];
+        aes::unwrap_key(&kek, None, &mut key, wrapped).unwrap();
+
+        key
+    }
+
+    /// Unwrap the content encryption key from an AES-KW `recipient`.
+    fn key_wrap_cek(recipient: &Cbor, kek: &[u8]) -> Vec<u8> {
+        match recipient {
+            Cbor::Array(members) => match members.as_slice() {
+                [Cbor::Bstr(_), Cbor::Map(_), Cbor::Bstr(wrapped)] => unwrap_key(kek, wrapped),
+                _ => panic!("malformed recipient"),
+            },
+            _ => panic!("recipient is not an array"),
+        }
+    }
+
+    /// Decrypt `payload` with the content encryption key `cek`.
+    fn decrypt(payload: &EncryptedPayload, cek: &[u8]) -> Vec<u8> {
+        let (protected, unprotected, _recipients) = cose_encrypt(&payload.encryption_info);
+
+        let iv = match header(&unprotected, SuitConstant::CoseHeaderIv) {
+            Cbor::Bstr(iv) => iv,
+            _ => panic!("the IV is not a byte string"),
+        };
+
+        let algorithm = match protected.is_empty() {
+            true => header(&unprotected, SuitConstant::CoseHeaderAlgorithm),
+            false => match Cbor::deserialize(&protected).unwrap() {
+                Cbor::Map(protected) => header(&protected, SuitConstant::CoseHeaderAlgorithm),
+                _ => panic!("the protected header is not a map"),
+            },
+        };
+
+        match SuitConstant::lookup(KeySpace::CoseAlgorithm, &algorithm).unwrap() {
+            constant @ (SuitConstant::CoseAlgA128Gcm | SuitConstant::CoseAlgA256Gcm) => {
+                let cipher = match constant {
+                    SuitConstant::CoseAlgA128Gcm => Cipher::aes_128_gcm(),
+                    _ => Cipher::aes_256_gcm(),
+                };
+
+                let enc_structure = Cbor::Array(vec![
+                    String::from("Encrypt").into(),
+                    protected.into(),
+                    Vec::<u8>::new().into(),
+                ])
+                .serialize();
+
+                let (ciphertext, tag) = payload.ciphertext.split_at(payload.ciphertext.len() - 16);
+
+                symm::decrypt_aead(cipher, cek, Some(&iv), &enc_structure, ciphertext, tag).unwrap()
+            }
+            SuitConstant::CoseAlgA128Ctr => {
+                symm::decrypt(Cipher::aes_128_ctr(), cek, Some(&iv), &payload.ciphertext).unwrap()
+            }
+            SuitConstant::CoseAlgA256Ctr => {
+                symm::decrypt(Cipher::aes_256_ctr(), cek, Some(&iv), &payload.ciphertext).unwrap()
+            }
+            constant => panic!("unexpected content algorithm `{}`", constant.name()),
+        }
+    }
+
+    #[test]
+    fn wraps_keys_as_in_rfc_3394() {
+        // Sections 4.1 and 4.6 of RFC 3394.
+        assert_eq!(
+            wrap_key(
+                &decode("000102030405060708090a0b0c0d0e0f"),
+                &decode("00112233445566778899aabbccddeeff")
+            ),
+            decode("1fa68b0a8112b447aef34bd8fb5a7b829d3e862371d2cfe5")
+        );
+        assert_eq!(
+            wrap_key(
+                &decode("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"),
+                &decode("00112233445566778899aabbccddeeff000102030405060708090a0b0c0d0e0f")
+            ),
+            decode(
+                "28c9f404c4b810f4cbccb35cfb87f8263f5786e2d80ed326\
+                 cbc7f0e71a99f43bfb988b9b7a02dd21"
+            )
+        );
+    }
+
+    #[test]
+    fn derives_keys_as_in_rfc_5869() {
+        // Test case 3 of RFC 5869, which is without salt.
+        assert_eq!(
+            hkdf_sha256(&[0x0b; 22], &[], 42),
+            decode(
+                "8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d\
+                 9d201395faa4b61a96c8"
+            )
+        );
+    }
+
+    #[test]
+    fn names_the_content_algorithms_as_in_rfc_9459() {
+        assert_eq!(Cbor::from(ContentAlgorithm::A128Gcm), Cbor::Uint(1));
+        assert_eq!(Cbor::from(ContentAlgorithm::A256Gcm), Cbor::Uint(3));
+        assert_eq!(Cbor::from(ContentAlgorithm::A128Ctr), Cbor::Nint(65534));
+        assert_eq!(Cbor::from(ContentAlgorithm::A256Ctr), Cbor::Nint(65532));
+    }
+
+    #[test]
+    fn encrypts_for_key_wrap_recipients() {
+        let plaintext: Vec<u8> = (0..=255).collect();
+
+        for (algorithm, kek) in [
+            (ContentAlgorithm::A128Gcm, vec![0x11; 16]),
+            (ContentAlgorithm::A256Gcm, vec![0x22; 32]),
+            (ContentAlgorithm::A128Ctr, vec![0x33; 16]),
+            (ContentAlgorithm::A256Ctr, vec![0x44; 32]),
+        ] {
+            let payload = Encryptor::new(algorithm)
+                .recipient(RecipientKey::KeyWrap(kek.clone()))
+                .encrypt(&plaintext);
+
+            let (protected, unprotected, recipients) = cose_encrypt(&payload.encryption_info);
+
+            assert_eq!(protected.is_empty(), !algorithm.is_authenticated());
+            assert_eq!(recipients.len(), 1);
+            assert!(unprotected.len() <= 2);
+
+            let cek = key_wrap_cek(&recipients[0], &kek);
+            assert_eq!(decrypt(&payload, &cek), plaintext);
+        }
+    }
+
+    #[test]
+    fn encrypts_for_key_agreement_recipients() {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let device = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let public_key = PKey::public_key_from_pem(&device.public_key_to_pem().unwrap()).unwrap();
+
+        let plaintext = b"firmware".to_vec();
+
+        let payload = Encryptor::new(ContentAlgorithm::A128Gcm)
+            .recipient(RecipientKey::KeyAgreement(public_key))
+            .encrypt(&plaintext);
+
+        let (_protected, _unprotected, recipients) = cose_encrypt(&payload.encryption_info);
+
+        let (protected, ephemeral, wrapped) = match &recipients[0] {
+            Cbor::Array(members) => match members.as_slice() {
+                [Cbor::Bstr(protected), Cbor::Map(unprotected), Cbor::Bstr(wrapped)] => (
+                    protected.clone(),
+                    header(unprotected, SuitConstant::CoseHeaderEphemeralKey),
+                    wrapped.clone(),
+                ),
+                _ => panic!("malformed recipient"),
+            },
+            _ => panic!("recipient is not an array"),
+        };
+
+        let coordinate = |label| match &ephemeral {
+            Cbor::Map(key) => match header(key, label) {
+                Cbor::Bstr(coordinate) => openssl::bn::BigNum::from_slice(&coordinate).unwrap(),
+                _ => panic!("the coordinate is not a byte string"),
+            },
+            _ => panic!("the ephemeral key is not a map"),
+        };
+
+        let ephemeral = EcKey::from_public_key_affine_coordinates(
+            &group,
+            &coordinate(SuitConstant::CoseKeyX),
+            &coordinate(SuitConstant::CoseKeyY),
+        )
+        .unwrap();
+
+        let ephemeral = PKey::from_ec_key(ephemeral).unwrap();
+
+        let mut deriver = Deriver::new(&device).unwrap();
+        deriver.set_peer(&ephemeral).unwrap();
+        let shared_secret = deriver.derive_to_vec().unwrap();
+
+        let context = Cbor::Array(vec![
+            SuitConstant::CoseAlgA128Kw.into(),
+            Cbor::Array(vec![Cbor::Null, Cbor::Null, Cbor::Null]),
+            Cbor::Array(vec![Cbor::Null, Cbor::Null, Cbor::Null]),
+            Cbor::Array(vec![
+                128u64.into(),
+                protected.into(),
+                KDF_CONTEXT_OTHER.as_bytes().to_vec().into(),
+            ]),
+        ])
+        .serialize();
+
+        let kek = hkdf_sha256(&shared_secret, &context, 16);
+        let cek = unwrap_key(&kek, &wrapped);
+
+        assert_eq!(decrypt(&payload, &cek), plaintext);
+    }
+}
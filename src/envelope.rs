@@ -0,0 +1,266 @@
+//! The SUIT envelope; the outermost structure of an update, carrying the
+//! manifest along with its authentication and any integrated payloads.
+
+use crate::cbor::Cbor;
+use crate::cose::{MacKey, SigningKey};
+use crate::dependency::Dependency;
+use crate::digest::DigestAlgorithm;
+use crate::manifest::Manifest;
+use crate::payload::Payload;
+use crate::suit_constant::{self, SuitConstant};
+
+#[derive(Debug)]
+pub struct Envelope {
+    pub authentication_wrapper: Authentication,
+    pub manifest: Manifest,
+    pub integrated_payloads: Vec<Payload>,
+
+    /// Envelopes of the manifests that the manifest depends on, kept
+    /// with their own authentication.
+    pub integrated_dependencies: Vec<Dependency>,
+
+    pub add_tag: bool,
+
+    /// Leave out the members severed from the manifest, e.g. to save
+    /// bandwidth when devices have no use for them.
+    pub strip_severed: bool,
+}
+
+impl Envelope {
+    /// Serialize the envelope to its CBOR encoding.
+    pub fn serialize(self) -> Vec<u8> {
+        Cbor::from(self).serialize()
+    }
+}
+
+impl From<Envelope> for Cbor {
+    fn from(envelope: Envelope) -> Self {
+        let payloads = envelope.integrated_payloads.into_iter().map(|payload| {
+            (
+                payload.uri.clone().into(),
+                payload.delivered_bytes().to_vec().into(),
+            )
+        });
+
+        let dependencies = envelope
+            .integrated_dependencies
+            .into_iter()
+            .map(|dependency| (dependency.uri.into(), dependency.envelope.into()));
+
+        let (manifest, severed) = envelope
+            .manifest
+            .encode(envelope.authentication_wrapper.digest_algorithm);
+
+        // The manifest is bstr-wrapped, and its digest is computed over
+        // the exact same bytes as those placed in the envelope.
+        let manifest = manifest.serialize();
+
+        let mut envelope_content = vec![
+            (
+                SuitConstant::AuthenticationWrapper.into(),
+                envelope.authentication_wrapper.authenticate(&manifest),
+            ),
+            (SuitConstant::Manifest.into(), manifest.into()),
+        ];
+
+        if !envelope.strip_severed {
+            envelope_content.extend(severed);
+        }
+
+        envelope_content.extend(dependencies);
+        envelope_content.extend(payloads);
+
+        let untagged_envelope = Cbor::Map(envelope_content);
+
+        if envelope.add_tag {
+            Cbor::Tag(
+                suit_constant::SUIT_ENVELOPE_TAG,
+                Box::new(untagged_envelope),
+            )
+        } else {
+            untagged_envelope
+        }
+    }
+}
+
+/// Builder for an [`Envelope`]. By default, the manifest digest is
+/// computed with SHA-256, no authentication blocks are added, no payloads
+/// or dependencies are integrated, the envelope is tagged, and severed
+/// members are kept.
+#[derive(Debug)]
+pub struct EnvelopeBuilder {
+    manifest: Manifest,
+    digest_algorithm: DigestAlgorithm,
+    method: AuthenticationMethod,
+    integrated_payloads: Vec<Payload>,
+    integrated_dependencies: Vec<Dependency>,
+    add_tag: bool,
+    strip_severed: bool,
+}
+
+impl EnvelopeBuilder {
+    pub fn new(manifest: Manifest) -> Self {
+        Self {
+            manifest,
+            digest_algorithm: DigestAlgorithm::Sha256,
+            method: AuthenticationMethod::Sign(Vec::new()),
+            integrated_payloads: Vec::new(),
+            integrated_dependencies: Vec::new(),
+            add_tag: true,
+            strip_severed: false,
+        }
+    }
+
+    /// Algorithm to compute the manifest digest with.
+    pub fn digest_algorithm(mut self, algorithm: DigestAlgorithm) -> Self {
+        self.digest_algorithm = algorithm;
+        self
+    }
+
+    /// Add a signature made with `key`. Replaces any MAC key.
+    pub fn sign_with(mut self, key: SigningKey) -> Self {
+        match &mut self.method {
+            AuthenticationMethod::Sign(keys) => keys.push(key),
+            AuthenticationMethod::Mac(_) => self.method = AuthenticationMethod::Sign(vec![key]),
+        }
+        self
+    }
+
+    /// Authenticate the manifest with a MAC made with `key`, instead of signing.
+    pub fn mac_with(mut self, key: MacKey) -> Self {
+        self.method = AuthenticationMethod::Mac(key);
+        self
+    }
+
+    /// Integrate `payloads` in the envelope, referenced by their URIs.
+    pub fn integrate(mut self, payloads: Vec<Payload>) -> Self {
+        self.integrated_payloads.extend(payloads);
+        self
+    }
+
+    /// Integrate the envelopes of `dependencies`, referenced by their URIs.
+    pub fn integrate_dependencies(mut self, dependencies: Vec<Dependency>) -> Self {
+        self.integrated_dependencies.extend(dependencies);
+        self
+    }
+
+    /// Whether to add the SUIT envelope tag.
+    pub fn tag(mut self, add_tag: bool) -> Self {
+        self.add_tag = add_tag;
+        self
+    }
+
+    /// Whether to leave out the members severed from the manifest.
+    pub fn strip_severed(mut self, strip_severed: bool) -> Self {
+        self.strip_severed = strip_severed;
+        self
+    }
+
+    pub fn build(self) -> Envelope {
+        Envelope {
+            authentication_wrapper: Authentication {
+                digest_algorithm: self.digest_algorithm,
+                method: self.method,
+            },
+            manifest: self.manifest,
+            integrated_payloads: self.integrated_payloads,
+            integrated_dependencies: self.integrated_dependencies,
+            add_tag: self.add_tag,
+            strip_severed: self.strip_severed,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Authentication {
+    pub digest_algorithm: DigestAlgorithm,
+    pub method: AuthenticationMethod,
+}
+
+/// How the manifest digest is authenticated; either with any number of
+/// asymmetric signatures, or with a MAC from a single pre-shared key.
+#[derive(Debug)]
+pub enum AuthenticationMethod {
+    Sign(Vec<SigningKey>),
+    Mac(MacKey),
+}
+
+impl Authentication {
+    /// Create the bstr-wrapped authentication wrapper for an encoded
+    /// `manifest`; the manifest digest followed by the COSE_Sign1 or
+    /// COSE_Mac0 blocks authenticating it.
+    pub fn authenticate(self, manifest: &[u8]) -> Cbor {
+        let digest = Cbor::from(self.digest_algorithm.apply(manifest)).serialize();
+
+        let blocks: Vec<Cbor> = match self.method {
+            AuthenticationMethod::Sign(keys) => keys.iter().map(|key| key.sign1(&digest)).collect(),
+            AuthenticationMethod::Mac(key) => vec![key.mac0(&digest)],
+        };
+
+        let authentication = std::iter::once(digest.into())
+            .chain(blocks.into_iter().map(|block| block.serialize().into()))
+            .collect();
+
+        Cbor::Array(authentication).serialize().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::manifest::{
+        ComponentIdentifier, ManifestBuilder, SequenceBuilder, SeverableMember, Text,
+    };
+
+    /// The keys of the members of an untagged envelope.
+    fn members(envelope: Envelope) -> Vec<Cbor> {
+        match Cbor::from(envelope) {
+            Cbor::Map(entries) => entries.into_iter().map(|(key, _value)| key).collect(),
+            _ => panic!("the envelope is not a map"),
+        }
+    }
+
+    #[test]
+    fn strips_severed_members() {
+        let manifest = ManifestBuilder::new(vec![ComponentIdentifier(0x1000)])
+            .install(SequenceBuilder::new().set_component_index(0).build())
+            .text(Text {
+                manifest_description: Some(String::from("Sensor node firmware")),
+                ..Text::default()
+            })
+            .sever(vec![SeverableMember::Install])
+            .build();
+
+        let envelope = |strip_severed| {
+            EnvelopeBuilder::new(manifest.clone())
+                .tag(false)
+                .strip_severed(strip_severed)
+                .build()
+        };
+
+        assert_eq!(
+            members(envelope(false)),
+            [
+                SuitConstant::AuthenticationWrapper.into(),
+                SuitConstant::Manifest.into(),
+                SuitConstant::Install.into(),
+            ]
+        );
+        assert_eq!(
+            members(envelope(true)),
+            [
+                SuitConstant::AuthenticationWrapper.into(),
+                SuitConstant::Manifest.into(),
+            ]
+        );
+
+        // Stripping leaves the manifest itself, and so its digest, unchanged.
+        let manifest = |envelope: Envelope| match Cbor::from(envelope) {
+            Cbor::Map(entries) => entries[..2].to_vec(),
+            _ => panic!("the envelope is not a map"),
+        };
+
+        assert_eq!(manifest(envelope(false)), manifest(envelope(true)));
+    }
+}
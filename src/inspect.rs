@@ -0,0 +1,360 @@
+//! Human readable rendering of SUIT envelopes.
+//!
+//! The output is CBOR diagnostic notation, where keys and values that
+//! have a meaning in SUIT are replaced by their symbolic names, and byte
+//! strings that wrap CBOR are decoded and shown between `<<` and `>>`.
+
+use crate::cbor::Cbor;
+use crate::suit_constant::{self, KeySpace, SuitConstant};
+
+/// Longest line that is kept on a single line when rendering containers.
+const MAX_INLINE_WIDTH: usize = 96;
+
+/// Longest byte string that is rendered in full; longer byte strings
+/// (typically integrated payloads) are shortened to this many bytes.
+const MAX_BSTR_BYTES: usize = 64;
+
+/// Deepest nesting of byte strings that wrap CBOR which is decoded;
+/// byte strings nested deeper are rendered as they are.
+const MAX_WRAPPED_DEPTH: usize = 32;
+
+/// Which part of the SUIT structure an item is found in; determines
+/// how its keys are named and how its children are interpreted.
+#[derive(Clone, Copy)]
+enum Context {
+    Any,
+    Envelope,
+    Integrated,
+    Authentication,
+    AuthenticationBlock,
+    CoseHeader,
+    CoseKey,
+    Encrypt,
+    Recipients,
+    Recipient,
+    Algorithm,
+    Digest,
+    Manifest,
+    Common,
+    Dependencies,
+    Dependency,
+    Sequence,
+    SeverableSequence,
+    SequenceList,
+    Parameters,
+    Text,
+    TextComponent,
+}
+
+/// Render a decoded SUIT envelope.
+pub fn render(envelope: &Cbor) -> String {
+    render_item(envelope, Context::Envelope, 0)
+}
+
+/// Render the parameters of a component, as they are given to the
+/// override-parameters directive.
+pub fn render_parameters(parameters: &Cbor) -> String {
+    render_item(parameters, Context::Parameters, 0)
+}
+
+/// Render `item` as found in `context`, within `depth` byte strings that
+/// wrap CBOR.
+fn render_item(item: &Cbor, context: Context, depth: usize) -> String {
+    use SuitConstant::*;
+
+    match (context, item) {
+        (Context::Any, _) => render_plain(item),
+        (_, Cbor::Tag(number, content)) => {
+            format!("{}({})", number, render_item(content, context, depth))
+        }
+
+        (_, Cbor::Bstr(_)) if depth >= MAX_WRAPPED_DEPTH => render_plain(item),
+
+        // Severed members are replaced by their digest in the manifest.
+        (Context::SeverableSequence, Cbor::Bstr(_)) => render_item(item, Context::Sequence, depth),
+        (Context::SeverableSequence, _) => render_item(item, Context::Digest, depth),
+        (Context::Text, Cbor::Array(_)) => render_item(item, Context::Digest, depth),
+
+        // Integrated dependencies are envelopes themselves, whereas
+        // integrated payloads are shown as they are.
+        (Context::Integrated, Cbor::Bstr(bytes)) => match Cbor::deserialize(bytes) {
+            Ok(envelope @ Cbor::Tag(suit_constant::SUIT_ENVELOPE_TAG, _)) => {
+                format!(
+                    "<< {} >>",
+                    render_item(&envelope, Context::Envelope, depth + 1)
+                )
+            }
+            _ => render_plain(item),
+        },
+
+        (_, Cbor::Bstr(bytes)) => match Cbor::deserialize(bytes) {
+            Ok(decoded) => format!("<< {} >>", render_item(&decoded, context, depth + 1)),
+            Err(_) => render_plain(item),
+        },
+
+        (Context::Envelope, Cbor::Map(entries)) => {
+            render_map(entries, depth, KeySpace::Envelope, |key| match key {
+                Some(AuthenticationWrapper) => Context::Authentication,
+                Some(Manifest) => Context::Manifest,
+                Some(PayloadFetch | Install) => Context::Sequence,
+                Some(Text) => Context::Text,
+                _ => Context::Integrated,
+            })
+        }
+        (Context::Authentication, Cbor::Array(elements)) => render_array(
+            elements
+                .iter()
+                .enumerate()
+                .map(|(index, element)| match index {
+                    0 => render_item(element, Context::Digest, depth),
+                    _ => render_item(element, Context::AuthenticationBlock, depth),
+                }),
+        ),
+        (Context::AuthenticationBlock, Cbor::Array(elements)) => render_array(
+            elements
+                .iter()
+                .enumerate()
+                .map(|(index, element)| match index {
+                    0 | 1 => render_item(element, Context::CoseHeader, depth),
+                    _ => render_plain(element),
+                }),
+        ),
+        (Context::CoseHeader, Cbor::Map(entries)) => {
+            render_map(entries, depth, KeySpace::CoseHeader, |key| match key {
+                Some(CoseHeaderAlgorithm) => Context::Algorithm,
+                Some(CoseHeaderEphemeralKey) => Context::CoseKey,
+                _ => Context::Any,
+            })
+        }
+        (Context::CoseKey, Cbor::Map(entries)) => {
+            render_map(entries, depth, KeySpace::CoseKey, |_key| Context::Any)
+        }
+        (Context::Encrypt, Cbor::Array(elements)) => render_array(elements.iter().enumerate().map(
+            |(index, element)| match index {
+                0 | 1 => render_item(element, Context::CoseHeader, depth),
+                3 => render_item(element, Context::Recipients, depth),
+                _ => render_plain(element),
+            },
+        )),
+        (Context::Recipients, Cbor::Array(elements)) => render_array(
+            elements
+                .iter()
+                .map(|element| render_item(element, Context::Recipient, depth)),
+        ),
+        (Context::Recipient, Cbor::Array(elements)) => render_array(
+            elements
+                .iter()
+                .enumerate()
+                .map(|(index, element)| match index {
+                    0 | 1 => render_item(element, Context::CoseHeader, depth),
+                    _ => render_plain(element),
+                }),
+        ),
+        (Context::Algorithm, algorithm) => {
+            match SuitConstant::lookup(KeySpace::CoseAlgorithm, algorithm) {
+                Some(constant) => constant.name().to_string(),
+                None => render_plain(algorithm),
+            }
+        }
+        (Context::Digest, Cbor::Array(elements)) => render_array(elements.iter().enumerate().map(
+            |(index, element)| match index {
+                0 => render_item(element, Context::Algorithm, depth),
+                _ => render_plain(element),
+            },
+        )),
+        (Context::Manifest, Cbor::Map(entries)) => {
+            render_map(entries, depth, KeySpace::Manifest, |key| match key {
+                Some(Common) => Context::Common,
+                Some(PayloadFetch | Install) => Context::SeverableSequence,
+                Some(Validate | Load | Run) => Context::Sequence,
+                Some(Text) => Context::Text,
+                _ => Context::Any,
+            })
+        }
+        (Context::Common, Cbor::Map(entries)) => {
+            render_map(entries, depth, KeySpace::Common, |key| match key {
+                Some(Dependencies) => Context::Dependencies,
+                Some(CommonSequence) => Context::Sequence,
+                _ => Context::Any,
+            })
+        }
+        (Context::Dependencies, Cbor::Array(elements)) => render_array(
+            elements
+                .iter()
+                .map(|element| render_item(element, Context::Dependency, depth)),
+        ),
+        (Context::Dependency, Cbor::Map(entries)) => {
+            render_map(entries, depth, KeySpace::Dependency, |key| match key {
+                Some(DependencyDigest) => Context::Digest,
+                _ => Context::Any,
+            })
+        }
+        (Context::Sequence, Cbor::Array(elements)) => {
+            render_array(elements.chunks(2).map(|pair| match pair {
+                [command, argument] => {
+                    let constant = SuitConstant::lookup(KeySpace::Command, command);
+
+                    let argument_context = match constant {
+                        Some(DirectiveOverrideParameters) => Context::Parameters,
+                        Some(DirectiveTryEach) => Context::SequenceList,
+                        Some(DirectiveRunSequence) => Context::Sequence,
+                        _ => Context::Any,
+                    };
+
+                    format!(
+                        "{}: {}",
+                        render_key(command, constant),
+                        render_item(argument, argument_context, depth)
+                    )
+                }
+                [command] => render_key(command, SuitConstant::lookup(KeySpace::Command, command)),
+                _ => unreachable!("chunks are never empty"),
+            }))
+        }
+        (Context::SequenceList, Cbor::Array(elements)) => render_array(
+            elements
+                .iter()
+                .map(|element| render_item(element, Context::Sequence, depth)),
+        ),
+        (Context::Parameters, Cbor::Map(entries)) => {
+            render_map(entries, depth, KeySpace::Parameter, |key| match key {
+                Some(ParameterImageDigest) => Context::Digest,
+                Some(ParameterEncryptionInfo) => Context::Encrypt,
+                _ => Context::Any,
+            })
+        }
+        (Context::Text, Cbor::Map(entries)) => {
+            render_entries(entries.iter().map(|(key, value)| match key {
+                Cbor::Array(_) => format!(
+                    "{}: {}",
+                    render_plain(key),
+                    render_item(value, Context::TextComponent, depth)
+                ),
+                _ => {
+                    let constant = SuitConstant::lookup(KeySpace::Text, key);
+                    format!("{}: {}", render_key(key, constant), render_plain(value))
+                }
+            }))
+        }
+        (Context::TextComponent, Cbor::Map(entries)) => {
+            render_map(entries, depth, KeySpace::TextComponent, |_key| Context::Any)
+        }
+
+        _ => render_plain(item),
+    }
+}
+
+/// Render a map whose keys are SUIT constants in `key_space`. The context
+/// of each value is chosen by `value_context` from the constant of its key.
+fn render_map<F>(
+    entries: &[(Cbor, Cbor)],
+    depth: usize,
+    key_space: KeySpace,
+    value_context: F,
+) -> String
+where
+    F: Fn(Option<SuitConstant>) -> Context,
+{
+    render_entries(entries.iter().map(|(key, value)| {
+        let constant = SuitConstant::lookup(key_space, key);
+
+        format!(
+            "{}: {}",
+            render_key(key, constant),
+            render_item(value, value_context(constant), depth)
+        )
+    }))
+}
+
+fn render_key(key: &Cbor, constant: Option<SuitConstant>) -> String {
+    match constant {
+        Some(constant) => constant.name().to_string(),
+        None => render_plain(key),
+    }
+}
+
+/// Render an item in plain diagnostic notation, without giving a SUIT
+/// meaning to its keys and values.
+pub fn render_plain(item: &Cbor) -> String {
+    match item {
+        Cbor::Uint(number) => number.to_string(),
+        Cbor::Nint(number) => format!("-{}", number),
+        Cbor::Bstr(bytes) if bytes.len() > MAX_BSTR_BYTES => format!(
+            "h'{}…' ({} bytes)",
+            hex::encode(&bytes[..MAX_BSTR_BYTES]),
+            bytes.len()
+        ),
+        Cbor::Bstr(bytes) => format!("h'{}'", hex::encode(bytes)),
+        Cbor::Tstr(text) => format!("{:?}", text),
+        Cbor::Array(elements) => render_array(elements.iter().map(render_plain)),
+        Cbor::Map(entries) => render_entries(
+            entries
+                .iter()
+                .map(|(key, value)| format!("{}: {}", render_plain(key), render_plain(value))),
+        ),
+        Cbor::Tag(number, content) => format!("{}({})", number, render_plain(content)),
+        Cbor::True => String::from("true"),
+        Cbor::False => String::from("false"),
+        Cbor::Null => String::from("null"),
+        Cbor::Undefined => String::from("undefined"),
+        Cbor::Simple(value) => format!("simple({})", value),
+        Cbor::Float(number) => format!("{:?}", number),
+    }
+}
+
+fn render_array<I>(elements: I) -> String
+where
+    I: Iterator<Item = String>,
+{
+    render_container('[', ']', elements.collect())
+}
+
+fn render_entries<I>(entries: I) -> String
+where
+    I: Iterator<Item = String>,
+{
+    render_container('{', '}', entries.collect())
+}
+
+/// Lay out already rendered `elements` between delimiters; on a single
+/// line if they fit, and otherwise one indented element per line.
+fn render_container(open: char, close: char, elements: Vec<String>) -> String {
+    let inline = elements.join(", ");
+
+    if !inline.contains('\n') && inline.len() <= MAX_INLINE_WIDTH {
+        return format!("{}{}{}", open, inline, close);
+    }
+
+    let body = elements
+        .iter()
+        .map(|element| {
+            element
+                .lines()
+                .map(|line| format!("  {}", line))
+                .collect::<Vec<String>>()
+                .join("\n")
+        })
+        .collect::<Vec<String>>()
+        .join(",\n");
+
+    format!("{}\n{}\n{}", open, body, close)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_the_nesting_of_wrapped_items() {
+        let mut item = Cbor::Uint(1);
+
+        for _ in 0..10_000 {
+            item = Cbor::Bstr(item.serialize());
+        }
+
+        let rendered = render(&item);
+
+        assert_eq!(rendered.matches("<<").count(), MAX_WRAPPED_DEPTH);
+        assert!(rendered.contains("h'"));
+    }
+}
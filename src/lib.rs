@@ -0,0 +1,26 @@
+//! Generation of SUIT envelopes for firmware updates.
+//!
+//! Payloads are read from firmware files with [`payload::PayloadBuilder`],
+//! described by a manifest made with [`manifest::ManifestBuilder`], and
+//! wrapped in an authenticated envelope made with
+//! [`envelope::EnvelopeBuilder`]. Existing envelopes are checked with
+//! [`verify::verify`], and processed on a simulated device with
+//! [`simulator::Device`].
+
+pub mod cbor;
+pub mod cose;
+pub mod dependency;
+pub mod description;
+pub mod digest;
+pub mod encryption;
+pub mod envelope;
+pub mod inspect;
+pub mod manifest;
+pub mod output;
+pub mod payload;
+pub mod simulator;
+pub mod suit_constant;
+pub mod uuid;
+pub mod verify;
+
+pub use cbor::Cbor;
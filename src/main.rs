@@ -1,21 +1,65 @@
-use std::path::PathBuf;
-
-use clap::{Parser, ValueEnum};
-
-mod cbor;
-mod payload;
-mod suit_constant;
-
-use cbor::Cbor;
-use payload::Payload;
-use suit_constant::SuitConstant;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Parser, Subcommand};
+
+use suit_manifest_generator::cose::{MacAlgorithm, MacKey, SigningKey, VerifyingKey};
+use suit_manifest_generator::dependency::Dependency;
+use suit_manifest_generator::description::Description;
+use suit_manifest_generator::digest::DigestAlgorithm;
+use suit_manifest_generator::encryption::{ContentAlgorithm, Encryptor, RecipientKey};
+use suit_manifest_generator::envelope::{Envelope, EnvelopeBuilder};
+use suit_manifest_generator::manifest::{
+    Command, ComponentIdentifier, InstallMethod, Manifest, ManifestBuilder, Parameter,
+    ReportingPolicy, SequenceBuilder, SeverableMember,
+};
+use suit_manifest_generator::output::{self, OutputFormat};
+use suit_manifest_generator::payload::{FileFormat, InputFile, Payload, PayloadBuilder};
+use suit_manifest_generator::simulator::Device;
+use suit_manifest_generator::uuid::Uuid;
+use suit_manifest_generator::verify::{self, Severity, VerificationKey};
+use suit_manifest_generator::{inspect, Cbor};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(args_conflicts_with_subcommands = true)]
 struct Cli {
-    /// Payload list to use in manifest
+    #[clap(subcommand)]
+    command: Option<CliCommand>,
+
+    /// Without a subcommand, an envelope is generated as with `generate`
+    #[clap(flatten)]
+    generate: GenerateArgs,
+}
+
+#[derive(Subcommand, Debug)]
+enum CliCommand {
+    /// Generate a SUIT envelope from a set of firmware files
+    Generate(GenerateArgs),
+
+    /// Generate a SUIT envelope from a TOML manifest description
+    Build(BuildArgs),
+
+    /// Print the content of an existing SUIT envelope
+    Inspect {
+        /// Envelope file to print
+        #[clap(value_parser)]
+        envelope: PathBuf,
+    },
+
+    /// Check the authentication, payloads and structure of an existing SUIT envelope
+    Verify(VerifyArgs),
+
+    /// Process SUIT envelopes on a simulated device, and report what it does
+    Simulate(SimulateArgs),
+}
+
+#[derive(Args, Debug)]
+struct GenerateArgs {
+    /// Payload list to use in manifest; binary files are given with their
+    /// base address, e.g. `image.bin@0x00040000`
     #[clap(value_parser = cli_legal_hex_file)]
-    payload: Vec<PathBuf>,
+    payload: Vec<InputFile>,
 
     /// Allow that subsequent payloads overwrite earlier ones without error
     #[clap(short, long, value_parser, default_value_t = false)]
@@ -36,512 +80,839 @@ struct Cli {
     /// Algorithm to create payload digests with
     #[clap(short, long, value_parser, default_value = "sha256")]
     digest_algorithm: DigestAlgorithm,
-}
 
-fn cli_legal_hex_file(arg: &str) -> Result<PathBuf, String> {
-    let path = PathBuf::from(arg);
+    /// Install each payload into whichever of this many slots the device
+    /// accepts, e.g. 2 for the banks of a dual-bank flash, instead of
+    /// loading it in place
+    #[clap(long, value_parser = clap::value_parser!(u8).range(2..))]
+    slots: Option<u8>,
+
+    /// Payload of a further slot, e.g. the firmware linked for the second bank
+    /// of a dual-bank flash, where the payload list is that of the first slot;
+    /// may be given multiple times. Each payload is installed into whichever of
+    /// its slots the device accepts
+    #[clap(long = "slot-payload", value_parser = cli_legal_hex_file, conflicts_with = "slots")]
+    slot_payloads: Vec<InputFile>,
+
+    /// Address of a staging component to fetch each payload into, after
+    /// which it is installed into its own component, instead of loading
+    /// it in place
+    #[clap(
+        long,
+        value_parser = cli_component_address,
+        conflicts_with_all = &["slots", "slot-payloads"]
+    )]
+    staging_address: Option<u32>,
+
+    /// How payloads are installed from the staging component
+    #[clap(
+        long,
+        value_parser,
+        default_value = "copy",
+        requires = "staging-address"
+    )]
+    install_method: InstallMethod,
+
+    /// URI template that devices fetch the payloads from, instead of them being
+    /// integrated in the envelope; `{index}` in the last segment is replaced by
+    /// the number of the payload, e.g. `https://example.com/firmware/{index}.bin`.
+    /// Such payloads are not compressed
+    #[clap(long, value_parser)]
+    payload_uri: Option<String>,
+
+    /// Pass the entry point of the firmware files as run arguments
+    /// to the component that contains it, and run that component
+    #[clap(short, long, value_parser, default_value_t = false)]
+    entry_point_run_args: bool,
 
-    let extension = path
-        .extension()
-        .ok_or(String::from("Unknown file type; no extension specified"))?;
+    #[clap(flatten)]
+    identifiers: IdentifierArgs,
 
-    match extension.to_str().unwrap() {
-        "hex" => Ok(path),
-        file_type => Err(format!("Unsupported file format `{}`", file_type)),
-    }
+    /// Envelope of a manifest that this manifest depends on, to integrate in
+    /// the envelope; may be given multiple times
+    #[clap(long = "dependency", value_parser)]
+    dependencies: Vec<PathBuf>,
+
+    #[clap(flatten)]
+    devices: DeviceArgs,
+
+    #[clap(flatten)]
+    encryption: EncryptionArgs,
+
+    #[clap(flatten)]
+    authentication: AuthenticationArgs,
+
+    #[clap(flatten)]
+    output: OutputArgs,
 }
 
-fn main() {
-    let args = Cli::parse();
+#[derive(Args, Debug)]
+struct BuildArgs {
+    /// TOML file describing the components of the manifest and their payloads
+    #[clap(value_parser)]
+    description: PathBuf,
 
-    let payloads = payload::from_hex_files(
-        &args.payload,
-        args.fill,
-        args.allow_overwrites,
-        args.compress,
-    );
+    /// Envelope of a manifest that this manifest depends on, to integrate in
+    /// the envelope; may be given multiple times
+    #[clap(long = "dependency", value_parser)]
+    dependencies: Vec<PathBuf>,
 
-    let components: Vec<ComponentIdentifier> = payloads
-        .iter()
-        .map(|payload| ComponentIdentifier(payload.start_address))
-        .collect();
+    #[clap(flatten)]
+    devices: DeviceArgs,
 
-    let common = Common {
-        components,
-        common_sequence: None,
-    };
+    #[clap(flatten)]
+    encryption: EncryptionArgs,
 
-    let validate = payloads
-        .iter()
-        .enumerate()
-        .map(|(component_index, payload)| {
-            vec![
-                Command::DirectiveSetComponentIndex(IndexArgument::Single(component_index)),
-                Command::DirectiveOverrideParameters(vec![
-                    Parameter::ImageDigest(args.digest_algorithm.apply(&payload.bytes)),
-                    Parameter::ImageSize(payload.size),
-                ]),
-                Command::ConditionImageMatch(ReportingPolicy::all()),
-            ]
-        })
-        .reduce(|mut acc, mut sequence| {
-            acc.append(&mut sequence);
-            acc
-        });
+    #[clap(flatten)]
+    authentication: AuthenticationArgs,
 
-    let load = payloads
-        .iter()
-        .enumerate()
-        .map(|(component_index, payload)| {
-            vec![
-                Command::DirectiveSetComponentIndex(IndexArgument::Single(component_index)),
-                Command::DirectiveOverrideParameters(vec![Parameter::Uri(payload.uri.clone())]),
-                Command::DirectiveFetch(ReportingPolicy::all()),
-            ]
-        })
-        .reduce(|mut acc, mut sequence| {
-            acc.append(&mut sequence);
-            acc
-        });
+    #[clap(flatten)]
+    output: OutputArgs,
+}
 
-    let run = Some(vec![
-        Command::DirectiveSetComponentIndex(IndexArgument::Single(0)),
-        Command::DirectiveRun(ReportingPolicy::none()),
-    ]);
+#[derive(Args, Debug)]
+struct IdentifierArgs {
+    /// Domain name of the vendor to derive the vendor identifier from
+    #[clap(long, value_parser)]
+    vendor_domain: Option<String>,
 
-    let manifest = Manifest {
-        sequence_number: args.sequence_number,
-        reference_uri: None,
+    /// UUID of the vendor that devices must match
+    #[clap(long, value_parser = cli_uuid, conflicts_with = "vendor-domain")]
+    vendor_id: Option<Uuid>,
 
-        common,
+    /// Name of the device model to derive the class identifier from,
+    /// along with the vendor identifier
+    #[clap(long, value_parser)]
+    model_name: Option<String>,
 
-        validate,
-        load,
-        run,
+    /// UUID of the device class that devices must match
+    #[clap(long, value_parser = cli_uuid, conflicts_with = "model-name")]
+    class_id: Option<Uuid>,
+}
 
-        payload_fetch: None,
-        install: None,
-        text: None,
-    };
+#[derive(Args, Debug)]
+struct DeviceArgs {
+    /// UUID of a single device to restrict the manifest to; may be given
+    /// multiple times to make an envelope for each device
+    #[clap(long = "device-id", value_parser = cli_uuid)]
+    device_ids: Vec<Uuid>,
+
+    /// File with a device UUID on each line, to make an envelope for each
+    /// device; empty lines and lines starting with `#` are ignored
+    #[clap(long, value_parser)]
+    device_list: Option<PathBuf>,
+}
 
-    let envelope = Envelope {
-        authentication_wrapper: Authentication {},
-        manifest,
-        integrated_payloads: payloads,
-        add_tag: true,
-    };
+#[derive(Args, Debug)]
+struct EncryptionArgs {
+    /// File with a raw 128 or 256 bit AES key shared with devices, to
+    /// distribute the payload encryption key with by AES key wrap; may be
+    /// given multiple times. Payloads are only encrypted when keys are given
+    #[clap(long = "encryption-key", value_parser = cli_key_wrap_key)]
+    encryption_keys: Vec<RecipientKey>,
+
+    /// PEM public key of a device, to distribute the payload encryption key
+    /// with by ECDH-ES key agreement; may be given multiple times
+    #[clap(long = "recipient-key", value_parser = cli_recipient_key)]
+    recipient_keys: Vec<RecipientKey>,
+
+    /// Algorithm to encrypt payloads with
+    #[clap(long, value_parser, default_value = "a128gcm")]
+    encryption_algorithm: ContentAlgorithm,
+}
 
-    let cbor = Cbor::from(envelope);
+#[derive(Args, Debug)]
+struct AuthenticationArgs {
+    /// PEM private key to sign the manifest with; may be given multiple times
+    #[clap(short, long = "key", value_parser = cli_signing_key)]
+    keys: Vec<SigningKey>,
 
-    let serialized = cbor.serialize();
+    /// Pre-shared key file to authenticate the manifest with HMAC instead of signing
+    #[clap(short, long, value_parser = cli_mac_key, conflicts_with = "keys")]
+    mac_key: Option<MacKey>,
 
-    println!("{:#?}", cbor);
-    println!("{:?}", serialized);
+    /// Algorithm to authenticate the manifest with when using a pre-shared key
+    #[clap(long, value_parser, default_value = "hmac256", requires = "mac-key")]
+    mac_algorithm: MacAlgorithm,
 }
 
-#[derive(Debug)]
-struct Envelope {
-    authentication_wrapper: Authentication,
-    manifest: Manifest,
-    integrated_payloads: Vec<Payload>,
+#[derive(Args, Debug)]
+struct VerifyArgs {
+    /// Envelope file to check
+    #[clap(value_parser)]
+    envelope: PathBuf,
+
+    /// PEM public key to check signatures with; may be given multiple times,
+    /// also for the authorities of integrated dependencies
+    #[clap(short, long = "key", value_parser = cli_verifying_key)]
+    keys: Vec<VerifyingKey>,
+
+    /// Pre-shared key file to check HMAC authentication blocks with
+    #[clap(short, long, value_parser = cli_mac_key)]
+    mac_key: Option<MacKey>,
 
-    add_tag: bool,
+    /// Algorithm the manifest is authenticated with when using a pre-shared key
+    #[clap(long, value_parser, default_value = "hmac256", requires = "mac-key")]
+    mac_algorithm: MacAlgorithm,
 }
 
-impl From<Envelope> for Cbor {
-    fn from(envelope: Envelope) -> Self {
-        let payloads = envelope
-            .integrated_payloads
-            .into_iter()
-            .map(|payload| (payload.uri.into(), payload.bytes.into()));
+#[derive(Args, Debug)]
+struct SimulateArgs {
+    /// Envelope files to process in turn on the same device
+    #[clap(value_parser, required = true)]
+    envelopes: Vec<PathBuf>,
 
-        let mut envelope_content = vec![
-            (
-                SuitConstant::AuthenticationWrapper.into(),
-                envelope.authentication_wrapper.into(),
-            ),
-            (SuitConstant::Manifest.into(), envelope.manifest.into()),
-        ];
+    /// UUID of the vendor of the device; any vendor identifier is accepted if not given
+    #[clap(long, value_parser = cli_uuid)]
+    vendor_id: Option<Uuid>,
 
-        envelope_content.extend(payloads);
+    /// UUID of the class of the device; any class identifier is accepted if not given
+    #[clap(long, value_parser = cli_uuid)]
+    class_id: Option<Uuid>,
 
-        let untagged_envelope = Cbor::Map(envelope_content);
+    /// UUID of the device; any device identifier is accepted if not given
+    #[clap(long, value_parser = cli_uuid)]
+    device_id: Option<Uuid>,
 
-        if envelope.add_tag {
-            Cbor::Tag(
-                suit_constant::SUIT_ENVELOPE_TAG,
-                Box::new(untagged_envelope),
-            )
-        } else {
-            untagged_envelope
-        }
-    }
-}
+    /// Slot that the device accepts in component slot conditions; any slot
+    /// is accepted if not given
+    #[clap(long, value_parser)]
+    slot: Option<u64>,
 
-#[derive(Debug)]
-struct Authentication {}
+    /// Directory that detached payloads are fetched from, each named after
+    /// the last segment of its URI
+    #[clap(long, value_parser)]
+    payload_dir: Option<PathBuf>,
 
-impl From<Authentication> for Cbor {
-    fn from(authentication: Authentication) -> Self {
-        Cbor::Uint(1)
-    }
+    /// File to write the resulting memory to, as a binary image from the
+    /// lowest to the highest address written to
+    #[clap(long, value_parser)]
+    memory_image: Option<PathBuf>,
+
+    /// The value that an unwritten byte has in the memory image
+    #[clap(short, long, value_parser, default_value_t = 0xff)]
+    fill: u8,
 }
 
-#[derive(Debug, Clone, Copy, ValueEnum)]
-enum DigestAlgorithm {
-    Sha256,
-    Sha384,
-    Sha512,
-    Shake128,
-    Shake256,
+#[derive(Args, Debug)]
+struct OutputArgs {
+    /// File to write the envelope to; written to standard output if not given.
+    /// When making an envelope for each of several devices, `{device}` in the
+    /// file name is replaced by the UUID of the device
+    #[clap(short, long, value_parser)]
+    output: Option<PathBuf>,
+
+    /// Encoding of the written envelope
+    #[clap(long, value_parser, default_value = "binary")]
+    format: OutputFormat,
+
+    /// Members to sever from the manifest and place in the envelope, separated
+    /// by commas; only the text if not given
+    #[clap(long, value_parser, use_value_delimiter = true)]
+    sever: Option<Vec<SeverableMember>>,
+
+    /// Leave the members severed from the manifest out of the envelope
+    #[clap(long, value_parser, default_value_t = false)]
+    strip_severed: bool,
+
+    /// Directory to write detached payloads to, each named after the last
+    /// segment of its URI; the directory of the output file if not given
+    #[clap(long, value_parser)]
+    payload_dir: Option<PathBuf>,
+
+    /// Print the structure of the envelope before it is serialized
+    #[clap(short, long, value_parser, default_value_t = false)]
+    verbose: bool,
 }
 
-impl DigestAlgorithm {
-    fn apply(&self, input: &[u8]) -> Digest {
-        use openssl::hash::{hash, MessageDigest};
-        use DigestAlgorithm::*;
+fn cli_legal_hex_file(arg: &str) -> Result<InputFile, String> {
+    let (file, base_address) = match arg.rsplit_once('@') {
+        Some((file, address)) => match cli_address(address) {
+            Some(address) => (file, Some(address)),
+            None => (arg, None),
+        },
+        None => (arg, None),
+    };
 
-        let hasher = match self {
-            Sha256 => MessageDigest::sha256(),
-            Sha384 => MessageDigest::sha384(),
-            Sha512 => MessageDigest::sha512(),
-            Shake128 => MessageDigest::shake_128(),
-            Shake256 => MessageDigest::shake_256(),
-        };
+    let path = PathBuf::from(file);
 
-        Digest {
-            algorithm: *self,
-            bytes: hash(hasher, input).unwrap().to_vec(),
-        }
+    let extension = path
+        .extension()
+        .ok_or(String::from("Unknown file type; no extension specified"))?;
+
+    let file_type = extension.to_str().ok_or(String::from(
+        "Unsupported file type; the extension is not valid UTF-8",
+    ))?;
+
+    let format = FileFormat::from_extension(file_type)
+        .ok_or(format!("Unsupported file format `{}`", file_type))?;
+
+    match (format, base_address) {
+        (FileFormat::Binary, None) => Err(String::from(
+            "Binary files need a base address; e.g. `image.bin@0x00040000`",
+        )),
+        (FileFormat::Binary, Some(_)) | (_, None) => Ok(InputFile { path, base_address }),
+        (_, Some(_)) => Err(format!(
+            "A base address can only be given for binary files, not `{}` files",
+            file_type
+        )),
     }
 }
 
-impl From<DigestAlgorithm> for Cbor {
-    fn from(algorithm: DigestAlgorithm) -> Self {
-        match algorithm {
-            DigestAlgorithm::Sha256 => SuitConstant::CoseAlgSha256.into(),
-            DigestAlgorithm::Shake128 => SuitConstant::CoseAlgShake128.into(),
-            DigestAlgorithm::Sha384 => SuitConstant::CoseAlgSha384.into(),
-            DigestAlgorithm::Sha512 => SuitConstant::CoseAlgSha512.into(),
-            DigestAlgorithm::Shake256 => SuitConstant::CoseAlgShake256.into(),
-        }
+/// Parse an address given in either hexadecimal with a `0x` prefix,
+/// or in decimal.
+fn cli_address(arg: &str) -> Option<u32> {
+    match arg.strip_prefix("0x").or_else(|| arg.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => arg.parse().ok(),
     }
 }
 
-#[derive(Debug)]
-struct Digest {
-    algorithm: DigestAlgorithm,
-    bytes: Vec<u8>,
+fn cli_component_address(arg: &str) -> Result<u32, String> {
+    cli_address(arg).ok_or(format!("`{}` is not an address", arg))
 }
 
-impl From<Digest> for Cbor {
-    fn from(digest: Digest) -> Cbor {
-        Cbor::Array(vec![digest.algorithm.into(), digest.bytes.into()])
-    }
+fn cli_signing_key(arg: &str) -> Result<SigningKey, String> {
+    SigningKey::from_pem_file(&PathBuf::from(arg))
 }
 
-#[derive(Debug)]
-struct Manifest {
-    sequence_number: u64,
-    reference_uri: Option<String>,
-
-    common: Common,
-
-    validate: Option<Vec<Command>>,
-    load: Option<Vec<Command>>,
-    run: Option<Vec<Command>>,
-
-    payload_fetch: Option<Vec<Command>>,
-    install: Option<Vec<Command>>,
-    text: Option<Vec<Command>>,
-}
-
-impl From<Manifest> for Cbor {
-    fn from(manifest: Manifest) -> Cbor {
-        let components = Cbor::Array(
-            manifest
-                .common
-                .components
-                .into_iter()
-                .map(|component| component.into())
-                .collect(),
-        );
-
-        let mut common_content = vec![(SuitConstant::Components.into(), components)];
-
-        if let Some(commands) = manifest.common.common_sequence {
-            let sequence = commands
-                .into_iter()
-                .map(|command| command.into_cbor_pair())
-                .fold(Vec::new(), |mut acc, pair| {
-                    acc.push(pair.0);
-                    acc.push(pair.1);
-                    acc
-                });
+fn cli_verifying_key(arg: &str) -> Result<VerifyingKey, String> {
+    VerifyingKey::from_pem_file(&PathBuf::from(arg))
+}
 
-            common_content.push((SuitConstant::CommonSequence.into(), Cbor::Array(sequence)));
-        }
+/// Load a pre-shared key; the algorithm it is used with is set once the
+/// `--mac-algorithm` option is parsed.
+fn cli_mac_key(arg: &str) -> Result<MacKey, String> {
+    MacKey::from_file(&PathBuf::from(arg), MacAlgorithm::Hmac256)
+}
 
-        let common = Cbor::Map(common_content);
+fn cli_key_wrap_key(arg: &str) -> Result<RecipientKey, String> {
+    RecipientKey::key_wrap_from_file(&PathBuf::from(arg))
+}
 
-        let head = vec![
-            (SuitConstant::ManifestVersion.into(), 1.into()),
-            (
-                SuitConstant::ManifestSequenceNumber.into(),
-                (manifest.sequence_number).into(),
-            ),
-            (SuitConstant::Common.into(), common),
-        ];
+fn cli_recipient_key(arg: &str) -> Result<RecipientKey, String> {
+    RecipientKey::key_agreement_from_pem_file(&PathBuf::from(arg))
+}
 
-        let reference_uri = match manifest.reference_uri {
-            Some(uri) => vec![(SuitConstant::ReferenceUri.into(), uri.into())],
-            None => Vec::new(),
-        };
+fn cli_uuid(arg: &str) -> Result<Uuid, String> {
+    arg.parse()
+}
 
-        let command_sequences = [
-            (SuitConstant::PayloadFetch, manifest.payload_fetch),
-            (SuitConstant::Install, manifest.install),
-            (SuitConstant::Text, manifest.text),
-            (SuitConstant::Validate, manifest.validate),
-            (SuitConstant::Load, manifest.load),
-            (SuitConstant::Run, manifest.run),
-        ]
-        .into_iter()
-        .filter(|(_key, value)| value.is_some())
-        .map(|(key, value)| (key.into(), value.unwrap().into()));
-
-        Cbor::Map(
-            head.into_iter()
-                .chain(reference_uri.into_iter())
-                .chain(command_sequences)
-                .collect(),
-        )
+fn main() {
+    let cli = Cli::parse();
+
+    match cli.command {
+        None => generate(cli.generate),
+        Some(CliCommand::Generate(args)) => generate(args),
+        Some(CliCommand::Build(args)) => build(args),
+        Some(CliCommand::Inspect { envelope }) => inspect(&envelope),
+        Some(CliCommand::Verify(args)) => verify(args),
+        Some(CliCommand::Simulate(args)) => simulate(args),
     }
 }
 
-#[derive(Debug)]
-struct Common {
-    components: Vec<ComponentIdentifier>,
-    common_sequence: Option<Vec<Command>>,
+fn inspect(file: &PathBuf) {
+    let bytes = read_file(file);
+
+    let envelope = Cbor::deserialize(&bytes).unwrap_or_else(|error| {
+        eprintln!("error: could not decode `{:?}`: {}", file, error);
+        std::process::exit(1);
+    });
+
+    println!("{}", inspect::render(&envelope));
 }
 
-impl From<Common> for Cbor {
-    fn from(common: Common) -> Cbor {
-        let identifiers = common
-            .components
-            .into_iter()
-            .map(|component| component.into())
-            .collect();
-        let components = (SuitConstant::Components.into(), Cbor::Array(identifiers));
+fn verify(args: VerifyArgs) {
+    let bytes = read_file(&args.envelope);
 
-        match common.common_sequence {
-            Some(sequence) => Cbor::Map(vec![
-                components,
-                (SuitConstant::CommonSequence.into(), sequence.into()),
-            ]),
-            None => Cbor::Map(vec![components]),
-        }
+    let mut keys: Vec<VerificationKey> = args
+        .keys
+        .into_iter()
+        .map(VerificationKey::Signature)
+        .collect();
+
+    if let Some(key) = args.mac_key {
+        keys.push(VerificationKey::Mac(key.with_algorithm(args.mac_algorithm)));
     }
-}
 
-#[derive(Debug)]
-struct ComponentIdentifier(u32);
+    let findings = verify::verify(&bytes, &keys);
 
-impl From<u32> for ComponentIdentifier {
-    fn from(address: u32) -> Self {
-        Self(address)
+    for finding in &findings {
+        println!("{}", finding);
     }
-}
 
-impl From<ComponentIdentifier> for Cbor {
-    fn from(component: ComponentIdentifier) -> Cbor {
-        Cbor::Array(vec![component.0.to_be_bytes().to_vec().into()])
+    if findings
+        .iter()
+        .any(|finding| finding.severity == Severity::Error)
+    {
+        eprintln!("error: `{:?}` is not a valid envelope", args.envelope);
+        std::process::exit(1);
     }
+
+    println!("`{:?}` is a valid envelope", args.envelope);
 }
 
-#[derive(Debug)]
-enum Command {
-    ConditionVendorIdentifier(ReportingPolicy),
-    ConditionClassIdentifier(ReportingPolicy),
-    ConditionDeviceIdentifier(ReportingPolicy),
-    ConditionImageMatch(ReportingPolicy),
-    ConditionComponentSlot(ReportingPolicy),
-    ConditionAbort(ReportingPolicy),
-
-    DirectiveSetComponentIndex(IndexArgument),
-    // DirectiveRunSequence,
-    // DirectiveTryEach,
-    // DirectiveProcessDependency(ReportingPolicy),
-    DirectiveOverrideParameters(Vec<Parameter>),
-    DirectiveFetch(ReportingPolicy),
-    DirectiveCopy(ReportingPolicy),
-    DirectiveSwap(ReportingPolicy),
-    DirectiveRun(ReportingPolicy),
-}
-
-impl Command {
-    fn into_cbor_pair(self) -> (Cbor, Cbor) {
-        match self {
-            Command::ConditionVendorIdentifier(policy) => (
-                SuitConstant::ConditionVendorIdentifier.into(),
-                policy.into(),
-            ),
-            Command::ConditionClassIdentifier(policy) => {
-                (SuitConstant::ConditionClassIdentifier.into(), policy.into())
+fn simulate(args: SimulateArgs) {
+    let mut device = Device::new();
+
+    if let Some(vendor_id) = args.vendor_id {
+        device = device.vendor_identifier(vendor_id);
+    }
+    if let Some(class_id) = args.class_id {
+        device = device.class_identifier(class_id);
+    }
+    if let Some(device_id) = args.device_id {
+        device = device.device_identifier(device_id);
+    }
+    if let Some(slot) = args.slot {
+        device = device.slot(slot);
+    }
+    if let Some(payload_dir) = args.payload_dir {
+        device = device.payload_dir(payload_dir);
+    }
+
+    let mut has_failed = false;
+
+    for file in &args.envelopes {
+        let bytes = read_file(file);
+
+        let reports = device.process(&bytes).unwrap_or_else(|error| {
+            eprintln!("error: {:?}: {}", file, error);
+            std::process::exit(1);
+        });
+
+        println!("{:?}:", file);
+
+        for report in reports {
+            match &report.outcome {
+                Ok(()) => println!("  {}: succeeded", report.procedure),
+                Err(failure) => println!("  {}: failed at {}", report.procedure, failure),
             }
-            Command::ConditionDeviceIdentifier(policy) => (
-                SuitConstant::ConditionDeviceIdentifier.into(),
-                policy.into(),
-            ),
-            Command::ConditionImageMatch(policy) => {
-                (SuitConstant::ConditionImageMatch.into(), policy.into())
+
+            for record in &report.records {
+                println!("    {}", record.to_string().replace('\n', "\n    "));
             }
-            Command::ConditionComponentSlot(policy) => {
-                (SuitConstant::ConditionComponentSlot.into(), policy.into())
+
+            for run in &report.runs {
+                match &run.arguments {
+                    Some(arguments) => println!(
+                        "    {}: run component {} with arguments h'{}'",
+                        run.path,
+                        run.component,
+                        hex::encode(arguments)
+                    ),
+                    None => println!("    {}: run component {}", run.path, run.component),
+                }
             }
-            Command::ConditionAbort(policy) => (SuitConstant::ConditionAbort.into(), policy.into()),
 
-            Command::DirectiveSetComponentIndex(index) => (
-                SuitConstant::DirectiveSetComponentIndex.into(),
-                index.into(),
+            has_failed |= report.outcome.is_err();
+        }
+    }
+
+    println!("memory:");
+
+    for image in device.images() {
+        let slot = match image.slot {
+            Some(slot) => format!(", slot {}", slot),
+            None => String::new(),
+        };
+
+        match image.address() {
+            Some(address) => println!(
+                "  0x{:08x}..0x{:08x}: {} bytes{}",
+                address,
+                address as usize + image.content.len(),
+                image.content.len(),
+                slot
             ),
-            Command::DirectiveOverrideParameters(parameters) => (
-                SuitConstant::DirectiveOverrideParameters.into(),
-                parameters.into(),
+            None => println!(
+                "  {}: {} bytes{}",
+                inspect::render_plain(&image.component),
+                image.content.len(),
+                slot
             ),
-            Command::DirectiveFetch(policy) => (SuitConstant::DirectiveFetch.into(), policy.into()),
-            Command::DirectiveCopy(policy) => (SuitConstant::DirectiveCopy.into(), policy.into()),
-            Command::DirectiveSwap(policy) => (SuitConstant::DirectiveSwap.into(), policy.into()),
-            Command::DirectiveRun(policy) => (SuitConstant::DirectiveRun.into(), policy.into()),
         }
     }
+
+    if let Some(file) = &args.memory_image {
+        match device.memory_image(args.fill) {
+            Some((address, memory)) => {
+                write_file(file, &memory);
+                println!("memory image from 0x{:08x} written to {:?}", address, file);
+            }
+            None => {
+                eprintln!("warning: nothing is written to memory, so no memory image is written")
+            }
+        }
+    }
+
+    if has_failed {
+        std::process::exit(1);
+    }
 }
 
-impl From<Vec<Command>> for Cbor {
-    fn from(sequence: Vec<Command>) -> Cbor {
-        Cbor::Array(sequence.into_iter().fold(Vec::new(), |mut acc, x| {
-            let (key, value) = x.into_cbor_pair();
-            acc.push(key);
-            acc.push(value);
-            acc
-        }))
+fn generate(args: GenerateArgs) {
+    let encryptor = encryptor(args.encryption);
+
+    // The payloads of each slot are numbered on from those of the slots
+    // before it, so that their URIs differ.
+    let build_payloads = |files: &[InputFile], first_index: usize| {
+        let payloads = PayloadBuilder::new()
+            .files(files)
+            .fill_value(args.fill)
+            .allow_overwrites(args.allow_overwrites)
+            .compress(args.compress)
+            .first_index(first_index);
+
+        let payloads = match &args.payload_uri {
+            Some(template) => payloads.remote_uri(template.clone()),
+            None => payloads,
+        };
+
+        let payloads = match &encryptor {
+            Some(encryptor) => payloads.encrypt(encryptor.clone()),
+            None => payloads,
+        };
+
+        payloads.build().unwrap_or_else(|error| {
+            eprintln!("error: {}", error);
+            std::process::exit(1);
+        })
+    };
+
+    let (payloads, entry_point) = build_payloads(&args.payload, 0);
+
+    let mut slots = vec![payloads];
+
+    for file in &args.slot_payloads {
+        let first_index = slots.iter().map(Vec::len).sum();
+        let (payloads, _entry_point) = build_payloads(std::slice::from_ref(file), first_index);
+
+        if payloads.len() != slots[0].len() {
+            eprintln!(
+                "error: the slot payload {:?} has {} payloads, but the payload list has {}",
+                file.path,
+                payloads.len(),
+                slots[0].len()
+            );
+            std::process::exit(1);
+        }
+
+        slots.push(payloads);
+    }
+
+    let integrated_payloads = slots.concat();
+
+    if let Some(slot_count) = args.slots {
+        slots = vec![slots[0].clone(); slot_count as usize];
+    }
+
+    let payloads = &slots[0];
+
+    let manifest = match (slots.len() > 1, args.staging_address) {
+        (true, _) => ManifestBuilder::for_slotted_payloads(&slots, args.digest_algorithm),
+        (false, Some(address)) => ManifestBuilder::for_staged_payloads(
+            payloads,
+            ComponentIdentifier(address),
+            args.install_method,
+            args.digest_algorithm,
+        ),
+        (false, None) => match args.payload_uri {
+            Some(_) => ManifestBuilder::for_detached_payloads(payloads, args.digest_algorithm),
+            None => ManifestBuilder::for_payloads(payloads, args.digest_algorithm),
+        },
+    };
+
+    let mut manifest = manifest.sequence_number(args.sequence_number);
+
+    match (args.entry_point_run_args, entry_point) {
+        (false, _) => (),
+        (true, Some(address)) => {
+            let component_index = payloads
+                .iter()
+                .position(|payload| payload.contains(address))
+                .unwrap_or_else(|| {
+                    eprintln!(
+                        "error: the entry point `{:#x}` is not within any of the payloads",
+                        address
+                    );
+                    std::process::exit(1);
+                });
+
+            let run = SequenceBuilder::new()
+                .set_component_index(component_index)
+                .override_parameters(vec![Parameter::RunArgs(address.to_be_bytes().to_vec())])
+                .command(Command::DirectiveRun(ReportingPolicy::none()));
+
+            manifest = manifest.run(run.build());
+        }
+        (true, None) => {
+            eprintln!("error: none of the payload files specify an entry point");
+            std::process::exit(1);
+        }
+    };
+
+    let identifiers = args.identifiers;
+
+    let vendor_identifier = identifiers.vendor_id.or_else(|| {
+        identifiers
+            .vendor_domain
+            .as_deref()
+            .map(Uuid::vendor_identifier)
+    });
+
+    if let Some(uuid) = vendor_identifier {
+        manifest = manifest.vendor_identifier(uuid);
     }
+
+    let class_identifier = match (&identifiers.model_name, &vendor_identifier) {
+        (Some(model_name), Some(vendor)) => Some(Uuid::class_identifier(vendor, model_name)),
+        (Some(_), None) => {
+            eprintln!("error: a model name needs either a vendor domain or a vendor identifier");
+            std::process::exit(1);
+        }
+        (None, _) => identifiers.class_id,
+    };
+
+    if let Some(uuid) = class_identifier {
+        manifest = manifest.class_identifier(uuid);
+    }
+
+    let devices = device_identifiers(Vec::new(), args.devices);
+    let dependencies = dependencies(args.dependencies, args.digest_algorithm);
+
+    write_envelopes(
+        manifest,
+        integrated_payloads,
+        dependencies,
+        args.digest_algorithm,
+        devices,
+        &args.authentication,
+        &args.output,
+    );
 }
 
-#[derive(Debug)]
-enum Parameter {
-    // ClassIdentifier(RFC4122_UUID),
-    ImageDigest(Digest),
-    ImageSize(usize),
-    ComponentSlot(usize),
-    Uri(String),
-    SourceComponent(usize),
-    RunArgs(Vec<u8>),
-    // DeviceIdentifier(RFC4122_UUID),
-    StrictOrder(bool),
-    SoftFailure(bool),
-}
-
-impl Parameter {
-    fn into_cbor_pair(self) -> (Cbor, Cbor) {
-        match self {
-            Parameter::ImageDigest(digest) => {
-                (SuitConstant::ParameterImageDigest.into(), digest.into())
-            }
-            Parameter::ImageSize(size) => (
-                SuitConstant::ParameterImageSize.into(),
-                (size as u64).into(),
-            ),
-            Parameter::ComponentSlot(slot) => (
-                SuitConstant::ParameterComponentSlot.into(),
-                (slot as u64).into(),
-            ),
-            Parameter::Uri(uri) => (SuitConstant::ParameterUri.into(), uri.into()),
-            Parameter::SourceComponent(source) => (
-                SuitConstant::ParameterSourceComponent.into(),
-                (source as u64).into(),
-            ),
-            Parameter::RunArgs(arguments) => {
-                (SuitConstant::ParameterRunArgs.into(), arguments.into())
-            }
-            Parameter::StrictOrder(flag) => {
-                (SuitConstant::ParameterStrictOrder.into(), flag.into())
-            }
-            Parameter::SoftFailure(flag) => {
-                (SuitConstant::ParameterSoftFailure.into(), flag.into())
+fn build(args: BuildArgs) {
+    let description = Description::from_file(&args.description).unwrap_or_else(|error| {
+        eprintln!("error: {:?}: {}", args.description, error);
+        std::process::exit(1);
+    });
+
+    let encryptor = encryptor(args.encryption);
+
+    let (manifest, payloads) = description
+        .build(encryptor.as_ref())
+        .unwrap_or_else(|error| {
+            eprintln!("error: {:?}: {}", args.description, error);
+            std::process::exit(1);
+        });
+
+    let devices = device_identifiers(description.device_identifiers, args.devices);
+
+    let mut files = description.dependencies;
+    files.extend(args.dependencies);
+    let dependencies = dependencies(files, description.digest_algorithm);
+
+    write_envelopes(
+        manifest,
+        payloads,
+        dependencies,
+        description.digest_algorithm,
+        devices,
+        &args.authentication,
+        &args.output,
+    );
+}
+
+/// Collect the devices to make an envelope for from the command line,
+/// following those in `devices`.
+fn device_identifiers(mut devices: Vec<Uuid>, args: DeviceArgs) -> Vec<Uuid> {
+    devices.extend(args.device_ids);
+
+    if let Some(file) = &args.device_list {
+        let content = std::fs::read_to_string(file).unwrap_or_else(|_| {
+            eprintln!("error: could not read file `{:?}`", file);
+            std::process::exit(1);
+        });
+
+        for (line_index, line) in content.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
             }
+
+            devices.push(line.parse().unwrap_or_else(|error| {
+                eprintln!("error: {:?}:{}: {}", file, line_index + 1, error);
+                std::process::exit(1);
+            }));
         }
     }
+
+    devices
 }
 
-impl From<Vec<Parameter>> for Cbor {
-    fn from(parameters: Vec<Parameter>) -> Cbor {
-        Cbor::Map(
-            parameters
-                .into_iter()
-                .map(|parameter| parameter.into_cbor_pair())
-                .collect(),
-        )
+/// Create the encryptor for payloads, if any keys to distribute the
+/// content encryption key with are given.
+fn encryptor(args: EncryptionArgs) -> Option<Encryptor> {
+    let keys: Vec<RecipientKey> = args
+        .encryption_keys
+        .into_iter()
+        .chain(args.recipient_keys)
+        .collect();
+
+    if keys.is_empty() {
+        return None;
     }
+
+    Some(keys.into_iter().fold(
+        Encryptor::new(args.encryption_algorithm),
+        |encryptor, key| encryptor.recipient(key),
+    ))
 }
 
-#[derive(Debug)]
-struct ReportingPolicy {
-    record_success: bool,
-    record_failure: bool,
-    sysinfo_success: bool,
-    sysinfo_failure: bool,
+/// Read the envelopes of the dependencies from `files`, numbered in order.
+fn dependencies(files: Vec<PathBuf>, digest_algorithm: DigestAlgorithm) -> Vec<Dependency> {
+    files
+        .iter()
+        .enumerate()
+        .map(|(index, file)| {
+            Dependency::from_envelope_file(file, index, digest_algorithm).unwrap_or_else(|error| {
+                eprintln!("error: {}", error);
+                std::process::exit(1);
+            })
+        })
+        .collect()
 }
 
-impl ReportingPolicy {
-    fn all() -> Self {
-        Self {
-            record_success: true,
-            record_failure: true,
-            sysinfo_success: true,
-            sysinfo_failure: true,
-        }
+/// Write an envelope for the manifest; one for each of the `devices` if
+/// any are given, or a single one for all devices otherwise.
+fn write_envelopes(
+    manifest: ManifestBuilder,
+    payloads: Vec<Payload>,
+    dependencies: Vec<Dependency>,
+    digest_algorithm: DigestAlgorithm,
+    devices: Vec<Uuid>,
+    authentication: &AuthenticationArgs,
+    args: &OutputArgs,
+) {
+    let manifest = match &args.sever {
+        Some(members) => manifest.sever(members.clone()),
+        None => manifest,
+    };
+
+    let manifest = dependencies.iter().fold(manifest, |manifest, dependency| {
+        manifest.dependency(dependency)
+    });
+
+    let (detached, integrated): (Vec<Payload>, Vec<Payload>) =
+        payloads.into_iter().partition(Payload::is_detached);
+
+    write_detached_payloads(&detached, args);
+
+    let envelope = |manifest: Manifest| {
+        let envelope = EnvelopeBuilder::new(manifest)
+            .digest_algorithm(digest_algorithm)
+            .integrate(integrated.clone())
+            .integrate_dependencies(dependencies.clone())
+            .strip_severed(args.strip_severed);
+
+        authenticate(envelope, authentication).build()
+    };
+
+    if devices.len() <= 1 {
+        let manifest = match devices.first() {
+            Some(device) => manifest.device_identifier(*device),
+            None => manifest,
+        };
+
+        return write_envelope(envelope(manifest.build()), args, args.output.as_deref());
     }
 
-    fn none() -> Self {
-        Self {
-            record_success: false,
-            record_failure: false,
-            sysinfo_success: false,
-            sysinfo_failure: false,
+    let template = match &args.output {
+        Some(file) if file.to_string_lossy().contains("{device}") => file.to_string_lossy(),
+        _ => {
+            eprintln!(
+                "error: the output file must contain `{{device}}` \
+                    to make an envelope for each of several devices"
+            );
+            std::process::exit(1);
         }
+    };
+
+    for device in devices {
+        let file = PathBuf::from(template.replace("{device}", &device.to_string()));
+
+        let manifest = manifest.clone().device_identifier(device).build();
+
+        write_envelope(envelope(manifest), args, Some(&file));
     }
 }
 
-impl From<ReportingPolicy> for Cbor {
-    fn from(policy: ReportingPolicy) -> Cbor {
-        Cbor::Uint(
-            (policy.record_success as u64)
-                | ((policy.record_failure as u64) << 1)
-                | ((policy.sysinfo_success as u64) << 2)
-                | ((policy.sysinfo_failure as u64) << 3),
-        )
+/// Write each of the detached `payloads` to its own file, for it to be
+/// served from its URI.
+fn write_detached_payloads(payloads: &[Payload], args: &OutputArgs) {
+    let directory = match (&args.payload_dir, &args.output) {
+        (Some(directory), _) => directory.clone(),
+        (None, Some(file)) => file.parent().map(Path::to_path_buf).unwrap_or_default(),
+        (None, None) => PathBuf::new(),
+    };
+
+    for payload in payloads {
+        let name = payload.uri.rsplit('/').next().unwrap_or_default();
+
+        if name.is_empty() {
+            eprintln!("error: the payload URI `{}` has no file name", payload.uri);
+            std::process::exit(1);
+        }
+
+        let file = directory.join(name);
+
+        write_file(&file, payload.delivered_bytes());
     }
 }
 
-#[derive(Debug)]
-enum IndexArgument {
-    Single(usize),
-    All,
-    List(Vec<usize>),
+fn authenticate(envelope: EnvelopeBuilder, authentication: &AuthenticationArgs) -> EnvelopeBuilder {
+    match &authentication.mac_key {
+        Some(key) => envelope.mac_with(key.clone().with_algorithm(authentication.mac_algorithm)),
+        None => authentication
+            .keys
+            .iter()
+            .cloned()
+            .fold(envelope, |envelope, key| envelope.sign_with(key)),
+    }
 }
 
-impl From<IndexArgument> for Cbor {
-    fn from(index: IndexArgument) -> Cbor {
-        match index {
-            IndexArgument::Single(index) => Cbor::Uint(index as u64),
-            IndexArgument::All => Cbor::True,
-            IndexArgument::List(indices) => Cbor::Array(
-                indices
-                    .into_iter()
-                    .map(|index| Cbor::Uint(index as u64))
-                    .collect(),
-            ),
-        }
+/// Write the envelope in the requested format, to `file` or to
+/// standard output.
+fn write_envelope(envelope: Envelope, args: &OutputArgs, file: Option<&Path>) {
+    let cbor = Cbor::from(envelope);
+
+    if args.verbose {
+        eprintln!("{:#?}", cbor);
     }
+
+    let encoded = output::encode(&cbor.serialize(), args.format);
+
+    match file {
+        Some(file) => write_file(file, &encoded),
+        None => std::io::stdout().write_all(&encoded).unwrap_or_else(|_| {
+            eprintln!("error: could not write to standard output");
+            std::process::exit(1);
+        }),
+    }
+}
+
+fn read_file(file: &Path) -> Vec<u8> {
+    std::fs::read(file).unwrap_or_else(|_| {
+        eprintln!("error: could not read file `{:?}`", file);
+        std::process::exit(1);
+    })
+}
+
+fn write_file(file: &Path, content: &[u8]) {
+    std::fs::write(file, content).unwrap_or_else(|_| {
+        eprintln!("error: could not write file `{:?}`", file);
+        std::process::exit(1);
+    })
 }
@@ -0,0 +1,1395 @@
+//! The SUIT manifest, along with the command sequences it contains.
+
+use clap::ValueEnum;
+
+use crate::cbor::Cbor;
+use crate::dependency::Dependency;
+use crate::digest::{Digest, DigestAlgorithm};
+use crate::payload::Payload;
+use crate::suit_constant::SuitConstant;
+use crate::uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    pub sequence_number: u64,
+    pub reference_uri: Option<String>,
+
+    pub common: Common,
+
+    pub validate: Option<Vec<Command>>,
+    pub load: Option<Vec<Command>>,
+    pub run: Option<Vec<Command>>,
+
+    pub payload_fetch: Option<Vec<Command>>,
+    pub install: Option<Vec<Command>>,
+    pub text: Option<Text>,
+
+    /// Members that are severed from the manifest, so that only their
+    /// digest is included in the manifest itself.
+    pub severed: Vec<SeverableMember>,
+}
+
+/// Members of the manifest that may be severed from it, and placed in
+/// the envelope instead.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum SeverableMember {
+    PayloadFetch,
+    Install,
+    Text,
+}
+
+impl From<SeverableMember> for SuitConstant {
+    fn from(member: SeverableMember) -> Self {
+        match member {
+            SeverableMember::PayloadFetch => SuitConstant::PayloadFetch,
+            SeverableMember::Install => SuitConstant::Install,
+            SeverableMember::Text => SuitConstant::Text,
+        }
+    }
+}
+
+/// How an image is moved from the component it was staged in, into the
+/// component it is executed from.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum InstallMethod {
+    /// Copy the image, leaving the staged image in place.
+    Copy,
+
+    /// Exchange the images, so that the previous image is kept in the
+    /// staging component to revert to.
+    Swap,
+}
+
+impl Manifest {
+    /// Encode the manifest, along with the members that are severed from
+    /// it as entries for the envelope. Severed members are replaced in the
+    /// manifest by their digest, made with `digest_algorithm`.
+    pub fn encode(self, digest_algorithm: DigestAlgorithm) -> (Cbor, Vec<(Cbor, Cbor)>) {
+        let manifest = self;
+
+        let common = Cbor::from(manifest.common);
+
+        let head = vec![
+            (SuitConstant::ManifestVersion.into(), 1.into()),
+            (
+                SuitConstant::ManifestSequenceNumber.into(),
+                (manifest.sequence_number).into(),
+            ),
+            (SuitConstant::Common.into(), common),
+        ];
+
+        let reference_uri = match manifest.reference_uri {
+            Some(uri) => vec![(SuitConstant::ReferenceUri.into(), uri.into())],
+            None => Vec::new(),
+        };
+
+        let mut severed = Vec::new();
+
+        // Severable members are bstr-wrapped, and the digest of a severed
+        // member is computed over the exact same bytes as those placed in
+        // the envelope.
+        let mut severable = |member: SeverableMember, content: Option<Cbor>| {
+            let content = content?.serialize();
+
+            match manifest.severed.contains(&member) {
+                true => {
+                    let digest = digest_algorithm.apply(&content);
+                    severed.push((SuitConstant::from(member).into(), content.into()));
+                    Some(digest.into())
+                }
+                false => Some(content.into()),
+            }
+        };
+
+        let payload_fetch = severable(
+            SeverableMember::PayloadFetch,
+            manifest.payload_fetch.map(Cbor::from),
+        );
+        let install = severable(SeverableMember::Install, manifest.install.map(Cbor::from));
+        let text = severable(SeverableMember::Text, manifest.text.map(Cbor::from));
+
+        let command_sequences = [
+            (SuitConstant::PayloadFetch, payload_fetch),
+            (SuitConstant::Install, install),
+            (SuitConstant::Text, text),
+            (SuitConstant::Validate, manifest.validate.map(Cbor::from)),
+            (SuitConstant::Load, manifest.load.map(Cbor::from)),
+            (SuitConstant::Run, manifest.run.map(Cbor::from)),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key.into(), value?)));
+
+        let manifest = Cbor::Map(
+            head.into_iter()
+                .chain(reference_uri)
+                .chain(command_sequences)
+                .collect(),
+        );
+
+        (manifest, severed)
+    }
+}
+
+/// Builder for a [`Manifest`]. Only the components are required; all
+/// command sequences are left out unless they are given.
+#[derive(Debug, Clone)]
+pub struct ManifestBuilder {
+    manifest: Manifest,
+
+    vendor_identifier: Option<Uuid>,
+    class_identifier: Option<Uuid>,
+    device_identifier: Option<Uuid>,
+
+    /// URIs of the integrated envelopes of the dependencies.
+    dependency_uris: Vec<String>,
+}
+
+impl ManifestBuilder {
+    pub fn new(components: Vec<ComponentIdentifier>) -> Self {
+        Self {
+            manifest: Manifest {
+                sequence_number: 0,
+                reference_uri: None,
+
+                common: Common {
+                    dependencies: Vec::new(),
+                    components,
+                    common_sequence: None,
+                },
+
+                validate: None,
+                load: None,
+                run: None,
+
+                payload_fetch: None,
+                install: None,
+                text: None,
+
+                severed: vec![SeverableMember::Text],
+            },
+
+            vendor_identifier: None,
+            class_identifier: None,
+            device_identifier: None,
+
+            dependency_uris: Vec::new(),
+        }
+    }
+
+    /// Create a manifest with a component for each of the `payloads`,
+    /// where each component is installed from its integrated payload, is
+    /// validated against the digest and size of its payload, and where
+    /// the first component is run.
+    pub fn for_payloads(payloads: &[Payload], digest_algorithm: DigestAlgorithm) -> Self {
+        let components = payloads
+            .iter()
+            .map(|payload| ComponentIdentifier(payload.start_address))
+            .collect();
+
+        let mut install = SequenceBuilder::new();
+        let mut validate = SequenceBuilder::new();
+
+        for (component_index, payload) in payloads.iter().enumerate() {
+            install = install
+                .set_component_index(component_index)
+                .override_parameters(Parameter::for_fetching(payload))
+                .command(Command::DirectiveFetch(ReportingPolicy::all()));
+
+            validate = validate
+                .set_component_index(component_index)
+                .override_parameters(vec![
+                    Parameter::ImageDigest(digest_algorithm.apply(&payload.bytes)),
+                    Parameter::ImageSize(payload.size),
+                ])
+                .command(Command::ConditionImageMatch(ReportingPolicy::all()));
+        }
+
+        let run = SequenceBuilder::new()
+            .set_component_index(0)
+            .command(Command::DirectiveRun(ReportingPolicy::none()));
+
+        let builder = Self::new(components).run(run.build());
+
+        match payloads.is_empty() {
+            true => builder,
+            false => builder.install(install.build()).validate(validate.build()),
+        }
+    }
+
+    /// Create a manifest with a component for each of the detached
+    /// `payloads`, where each component is fetched from the URI of its
+    /// payload and checked in the payload fetch sequence, is validated
+    /// against the digest and size of its payload, and where the first
+    /// component is run.
+    pub fn for_detached_payloads(payloads: &[Payload], digest_algorithm: DigestAlgorithm) -> Self {
+        let components = payloads
+            .iter()
+            .map(|payload| ComponentIdentifier(payload.start_address))
+            .collect();
+
+        let mut payload_fetch = SequenceBuilder::new();
+        let mut validate = SequenceBuilder::new();
+
+        for (component_index, payload) in payloads.iter().enumerate() {
+            let image = vec![
+                Parameter::ImageDigest(digest_algorithm.apply(&payload.bytes)),
+                Parameter::ImageSize(payload.size),
+            ];
+
+            payload_fetch = payload_fetch
+                .set_component_index(component_index)
+                .override_parameters(Parameter::for_fetching(payload))
+                .command(Command::DirectiveFetch(ReportingPolicy::all()))
+                .override_parameters(image.clone())
+                .command(Command::ConditionImageMatch(ReportingPolicy::all()));
+
+            validate = validate
+                .set_component_index(component_index)
+                .override_parameters(image)
+                .command(Command::ConditionImageMatch(ReportingPolicy::all()));
+        }
+
+        let run = SequenceBuilder::new()
+            .set_component_index(0)
+            .command(Command::DirectiveRun(ReportingPolicy::none()));
+
+        let builder = Self::new(components).run(run.build());
+
+        match payloads.is_empty() {
+            true => builder,
+            false => builder
+                .payload_fetch(payload_fetch.build())
+                .validate(validate.build()),
+        }
+    }
+
+    /// Create a manifest with a component for each payload of the first
+    /// of the `slots`, where each of the `slots` holds the payloads of its
+    /// slot in the same order; e.g. images linked for either bank of a
+    /// dual-bank flash. Each component is installed into whichever of its
+    /// slots the device accepts, typically the inactive bank, and is
+    /// validated against the payload of the slot the device accepts. The
+    /// first component is run.
+    pub fn for_slotted_payloads(slots: &[Vec<Payload>], digest_algorithm: DigestAlgorithm) -> Self {
+        let payloads = slots.first().map(Vec::as_slice).unwrap_or_default();
+
+        let components = payloads
+            .iter()
+            .map(|payload| ComponentIdentifier(payload.start_address))
+            .collect();
+
+        let mut install = SequenceBuilder::new();
+        let mut validate = SequenceBuilder::new();
+
+        for component_index in 0..payloads.len() {
+            let slots: Vec<&Payload> = slots
+                .iter()
+                .map(|payloads| &payloads[component_index])
+                .collect();
+
+            install = install
+                .set_component_index(component_index)
+                .install_to_slot(&slots, digest_algorithm);
+
+            validate = validate
+                .set_component_index(component_index)
+                .match_slot(&slots, digest_algorithm);
+        }
+
+        let run = SequenceBuilder::new()
+            .set_component_index(0)
+            .command(Command::DirectiveRun(ReportingPolicy::none()));
+
+        let builder = Self::new(components).run(run.build());
+
+        match payloads.is_empty() {
+            true => builder,
+            false => builder.install(install.build()).validate(validate.build()),
+        }
+    }
+
+    /// Create a manifest with a component for each of the `payloads`,
+    /// along with a last `staging` component. Each payload is fetched into
+    /// the staging component and checked, after which it is installed into
+    /// its own component with `method`. Each component is validated
+    /// against the digest and size of its payload, and the first component
+    /// is run.
+    pub fn for_staged_payloads(
+        payloads: &[Payload],
+        staging: ComponentIdentifier,
+        method: InstallMethod,
+        digest_algorithm: DigestAlgorithm,
+    ) -> Self {
+        let components = payloads
+            .iter()
+            .map(|payload| ComponentIdentifier(payload.start_address))
+            .chain(std::iter::once(staging))
+            .collect();
+
+        let staging_index = payloads.len();
+
+        let mut install = SequenceBuilder::new();
+        let mut validate = SequenceBuilder::new();
+
+        for (component_index, payload) in payloads.iter().enumerate() {
+            install = install.install_staged(
+                component_index,
+                staging_index,
+                payload,
+                method,
+                digest_algorithm,
+            );
+
+            validate = validate
+                .set_component_index(component_index)
+                .override_parameters(vec![
+                    Parameter::ImageDigest(digest_algorithm.apply(&payload.bytes)),
+                    Parameter::ImageSize(payload.size),
+                ])
+                .command(Command::ConditionImageMatch(ReportingPolicy::all()));
+        }
+
+        let run = SequenceBuilder::new()
+            .set_component_index(0)
+            .command(Command::DirectiveRun(ReportingPolicy::none()));
+
+        let builder = Self::new(components).run(run.build());
+
+        match payloads.is_empty() {
+            true => builder,
+            false => builder.install(install.build()).validate(validate.build()),
+        }
+    }
+
+    pub fn sequence_number(mut self, sequence_number: u64) -> Self {
+        self.manifest.sequence_number = sequence_number;
+        self
+    }
+
+    pub fn reference_uri(mut self, uri: String) -> Self {
+        self.manifest.reference_uri = Some(uri);
+        self
+    }
+
+    pub fn common_sequence(mut self, sequence: Vec<Command>) -> Self {
+        self.manifest.common.common_sequence = Some(sequence);
+        self
+    }
+
+    pub fn validate(mut self, sequence: Vec<Command>) -> Self {
+        self.manifest.validate = Some(sequence);
+        self
+    }
+
+    pub fn load(mut self, sequence: Vec<Command>) -> Self {
+        self.manifest.load = Some(sequence);
+        self
+    }
+
+    pub fn run(mut self, sequence: Vec<Command>) -> Self {
+        self.manifest.run = Some(sequence);
+        self
+    }
+
+    pub fn payload_fetch(mut self, sequence: Vec<Command>) -> Self {
+        self.manifest.payload_fetch = Some(sequence);
+        self
+    }
+
+    pub fn install(mut self, sequence: Vec<Command>) -> Self {
+        self.manifest.install = Some(sequence);
+        self
+    }
+
+    pub fn text(mut self, text: Text) -> Self {
+        self.manifest.text = Some(text);
+        self
+    }
+
+    /// Members to sever from the manifest; only the text by default.
+    pub fn sever(mut self, members: Vec<SeverableMember>) -> Self {
+        self.manifest.severed = members;
+        self
+    }
+
+    /// Only allow the manifest to be installed on devices of the vendor
+    /// identified by `uuid`.
+    pub fn vendor_identifier(mut self, uuid: Uuid) -> Self {
+        self.vendor_identifier = Some(uuid);
+        self
+    }
+
+    /// Only allow the manifest to be installed on devices of the class
+    /// identified by `uuid`.
+    pub fn class_identifier(mut self, uuid: Uuid) -> Self {
+        self.class_identifier = Some(uuid);
+        self
+    }
+
+    /// Only allow the manifest to be installed on the single device
+    /// identified by `uuid`.
+    pub fn device_identifier(mut self, uuid: Uuid) -> Self {
+        self.device_identifier = Some(uuid);
+        self
+    }
+
+    /// Depend on the manifest of `dependency`, whose envelope is fetched
+    /// from its URI during installation. Each dependency is processed in
+    /// every command sequence, before the components of this manifest.
+    pub fn dependency(mut self, dependency: &Dependency) -> Self {
+        self.manifest
+            .common
+            .dependencies
+            .push(dependency.digest.clone());
+        self.dependency_uris.push(dependency.uri.clone());
+        self
+    }
+
+    /// Create the manifest. Any identifiers that the device must match
+    /// are set and checked at the start of the common sequence.
+    pub fn build(mut self) -> Manifest {
+        if !self.dependency_uris.is_empty() {
+            let mut install = SequenceBuilder::new();
+            let mut process = SequenceBuilder::new();
+
+            for (index, uri) in self.dependency_uris.iter().enumerate() {
+                install = install
+                    .set_dependency_index(index)
+                    .override_parameters(vec![Parameter::Uri(uri.clone())])
+                    .command(Command::DirectiveFetch(ReportingPolicy::all()))
+                    .command(Command::DirectiveProcessDependency(ReportingPolicy::all()));
+
+                process = process
+                    .set_dependency_index(index)
+                    .command(Command::DirectiveProcessDependency(ReportingPolicy::all()));
+            }
+
+            let process = process.build();
+
+            let manifest = &mut self.manifest;
+
+            for (sequence, dependencies) in [
+                (&mut manifest.install, install.build()),
+                (&mut manifest.validate, process.clone()),
+                (&mut manifest.load, process.clone()),
+                (&mut manifest.run, process),
+            ] {
+                let commands = sequence.take().into_iter().flatten();
+                *sequence = Some(dependencies.into_iter().chain(commands).collect());
+            }
+        }
+
+        let identifiers = [
+            (
+                self.vendor_identifier.map(Parameter::VendorIdentifier),
+                Command::ConditionVendorIdentifier(ReportingPolicy::all()),
+            ),
+            (
+                self.class_identifier.map(Parameter::ClassIdentifier),
+                Command::ConditionClassIdentifier(ReportingPolicy::all()),
+            ),
+            (
+                self.device_identifier.map(Parameter::DeviceIdentifier),
+                Command::ConditionDeviceIdentifier(ReportingPolicy::all()),
+            ),
+        ];
+
+        let (parameters, conditions): (Vec<Parameter>, Vec<Command>) = identifiers
+            .into_iter()
+            .filter_map(|(parameter, condition)| Some((parameter?, condition)))
+            .unzip();
+
+        if !parameters.is_empty() {
+            let mut sequence = SequenceBuilder::new().override_parameters(parameters);
+
+            for command in conditions.into_iter().chain(
+                self.manifest
+                    .common
+                    .common_sequence
+                    .take()
+                    .into_iter()
+                    .flatten(),
+            ) {
+                sequence = sequence.command(command);
+            }
+
+            self.manifest.common.common_sequence = Some(sequence.build());
+        }
+
+        self.manifest
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Common {
+    /// Digests of the manifests this manifest depends on.
+    pub dependencies: Vec<Digest>,
+    pub components: Vec<ComponentIdentifier>,
+    pub common_sequence: Option<Vec<Command>>,
+}
+
+impl From<Common> for Cbor {
+    fn from(common: Common) -> Cbor {
+        let identifiers = common
+            .components
+            .into_iter()
+            .map(|component| component.into())
+            .collect();
+        let components = (SuitConstant::Components.into(), Cbor::Array(identifiers));
+
+        let dependencies: Vec<Cbor> = common
+            .dependencies
+            .into_iter()
+            .map(|digest| Cbor::Map(vec![(SuitConstant::DependencyDigest.into(), digest.into())]))
+            .collect();
+
+        let dependencies = match dependencies.is_empty() {
+            true => None,
+            false => Some((SuitConstant::Dependencies.into(), Cbor::Array(dependencies))),
+        };
+
+        let common_sequence = common
+            .common_sequence
+            .map(|sequence| (SuitConstant::CommonSequence.into(), sequence.into()));
+
+        Cbor::Map(
+            dependencies
+                .into_iter()
+                .chain(std::iter::once(components))
+                .chain(common_sequence)
+                .collect(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ComponentIdentifier(pub u32);
+
+impl From<u32> for ComponentIdentifier {
+    fn from(address: u32) -> Self {
+        Self(address)
+    }
+}
+
+impl From<ComponentIdentifier> for Cbor {
+    fn from(component: ComponentIdentifier) -> Cbor {
+        Cbor::Array(vec![component.0.to_be_bytes().to_vec().into()])
+    }
+}
+
+/// Human readable descriptions of the manifest and its components.
+#[derive(Debug, Clone, Default)]
+pub struct Text {
+    pub manifest_description: Option<String>,
+    pub update_description: Option<String>,
+
+    pub components: Vec<(ComponentIdentifier, TextComponent)>,
+}
+
+impl From<Text> for Cbor {
+    fn from(text: Text) -> Cbor {
+        let fields = [
+            (
+                SuitConstant::TextManifestDescription,
+                text.manifest_description,
+            ),
+            (SuitConstant::TextUpdateDescription, text.update_description),
+        ]
+        .into_iter()
+        .filter_map(|(key, value)| Some((key.into(), value?.into())));
+
+        let components = text
+            .components
+            .into_iter()
+            .map(|(component, text)| (component.into(), text.into()));
+
+        Cbor::Map(fields.chain(components).collect())
+    }
+}
+
+/// Human readable descriptions of a single component.
+#[derive(Debug, Clone, Default)]
+pub struct TextComponent {
+    pub vendor_name: Option<String>,
+    pub model_name: Option<String>,
+    pub vendor_domain: Option<String>,
+    pub model_info: Option<String>,
+    pub component_description: Option<String>,
+    pub component_version: Option<String>,
+}
+
+impl TextComponent {
+    pub fn is_empty(&self) -> bool {
+        [
+            &self.vendor_name,
+            &self.model_name,
+            &self.vendor_domain,
+            &self.model_info,
+            &self.component_description,
+            &self.component_version,
+        ]
+        .iter()
+        .all(|field| field.is_none())
+    }
+}
+
+impl From<TextComponent> for Cbor {
+    fn from(text: TextComponent) -> Cbor {
+        Cbor::Map(
+            [
+                (SuitConstant::TextVendorName, text.vendor_name),
+                (SuitConstant::TextModelName, text.model_name),
+                (SuitConstant::TextVendorDomain, text.vendor_domain),
+                (SuitConstant::TextModelInfo, text.model_info),
+                (
+                    SuitConstant::TextComponentDescription,
+                    text.component_description,
+                ),
+                (SuitConstant::TextComponentVersion, text.component_version),
+            ]
+            .into_iter()
+            .filter_map(|(key, value)| Some((key.into(), value?.into())))
+            .collect(),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    ConditionVendorIdentifier(ReportingPolicy),
+    ConditionClassIdentifier(ReportingPolicy),
+    ConditionDeviceIdentifier(ReportingPolicy),
+    ConditionImageMatch(ReportingPolicy),
+    ConditionComponentSlot(ReportingPolicy),
+    ConditionAbort(ReportingPolicy),
+
+    DirectiveSetComponentIndex(IndexArgument),
+
+    /// Run a nested sequence, in which failing conditions only end the
+    /// nested sequence itself when soft failure is set.
+    DirectiveRunSequence(Vec<Command>),
+
+    /// Run nested sequences in order until one of them succeeds.
+    DirectiveTryEach(Vec<Vec<Command>>),
+
+    DirectiveSetDependencyIndex(IndexArgument),
+    DirectiveProcessDependency(ReportingPolicy),
+    DirectiveOverrideParameters(Vec<Parameter>),
+    DirectiveFetch(ReportingPolicy),
+    DirectiveCopy(ReportingPolicy),
+    DirectiveSwap(ReportingPolicy),
+    DirectiveRun(ReportingPolicy),
+}
+
+impl Command {
+    fn into_cbor_pair(self) -> (Cbor, Cbor) {
+        match self {
+            Command::ConditionVendorIdentifier(policy) => (
+                SuitConstant::ConditionVendorIdentifier.into(),
+                policy.into(),
+            ),
+            Command::ConditionClassIdentifier(policy) => {
+                (SuitConstant::ConditionClassIdentifier.into(), policy.into())
+            }
+            Command::ConditionDeviceIdentifier(policy) => (
+                SuitConstant::ConditionDeviceIdentifier.into(),
+                policy.into(),
+            ),
+            Command::ConditionImageMatch(policy) => {
+                (SuitConstant::ConditionImageMatch.into(), policy.into())
+            }
+            Command::ConditionComponentSlot(policy) => {
+                (SuitConstant::ConditionComponentSlot.into(), policy.into())
+            }
+            Command::ConditionAbort(policy) => (SuitConstant::ConditionAbort.into(), policy.into()),
+
+            Command::DirectiveSetComponentIndex(index) => (
+                SuitConstant::DirectiveSetComponentIndex.into(),
+                index.into(),
+            ),
+            Command::DirectiveRunSequence(sequence) => (
+                SuitConstant::DirectiveRunSequence.into(),
+                Cbor::from(sequence).serialize().into(),
+            ),
+            Command::DirectiveTryEach(sequences) => (
+                SuitConstant::DirectiveTryEach.into(),
+                Cbor::Array(
+                    sequences
+                        .into_iter()
+                        .map(|sequence| Cbor::from(sequence).serialize().into())
+                        .collect(),
+                ),
+            ),
+            Command::DirectiveOverrideParameters(parameters) => (
+                SuitConstant::DirectiveOverrideParameters.into(),
+                parameters.into(),
+            ),
+            Command::DirectiveFetch(policy) => (SuitConstant::DirectiveFetch.into(), policy.into()),
+            Command::DirectiveCopy(policy) => (SuitConstant::DirectiveCopy.into(), policy.into()),
+            Command::DirectiveSetDependencyIndex(index) => (
+                SuitConstant::DirectiveSetDependencyIndex.into(),
+                index.into(),
+            ),
+            Command::DirectiveProcessDependency(policy) => (
+                SuitConstant::DirectiveProcessDependency.into(),
+                policy.into(),
+            ),
+            Command::DirectiveSwap(policy) => (SuitConstant::DirectiveSwap.into(), policy.into()),
+            Command::DirectiveRun(policy) => (SuitConstant::DirectiveRun.into(), policy.into()),
+        }
+    }
+}
+
+/// Builder for a command sequence.
+#[derive(Debug, Default)]
+pub struct SequenceBuilder {
+    commands: Vec<Command>,
+}
+
+impl SequenceBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn command(mut self, command: Command) -> Self {
+        self.commands.push(command);
+        self
+    }
+
+    /// Make subsequent commands apply to the component at `index`.
+    pub fn set_component_index(self, index: usize) -> Self {
+        self.command(Command::DirectiveSetComponentIndex(IndexArgument::Single(
+            index,
+        )))
+    }
+
+    pub fn set_dependency_index(self, index: usize) -> Self {
+        self.command(Command::DirectiveSetDependencyIndex(IndexArgument::Single(
+            index,
+        )))
+    }
+
+    pub fn override_parameters(self, parameters: Vec<Parameter>) -> Self {
+        self.command(Command::DirectiveOverrideParameters(parameters))
+    }
+
+    /// Run each of the `sequences` in order, until one of them succeeds.
+    pub fn try_each(self, sequences: Vec<Vec<Command>>) -> Self {
+        self.command(Command::DirectiveTryEach(sequences))
+    }
+
+    pub fn run_sequence(self, sequence: Vec<Command>) -> Self {
+        self.command(Command::DirectiveRunSequence(sequence))
+    }
+
+    /// Install the current component into the first of its slots for
+    /// which the device accepts the component slot condition, where
+    /// `slots` holds the payload for each slot. The payload of that slot
+    /// is fetched into it, and the installed image is checked.
+    pub fn install_to_slot(self, slots: &[&Payload], digest_algorithm: DigestAlgorithm) -> Self {
+        self.try_each(slot_alternatives(slots, digest_algorithm, true))
+            .command(Command::DirectiveFetch(ReportingPolicy::all()))
+            .command(Command::ConditionImageMatch(ReportingPolicy::all()))
+    }
+
+    /// Check the image of the current component in the first of its slots
+    /// for which the device accepts the component slot condition, against
+    /// the payload of that slot in `slots`.
+    pub fn match_slot(self, slots: &[&Payload], digest_algorithm: DigestAlgorithm) -> Self {
+        self.try_each(slot_alternatives(slots, digest_algorithm, false))
+            .command(Command::ConditionImageMatch(ReportingPolicy::all()))
+    }
+
+    /// Fetch `payload` into the `staging` component and check it, then
+    /// install it into `component` by either copying or swapping it with
+    /// `method`, and check the installed image. Leaves `component` as the
+    /// current component.
+    pub fn install_staged(
+        self,
+        component: usize,
+        staging: usize,
+        payload: &Payload,
+        method: InstallMethod,
+        digest_algorithm: DigestAlgorithm,
+    ) -> Self {
+        let digest = Parameter::ImageDigest(digest_algorithm.apply(&payload.bytes));
+        let size = Parameter::ImageSize(payload.size);
+
+        let install = match method {
+            InstallMethod::Copy => Command::DirectiveCopy(ReportingPolicy::all()),
+            InstallMethod::Swap => Command::DirectiveSwap(ReportingPolicy::all()),
+        };
+
+        let mut parameters = Parameter::for_fetching(payload);
+        parameters.extend([digest.clone(), size.clone()]);
+
+        self.set_component_index(staging)
+            .override_parameters(parameters)
+            .command(Command::DirectiveFetch(ReportingPolicy::all()))
+            .command(Command::ConditionImageMatch(ReportingPolicy::all()))
+            .set_component_index(component)
+            .override_parameters(vec![Parameter::SourceComponent(staging), digest, size])
+            .command(install)
+            .command(Command::ConditionImageMatch(ReportingPolicy::all()))
+    }
+
+    pub fn build(self) -> Vec<Command> {
+        self.commands
+    }
+}
+
+/// An alternative for each of the `slots` of a component, which selects
+/// the slot if the device accepts it, and gives the digest and size of its
+/// payload, along with its URI if it is to be fetched.
+fn slot_alternatives(
+    slots: &[&Payload],
+    digest_algorithm: DigestAlgorithm,
+    is_fetched: bool,
+) -> Vec<Vec<Command>> {
+    slots
+        .iter()
+        .enumerate()
+        .map(|(slot, payload)| {
+            let mut parameters = match is_fetched {
+                true => Parameter::for_fetching(payload),
+                false => Vec::new(),
+            };
+            parameters.push(Parameter::ImageDigest(
+                digest_algorithm.apply(&payload.bytes),
+            ));
+            parameters.push(Parameter::ImageSize(payload.size));
+
+            SequenceBuilder::new()
+                .override_parameters(vec![Parameter::ComponentSlot(slot)])
+                .command(Command::ConditionComponentSlot(ReportingPolicy::none()))
+                .override_parameters(parameters)
+                .build()
+        })
+        .collect()
+}
+
+impl From<Vec<Command>> for Cbor {
+    fn from(sequence: Vec<Command>) -> Cbor {
+        Cbor::Array(sequence.into_iter().fold(Vec::new(), |mut acc, x| {
+            let (key, value) = x.into_cbor_pair();
+            acc.push(key);
+            acc.push(value);
+            acc
+        }))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Parameter {
+    VendorIdentifier(Uuid),
+    ClassIdentifier(Uuid),
+    ImageDigest(Digest),
+    ImageSize(usize),
+    ComponentSlot(usize),
+    Uri(String),
+    SourceComponent(usize),
+    RunArgs(Vec<u8>),
+    DeviceIdentifier(Uuid),
+    StrictOrder(bool),
+    SoftFailure(bool),
+
+    /// COSE structure describing how to decrypt the payload that is
+    /// fetched or copied.
+    EncryptionInfo(Cbor),
+}
+
+impl Parameter {
+    /// Parameters to fetch `payload` with; its URI, along with its
+    /// encryption info if it is encrypted.
+    pub fn for_fetching(payload: &Payload) -> Vec<Parameter> {
+        let uri = Parameter::Uri(payload.uri.clone());
+
+        match &payload.encryption {
+            Some(encryption) => vec![
+                uri,
+                Parameter::EncryptionInfo(encryption.encryption_info.clone()),
+            ],
+            None => vec![uri],
+        }
+    }
+
+    fn into_cbor_pair(self) -> (Cbor, Cbor) {
+        match self {
+            Parameter::VendorIdentifier(uuid) => {
+                (SuitConstant::ParameterVendorIdentifier.into(), uuid.into())
+            }
+            Parameter::ClassIdentifier(uuid) => {
+                (SuitConstant::ParameterClassIdentifier.into(), uuid.into())
+            }
+            Parameter::ImageDigest(digest) => {
+                (SuitConstant::ParameterImageDigest.into(), digest.into())
+            }
+            Parameter::ImageSize(size) => (
+                SuitConstant::ParameterImageSize.into(),
+                (size as u64).into(),
+            ),
+            Parameter::ComponentSlot(slot) => (
+                SuitConstant::ParameterComponentSlot.into(),
+                (slot as u64).into(),
+            ),
+            Parameter::Uri(uri) => (SuitConstant::ParameterUri.into(), uri.into()),
+            Parameter::SourceComponent(source) => (
+                SuitConstant::ParameterSourceComponent.into(),
+                (source as u64).into(),
+            ),
+            Parameter::RunArgs(arguments) => {
+                (SuitConstant::ParameterRunArgs.into(), arguments.into())
+            }
+            Parameter::DeviceIdentifier(uuid) => {
+                (SuitConstant::ParameterDeviceIdentifier.into(), uuid.into())
+            }
+            Parameter::StrictOrder(flag) => {
+                (SuitConstant::ParameterStrictOrder.into(), flag.into())
+            }
+            Parameter::SoftFailure(flag) => {
+                (SuitConstant::ParameterSoftFailure.into(), flag.into())
+            }
+            Parameter::EncryptionInfo(info) => (
+                SuitConstant::ParameterEncryptionInfo.into(),
+                info.serialize().into(),
+            ),
+        }
+    }
+}
+
+impl From<Vec<Parameter>> for Cbor {
+    fn from(parameters: Vec<Parameter>) -> Cbor {
+        Cbor::Map(
+            parameters
+                .into_iter()
+                .map(|parameter| parameter.into_cbor_pair())
+                .collect(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReportingPolicy {
+    pub record_success: bool,
+    pub record_failure: bool,
+    pub sysinfo_success: bool,
+    pub sysinfo_failure: bool,
+}
+
+impl ReportingPolicy {
+    pub fn all() -> Self {
+        Self {
+            record_success: true,
+            record_failure: true,
+            sysinfo_success: true,
+            sysinfo_failure: true,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            record_success: false,
+            record_failure: false,
+            sysinfo_success: false,
+            sysinfo_failure: false,
+        }
+    }
+}
+
+impl From<ReportingPolicy> for Cbor {
+    fn from(policy: ReportingPolicy) -> Cbor {
+        Cbor::Uint(
+            (policy.record_success as u64)
+                | ((policy.record_failure as u64) << 1)
+                | ((policy.sysinfo_success as u64) << 2)
+                | ((policy.sysinfo_failure as u64) << 3),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum IndexArgument {
+    Single(usize),
+    All,
+    List(Vec<usize>),
+}
+
+impl From<IndexArgument> for Cbor {
+    fn from(index: IndexArgument) -> Cbor {
+        match index {
+            IndexArgument::Single(index) => Cbor::Uint(index as u64),
+            IndexArgument::All => Cbor::True,
+            IndexArgument::List(indices) => Cbor::Array(
+                indices
+                    .into_iter()
+                    .map(|index| Cbor::Uint(index as u64))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(index: usize, start_address: u32, bytes: &[u8]) -> Payload {
+        Payload {
+            uri: format!("p:{}", index),
+            start_address,
+            size: bytes.len(),
+            memory_size: bytes.len(),
+            bytes: bytes.to_vec(),
+            encryption: None,
+        }
+    }
+
+    /// The command sequence made of `commands` with their arguments.
+    fn sequence(commands: Vec<(SuitConstant, Cbor)>) -> Cbor {
+        Cbor::Array(
+            commands
+                .into_iter()
+                .flat_map(|(command, argument)| [command.into(), argument])
+                .collect(),
+        )
+    }
+
+    fn parameters(parameters: Vec<(SuitConstant, Cbor)>) -> Cbor {
+        Cbor::Map(
+            parameters
+                .into_iter()
+                .map(|(key, value)| (key.into(), value))
+                .collect(),
+        )
+    }
+
+    /// The member `key` of an encoded manifest, unwrapped if it is a byte
+    /// string.
+    fn member(manifest: &Cbor, key: SuitConstant) -> Option<Cbor> {
+        match manifest {
+            Cbor::Map(entries) => entries
+                .iter()
+                .find(|(entry, _)| *entry == key.into())
+                .map(|(_, value)| value.decode_wrapped().unwrap_or_else(|| value.clone())),
+            _ => panic!("the manifest is not a map"),
+        }
+    }
+
+    fn all() -> Cbor {
+        ReportingPolicy::all().into()
+    }
+
+    /// The alternative of a try-each directive that selects `slot`, for
+    /// which `payload` is fetched if `uri` is set.
+    fn slot_alternative(slot: u64, payload: &Payload, is_fetched: bool) -> Cbor {
+        let mut image = match is_fetched {
+            true => vec![(SuitConstant::ParameterUri, payload.uri.clone().into())],
+            false => Vec::new(),
+        };
+        image.extend([
+            (
+                SuitConstant::ParameterImageDigest,
+                DigestAlgorithm::Sha256.apply(&payload.bytes).into(),
+            ),
+            (
+                SuitConstant::ParameterImageSize,
+                (payload.size as u64).into(),
+            ),
+        ]);
+
+        sequence(vec![
+            (
+                SuitConstant::DirectiveOverrideParameters,
+                parameters(vec![(SuitConstant::ParameterComponentSlot, slot.into())]),
+            ),
+            (
+                SuitConstant::ConditionComponentSlot,
+                ReportingPolicy::none().into(),
+            ),
+            (SuitConstant::DirectiveOverrideParameters, parameters(image)),
+        ])
+        .serialize()
+        .into()
+    }
+
+    #[test]
+    fn installs_to_the_accepted_slot() {
+        let bank_a = payload(0, 0x0001_0000, b"linked for bank a");
+        let bank_b = payload(1, 0x0008_0000, b"linked for bank b");
+
+        let install = SequenceBuilder::new()
+            .install_to_slot(&[&bank_a, &bank_b], DigestAlgorithm::Sha256)
+            .build();
+
+        assert_eq!(
+            Cbor::from(install),
+            sequence(vec![
+                (
+                    SuitConstant::DirectiveTryEach,
+                    Cbor::Array(vec![
+                        slot_alternative(0, &bank_a, true),
+                        slot_alternative(1, &bank_b, true),
+                    ])
+                ),
+                (SuitConstant::DirectiveFetch, all()),
+                (SuitConstant::ConditionImageMatch, all()),
+            ])
+        );
+    }
+
+    #[test]
+    fn installs_and_validates_slotted_payloads() {
+        let bank_a = vec![payload(0, 0x0001_0000, b"bank a")];
+        let bank_b = vec![payload(1, 0x0008_0000, b"bank b")];
+
+        let (manifest, _severed) = ManifestBuilder::for_slotted_payloads(
+            &[bank_a.clone(), bank_b.clone()],
+            DigestAlgorithm::Sha256,
+        )
+        .build()
+        .encode(DigestAlgorithm::Sha256);
+
+        let set_component = (SuitConstant::DirectiveSetComponentIndex, 0.into());
+
+        assert_eq!(
+            member(&manifest, SuitConstant::Install),
+            Some(sequence(vec![
+                set_component.clone(),
+                (
+                    SuitConstant::DirectiveTryEach,
+                    Cbor::Array(vec![
+                        slot_alternative(0, &bank_a[0], true),
+                        slot_alternative(1, &bank_b[0], true),
+                    ])
+                ),
+                (SuitConstant::DirectiveFetch, all()),
+                (SuitConstant::ConditionImageMatch, all()),
+            ]))
+        );
+
+        assert_eq!(
+            member(&manifest, SuitConstant::Validate),
+            Some(sequence(vec![
+                set_component,
+                (
+                    SuitConstant::DirectiveTryEach,
+                    Cbor::Array(vec![
+                        slot_alternative(0, &bank_a[0], false),
+                        slot_alternative(1, &bank_b[0], false),
+                    ])
+                ),
+                (SuitConstant::ConditionImageMatch, all()),
+            ]))
+        );
+
+        assert_eq!(member(&manifest, SuitConstant::Load), None);
+    }
+
+    #[test]
+    fn checks_the_device_identifier_in_the_common_sequence() {
+        let device: Uuid = "fa6b4a53-d5ad-5fdf-be9d-e663e4d41ffe".parse().unwrap();
+
+        let (manifest, _severed) = ManifestBuilder::new(vec![ComponentIdentifier(0x1000)])
+            .common_sequence(SequenceBuilder::new().set_component_index(0).build())
+            .device_identifier(device)
+            .build()
+            .encode(DigestAlgorithm::Sha256);
+
+        assert_eq!(
+            member(&manifest, SuitConstant::Common),
+            Some(Cbor::Map(vec![
+                (
+                    SuitConstant::Components.into(),
+                    Cbor::Array(vec![ComponentIdentifier(0x1000).into()])
+                ),
+                (
+                    SuitConstant::CommonSequence.into(),
+                    sequence(vec![
+                        (
+                            SuitConstant::DirectiveOverrideParameters,
+                            parameters(vec![(
+                                SuitConstant::ParameterDeviceIdentifier,
+                                device.into()
+                            )])
+                        ),
+                        (SuitConstant::ConditionDeviceIdentifier, all()),
+                        (SuitConstant::DirectiveSetComponentIndex, 0.into()),
+                    ])
+                ),
+            ]))
+        );
+    }
+
+    #[test]
+    fn severs_the_text_with_its_digest() {
+        let text = Text {
+            manifest_description: Some(String::from("Sensor node firmware")),
+            components: vec![(
+                ComponentIdentifier(0x1000),
+                TextComponent {
+                    vendor_name: Some(String::from("Example Inc.")),
+                    ..TextComponent::default()
+                },
+            )],
+            ..Text::default()
+        };
+
+        let (manifest, severed) = ManifestBuilder::new(vec![ComponentIdentifier(0x1000)])
+            .text(text)
+            .build()
+            .encode(DigestAlgorithm::Sha256);
+
+        let encoded = Cbor::Map(vec![
+            (
+                SuitConstant::TextManifestDescription.into(),
+                String::from("Sensor node firmware").into(),
+            ),
+            (
+                ComponentIdentifier(0x1000).into(),
+                Cbor::Map(vec![(
+                    SuitConstant::TextVendorName.into(),
+                    String::from("Example Inc.").into(),
+                )]),
+            ),
+        ])
+        .serialize();
+
+        assert_eq!(
+            member(&manifest, SuitConstant::Text),
+            Some(DigestAlgorithm::Sha256.apply(&encoded).into())
+        );
+        assert_eq!(severed, vec![(SuitConstant::Text.into(), encoded.into())]);
+    }
+
+    #[test]
+    fn fetches_detached_payloads_in_payload_fetch() {
+        let mut detached = payload(0, 0x1000, b"detached payload");
+        detached.uri = String::from("https://example.com/firmware/0.bin");
+
+        let (manifest, severed) =
+            ManifestBuilder::for_detached_payloads(&[detached.clone()], DigestAlgorithm::Sha256)
+                .build()
+                .encode(DigestAlgorithm::Sha256);
+
+        let image = parameters(vec![
+            (
+                SuitConstant::ParameterImageDigest,
+                DigestAlgorithm::Sha256.apply(&detached.bytes).into(),
+            ),
+            (
+                SuitConstant::ParameterImageSize,
+                (detached.size as u64).into(),
+            ),
+        ]);
+
+        assert_eq!(
+            member(&manifest, SuitConstant::PayloadFetch),
+            Some(sequence(vec![
+                (SuitConstant::DirectiveSetComponentIndex, 0.into()),
+                (
+                    SuitConstant::DirectiveOverrideParameters,
+                    parameters(vec![(SuitConstant::ParameterUri, detached.uri.into())])
+                ),
+                (SuitConstant::DirectiveFetch, all()),
+                (SuitConstant::DirectiveOverrideParameters, image.clone()),
+                (SuitConstant::ConditionImageMatch, all()),
+            ]))
+        );
+
+        // Nothing is left to install, but the image is still matched
+        // before it is run.
+        assert_eq!(member(&manifest, SuitConstant::Install), None);
+        assert_eq!(
+            member(&manifest, SuitConstant::Validate),
+            Some(sequence(vec![
+                (SuitConstant::DirectiveSetComponentIndex, 0.into()),
+                (SuitConstant::DirectiveOverrideParameters, image),
+                (SuitConstant::ConditionImageMatch, all()),
+            ]))
+        );
+
+        assert!(severed.is_empty());
+    }
+
+    #[test]
+    fn processes_dependencies_before_the_components() {
+        let dependency = Dependency {
+            uri: String::from("d:0"),
+            digest: DigestAlgorithm::Sha256.apply(b"dependency manifest"),
+            envelope: Vec::new(),
+        };
+
+        let component = SequenceBuilder::new()
+            .set_component_index(0)
+            .command(Command::DirectiveRun(ReportingPolicy::none()))
+            .build();
+
+        let (manifest, _severed) = ManifestBuilder::new(vec![ComponentIdentifier(0x1000)])
+            .install(component.clone())
+            .run(component.clone())
+            .dependency(&dependency)
+            .build()
+            .encode(DigestAlgorithm::Sha256);
+
+        assert_eq!(
+            member(&manifest, SuitConstant::Common),
+            Some(Cbor::Map(vec![
+                (
+                    SuitConstant::Dependencies.into(),
+                    Cbor::Array(vec![Cbor::Map(vec![(
+                        SuitConstant::DependencyDigest.into(),
+                        dependency.digest.clone().into()
+                    )])])
+                ),
+                (
+                    SuitConstant::Components.into(),
+                    Cbor::Array(vec![ComponentIdentifier(0x1000).into()])
+                ),
+            ]))
+        );
+
+        let set_dependency = (SuitConstant::DirectiveSetDependencyIndex, 0.into());
+        let process = (SuitConstant::DirectiveProcessDependency, all());
+        let component = vec![
+            (SuitConstant::DirectiveSetComponentIndex, 0.into()),
+            (SuitConstant::DirectiveRun, ReportingPolicy::none().into()),
+        ];
+
+        assert_eq!(
+            member(&manifest, SuitConstant::Install),
+            Some(sequence(
+                [
+                    set_dependency.clone(),
+                    (
+                        SuitConstant::DirectiveOverrideParameters,
+                        parameters(vec![(SuitConstant::ParameterUri, dependency.uri.into())])
+                    ),
+                    (SuitConstant::DirectiveFetch, all()),
+                    process.clone(),
+                ]
+                .into_iter()
+                .chain(component.clone())
+                .collect()
+            ))
+        );
+
+        // The dependency is processed in every sequence of the invoke
+        // procedure, also those that the manifest itself leaves out.
+        for key in [SuitConstant::Validate, SuitConstant::Load] {
+            assert_eq!(
+                member(&manifest, key),
+                Some(sequence(vec![set_dependency.clone(), process.clone()]))
+            );
+        }
+
+        assert_eq!(
+            member(&manifest, SuitConstant::Run),
+            Some(sequence(
+                [set_dependency, process]
+                    .into_iter()
+                    .chain(component)
+                    .collect()
+            ))
+        );
+    }
+}
@@ -0,0 +1,99 @@
+//! Encodings in which a serialized envelope can be written out.
+
+use clap::ValueEnum;
+
+/// Number of bytes on each line of a C array.
+const C_ARRAY_LINE_LENGTH: usize = 12;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    /// Raw CBOR bytes
+    Binary,
+    /// Hexadecimal text
+    Hex,
+    /// Base64 text
+    Base64,
+    /// C header defining a byte array
+    CArray,
+}
+
+/// Encode a serialized `envelope` in the given output `format`.
+pub fn encode(envelope: &[u8], format: OutputFormat) -> Vec<u8> {
+    match format {
+        OutputFormat::Binary => envelope.to_vec(),
+        OutputFormat::Hex => format!("{}\n", hex::encode(envelope)).into_bytes(),
+        OutputFormat::Base64 => {
+            format!("{}\n", openssl::base64::encode_block(envelope)).into_bytes()
+        }
+        OutputFormat::CArray => c_array(envelope).into_bytes(),
+    }
+}
+
+fn c_array(envelope: &[u8]) -> String {
+    let lines: Vec<String> = envelope
+        .chunks(C_ARRAY_LINE_LENGTH)
+        .map(|line| {
+            let bytes: Vec<String> = line.iter().map(|byte| format!("0x{:02x}", byte)).collect();
+            format!("    {},", bytes.join(", "))
+        })
+        .collect();
+
+    format!(
+        "#ifndef SUIT_ENVELOPE_H\n\
+         #define SUIT_ENVELOPE_H\n\
+         \n\
+         #include <stddef.h>\n\
+         #include <stdint.h>\n\
+         \n\
+         static const uint8_t suit_envelope[] = {{\n\
+         {}\n\
+         }};\n\
+         \n\
+         static const size_t suit_envelope_len = sizeof(suit_envelope);\n\
+         \n\
+         #endif /* SUIT_ENVELOPE_H */\n",
+        lines.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(envelope: &[u8], format: OutputFormat) -> String {
+        String::from_utf8(encode(envelope, format)).unwrap()
+    }
+
+    #[test]
+    fn encodes_text_formats() {
+        let envelope = [0xd8, 0x6b, 0xa2, 0x02, 0x58, 0xff];
+
+        assert_eq!(encode(&envelope, OutputFormat::Binary), envelope);
+        assert_eq!(encoded(&envelope, OutputFormat::Hex), "d86ba20258ff\n");
+        assert_eq!(encoded(&envelope, OutputFormat::Base64), "2GuiAlj/\n");
+        assert_eq!(encoded(&envelope[..5], OutputFormat::Base64), "2GuiAlg=\n");
+    }
+
+    #[test]
+    fn encodes_c_arrays() {
+        let envelope: Vec<u8> = (0..14).collect();
+
+        assert_eq!(
+            encoded(&envelope, OutputFormat::CArray),
+            "#ifndef SUIT_ENVELOPE_H\n\
+             #define SUIT_ENVELOPE_H\n\
+             \n\
+             #include <stddef.h>\n\
+             #include <stdint.h>\n\
+             \n\
+             static const uint8_t suit_envelope[] = {\n    \
+             0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b,\n    \
+             0x0c, 0x0d,\n\
+             };\n\
+             \n\
+             static const size_t suit_envelope_len = sizeof(suit_envelope);\n\
+             \n\
+             #endif /* SUIT_ENVELOPE_H */\n"
+        );
+    }
+}
@@ -1,17 +1,62 @@
 //! Representation for firmware upgrade payloads.
 
 use std::collections::hash_map::HashMap;
+use std::fmt;
 use std::path::PathBuf;
 
-mod compression;
+use crate::encryption::{EncryptedPayload, Encryptor};
+
+pub mod compression;
+mod elf;
+mod intel_hex;
+mod srec;
+
+pub use intel_hex::HexError;
 
 const PAYLOAD_SPLIT_PENALTY: u32 = 4;
 
+/// URI schemes that devices can fetch detached payloads with.
+const REMOTE_URI_SCHEMES: [&str; 3] = ["https", "coap", "coaps"];
+
+/// Firmware file formats that payloads can be read from.
+pub enum FileFormat {
+    IntelHex,
+    SRecord,
+    Elf,
+    Binary,
+}
+
+impl FileFormat {
+    /// Determine the file format from a file `extension`.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "hex" => Some(FileFormat::IntelHex),
+            "s19" | "s28" | "s37" | "srec" | "mot" => Some(FileFormat::SRecord),
+            "elf" | "axf" => Some(FileFormat::Elf),
+            "bin" => Some(FileFormat::Binary),
+            _ => None,
+        }
+    }
+}
+
+/// A firmware file to build payloads from.
+#[derive(Debug, Clone)]
+pub struct InputFile {
+    /// Path to the file.
+    pub path: PathBuf,
+
+    /// Address at which the content of the file is placed. Only used for
+    /// raw binary files, as all other formats carry their own addresses.
+    pub base_address: Option<u32>,
+}
+
 /// Representation of a SUIT payload.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Payload {
     /// URI of the payload. The custom URI schemes "p:" and "cp:"
-    /// are used to denote raw payloads and compressed payloads, respectively.
+    /// are used to denote raw payloads and compressed payloads, respectively,
+    /// that are integrated in the envelope. Any other URI refers to a
+    /// detached payload that the device fetches itself.
     pub uri: String,
 
     /// The start address of the payload location.
@@ -20,177 +65,486 @@ pub struct Payload {
     /// The size of the payload in bytes.
     pub size: usize,
 
+    /// The amount of memory the payload occupies once decompressed.
+    pub memory_size: usize,
+
     /// The raw bytes of the payload.
     pub bytes: Vec<u8>,
+
+    /// The payload as encrypted for the devices, if it is encrypted;
+    /// `bytes` and `size` then describe the payload once decrypted.
+    pub encryption: Option<EncryptedPayload>,
 }
 
-/// Create a list of SUIT Payloads from a list of all hex files that
-/// constitute a device firmware update.
-pub fn from_hex_files(
-    files: &[PathBuf],
+impl Payload {
+    /// Whether `address` is within the memory the payload occupies.
+    pub fn contains(&self, address: u32) -> bool {
+        (self.start_address as u64..self.start_address as u64 + self.memory_size as u64)
+            .contains(&(address as u64))
+    }
+
+    /// The bytes of the payload as they are delivered to the device;
+    /// encrypted if the payload is encrypted.
+    pub fn delivered_bytes(&self) -> &[u8] {
+        match &self.encryption {
+            Some(encryption) => &encryption.ciphertext,
+            None => &self.bytes,
+        }
+    }
+
+    /// Whether the payload is fetched by the device from its URI, rather
+    /// than integrated in the envelope.
+    pub fn is_detached(&self) -> bool {
+        !(self.uri.starts_with("p:") || self.uri.starts_with("cp:"))
+    }
+}
+
+/// Error produced when payloads cannot be built from a set of files.
+#[derive(Debug)]
+pub enum PayloadError {
+    /// A file could not be read.
+    Unreadable(PathBuf),
+
+    /// An Intel Hex file is malformed.
+    Hex(HexError),
+
+    /// A file in any other format is malformed.
+    InvalidFile { file: PathBuf, reason: String },
+
+    /// The same address is written by several files, and overwrites are not allowed.
+    Overlap {
+        address: u32,
+        first: (PathBuf, u8),
+        second: (PathBuf, u8),
+    },
+
+    /// Several files specify different entry points, and overwrites are not allowed.
+    ConflictingEntryPoints {
+        first: (PathBuf, u32),
+        second: (PathBuf, u32),
+    },
+
+    /// The URI template for detached payloads is not usable.
+    InvalidUriTemplate { template: String, reason: String },
+}
+
+impl fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PayloadError::Unreadable(file) => write!(f, "could not read file `{:?}`", file),
+            PayloadError::Hex(error) => write!(f, "{}", error),
+            PayloadError::InvalidFile { file, reason } => write!(f, "{:?}: {}", file, reason),
+            PayloadError::Overlap {
+                address,
+                first: (first_file, first_byte),
+                second: (second_file, second_byte),
+            } => write!(
+                f,
+                "the value at address `{:#04x}` is set multiple times; \
+                    first by `{:?}` ({:#02x}), and then by `{:?}` ({:#02x})",
+                address, first_file, first_byte, second_file, second_byte
+            ),
+            PayloadError::ConflictingEntryPoints {
+                first: (first_file, first_address),
+                second: (second_file, second_address),
+            } => write!(
+                f,
+                "the entry point is set multiple times; \
+                    first by `{:?}` ({:#x}), and then by `{:?}` ({:#x})",
+                first_file, first_address, second_file, second_address
+            ),
+            PayloadError::InvalidUriTemplate { template, reason } => {
+                write!(f, "invalid payload URI `{}`: {}", template, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PayloadError {}
+
+impl From<HexError> for PayloadError {
+    fn from(error: HexError) -> Self {
+        PayloadError::Hex(error)
+    }
+}
+
+/// Content of a single firmware file.
+struct FileContent {
+    /// Addresses with their corresponding byte values.
+    bytes: Vec<(u32, u8)>,
+
+    /// The address execution starts from, if the file specifies one.
+    entry_point: Option<u32>,
+}
+
+/// Builder for the payloads of a set of firmware files. By default,
+/// unwritten bytes are filled with `0xff`, overwrites are not allowed,
+/// payloads are compressed, and the memory is split into several
+/// payloads where it has large gaps. Payloads are integrated in the
+/// envelope unless a remote URI template is given.
+#[derive(Debug)]
+pub struct PayloadBuilder {
+    files: Vec<InputFile>,
     fill_value: u8,
     allow_overwrites: bool,
     use_compression: bool,
-) -> Vec<Payload> {
-    let mut raw_content: HashMap<u32, (u8, &PathBuf)> = HashMap::new();
-
-    for file in files {
-        let file_content = read_hex(file);
-
-        for (address, byte) in file_content {
-            if !allow_overwrites && raw_content.contains_key(&address) {
-                let (original_byte, original_file) = raw_content[&address];
-                panic!(
-                    "the value at address `{:#04x}` is set multiple times; \
-                        first by `{:?}` ({:#02x}), and then by `{:?}` ({:#02x})",
-                    address, original_file, original_byte, file, byte
-                );
-            }
+    split: bool,
+    first_index: usize,
+    remote_uri: Option<String>,
+    encryptor: Option<Encryptor>,
+}
 
-            raw_content.insert(address, (byte, file));
+impl PayloadBuilder {
+    pub fn new() -> Self {
+        Self {
+            files: Vec::new(),
+            fill_value: 0xff,
+            allow_overwrites: false,
+            use_compression: true,
+            split: true,
+            first_index: 0,
+            remote_uri: None,
+            encryptor: None,
         }
     }
 
-    let mut linear_memory: Vec<(u32, u8)> = raw_content
-        .into_iter()
-        .map(|(address, (byte, _path))| (address, byte))
-        .collect();
+    /// Add a file in a format that carries its own addresses.
+    pub fn file(mut self, path: PathBuf) -> Self {
+        self.files.push(InputFile {
+            path,
+            base_address: None,
+        });
+        self
+    }
 
-    linear_memory.sort_unstable();
+    /// Add a raw binary file, placed at `base_address`.
+    pub fn binary_file(mut self, path: PathBuf, base_address: u32) -> Self {
+        self.files.push(InputFile {
+            path,
+            base_address: Some(base_address),
+        });
+        self
+    }
 
-    let gaps = find_gaps(&linear_memory);
+    pub fn files(mut self, files: &[InputFile]) -> Self {
+        self.files.extend_from_slice(files);
+        self
+    }
 
-    let gap_offsets = gaps
-        .iter()
-        .filter(|(_offset, gap)| *gap >= PAYLOAD_SPLIT_PENALTY)
-        .map(|(offset, _gap)| offset);
+    /// The value that an unwritten byte has in memory.
+    pub fn fill_value(mut self, fill_value: u8) -> Self {
+        self.fill_value = fill_value;
+        self
+    }
 
-    let mut chunks = vec![0];
-    chunks.extend(gap_offsets);
-    chunks.push(linear_memory.len());
+    /// Allow that subsequent files overwrite earlier ones without error.
+    pub fn allow_overwrites(mut self, allow_overwrites: bool) -> Self {
+        self.allow_overwrites = allow_overwrites;
+        self
+    }
 
-    let segments: Vec<(u32, Vec<u8>)> = chunks
-        .windows(2)
-        .map(|window| {
-            let start = window[0];
-            let end = window[1];
+    pub fn compress(mut self, use_compression: bool) -> Self {
+        self.use_compression = use_compression;
+        self
+    }
 
-            normalize_memory(&linear_memory[start..end], fill_value)
-        })
-        .collect();
+    /// Whether to split the memory into several payloads where it has
+    /// large gaps; if not, a single payload is made with all gaps filled.
+    pub fn split(mut self, split: bool) -> Self {
+        self.split = split;
+        self
+    }
 
-    let model = compression::default_model::model();
+    /// Number of the first payload in its URI; subsequent payloads are
+    /// numbered consecutively.
+    pub fn first_index(mut self, first_index: usize) -> Self {
+        self.first_index = first_index;
+        self
+    }
 
-    segments
-        .iter()
-        .enumerate()
-        .map(|(index, (address, raw_bytes))| {
-            let uri = match use_compression {
-                true => format!("cp:{}", index),
-                false => format!("p:{}", index),
-            };
-
-            let bytes = match use_compression {
-                true => compression::encode(&model, &raw_bytes),
-                false => raw_bytes.to_vec(),
-            };
-
-            let size = bytes.len();
-
-            Payload {
-                uri,
-                start_address: *address,
-                size,
-                bytes,
-            }
-        })
-        .collect()
-}
+    /// Detach the payloads from the envelope, to be fetched by the device
+    /// from `template` with `{index}` replaced by the number of the payload,
+    /// e.g. `https://example.com/firmware/{index}.bin`. Detached payloads
+    /// are not compressed, as only the `cp:` scheme marks a payload as
+    /// compressed.
+    pub fn remote_uri(mut self, template: String) -> Self {
+        self.remote_uri = Some(template);
+        self
+    }
 
-/// Record type for the Intel Hex format.
-enum HexRecord {
-    Data,
-    EndOfFile,
-    ExtendedSegmentAddress,
-    ExtendedLinearAddress,
-}
+    /// Encrypt the payloads with `encryptor`, after compressing them.
+    pub fn encrypt(mut self, encryptor: Encryptor) -> Self {
+        self.encryptor = Some(encryptor);
+        self
+    }
+
+    /// Read the files, and create the payloads along with the entry
+    /// point of the firmware if any of the files specify one.
+    pub fn build(self) -> Result<(Vec<Payload>, Option<u32>), PayloadError> {
+        if let Some(template) = &self.remote_uri {
+            check_uri_template(template).map_err(|reason| PayloadError::InvalidUriTemplate {
+                template: template.clone(),
+                reason,
+            })?;
+        }
 
-impl From<u8> for HexRecord {
-    fn from(byte: u8) -> Self {
-        use HexRecord::*;
+        let use_compression = self.use_compression && self.remote_uri.is_none();
+
+        let mut raw_content: HashMap<u32, (u8, &PathBuf)> = HashMap::new();
+        let mut entry_point: Option<(u32, &PathBuf)> = None;
+
+        for InputFile { path, base_address } in &self.files {
+            let file_content = read_file(path, *base_address)?;
+
+            if let Some(address) = file_content.entry_point {
+                match entry_point {
+                    Some((original_address, original_file))
+                        if !self.allow_overwrites && original_address != address =>
+                    {
+                        return Err(PayloadError::ConflictingEntryPoints {
+                            first: (original_file.clone(), original_address),
+                            second: (path.clone(), address),
+                        });
+                    }
+                    _ => entry_point = Some((address, path)),
+                }
+            }
 
-        match byte {
-            0 => Data,
-            1 => EndOfFile,
-            2 => ExtendedSegmentAddress,
-            4 => ExtendedLinearAddress,
-            _ => panic!("Unsupported Hex record type `{}`", byte),
+            for (address, byte) in file_content.bytes {
+                if !self.allow_overwrites && raw_content.contains_key(&address) {
+                    let (original_byte, original_file) = raw_content[&address];
+                    return Err(PayloadError::Overlap {
+                        address,
+                        first: (original_file.clone(), original_byte),
+                        second: (path.clone(), byte),
+                    });
+                }
+
+                raw_content.insert(address, (byte, path));
+            }
         }
+
+        let mut linear_memory: Vec<(u32, u8)> = raw_content
+            .into_iter()
+            .map(|(address, (byte, _path))| (address, byte))
+            .collect();
+
+        linear_memory.sort_unstable();
+
+        let gaps = match self.split {
+            true => find_gaps(&linear_memory),
+            false => Vec::new(),
+        };
+
+        let gap_offsets = gaps
+            .iter()
+            .filter(|(_offset, gap)| *gap >= PAYLOAD_SPLIT_PENALTY)
+            .map(|(offset, _gap)| offset);
+
+        let mut chunks = vec![0];
+        chunks.extend(gap_offsets);
+        chunks.push(linear_memory.len());
+
+        let segments: Vec<(u32, Vec<u8>)> = chunks
+            .windows(2)
+            .map(|window| {
+                let start = window[0];
+                let end = window[1];
+
+                normalize_memory(&linear_memory[start..end], self.fill_value)
+            })
+            .collect();
+
+        let model = compression::default_model::model();
+
+        let payloads = segments
+            .iter()
+            .enumerate()
+            .map(|(index, (address, raw_bytes))| {
+                let index = self.first_index + index;
+
+                let uri = match (&self.remote_uri, use_compression) {
+                    (Some(template), _) => template.replace("{index}", &index.to_string()),
+                    (None, true) => format!("cp:{}", index),
+                    (None, false) => format!("p:{}", index),
+                };
+
+                let bytes = match use_compression {
+                    true => compression::encode(&model, raw_bytes),
+                    false => raw_bytes.to_vec(),
+                };
+
+                let size = bytes.len();
+
+                let encryption = self
+                    .encryptor
+                    .as_ref()
+                    .map(|encryptor| encryptor.encrypt(&bytes));
+
+                Payload {
+                    uri,
+                    start_address: *address,
+                    size,
+                    memory_size: raw_bytes.len(),
+                    bytes,
+                    encryption,
+                }
+            })
+            .collect();
+
+        Ok((payloads, entry_point.map(|(address, _path)| address)))
     }
 }
 
-/// Read a file in Intel Hex format, returning it as a vector of
-/// addresses with their corresponding byte values.
-fn read_hex(file: &PathBuf) -> Vec<(u32, u8)> {
-    let hex_content = std::fs::read_to_string(file)
-        .unwrap_or_else(|_| panic!("could not read file `{:?}`", file));
+impl Default for PayloadBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Decompress the bytes of a payload as delivered with the `cp:` URI
+/// scheme. Returns `None` if the bytes are malformed, or if they would
+/// decompress to more than `max_size` bytes.
+pub fn decompress(bytes: &[u8], max_size: usize) -> Option<Vec<u8>> {
+    compression::try_decode(&compression::default_model::model(), bytes, max_size)
+}
+
+/// Create a list of SUIT Payloads from a list of all hex files that
+/// constitute a device firmware update, along with the entry point
+/// of the firmware if any of the files specify one.
+pub fn from_hex_files(
+    files: &[InputFile],
+    fill_value: u8,
+    allow_overwrites: bool,
+    use_compression: bool,
+) -> Result<(Vec<Payload>, Option<u32>), PayloadError> {
+    PayloadBuilder::new()
+        .files(files)
+        .fill_value(fill_value)
+        .allow_overwrites(allow_overwrites)
+        .compress(use_compression)
+        .build()
+}
 
-    let mut result = Vec::new();
+/// Find an address that the payloads of more than one of `groups` occupy,
+/// such as those of different components, which are built separately;
+/// along with the indices of the first two groups that occupy it. A
+/// payload occupies its memory size from its start address, including the
+/// filled gaps between its files. Overlaps within a group are left to the
+/// builder.
+pub fn find_overlap(groups: &[Vec<&Payload>]) -> Option<(u32, usize, usize)> {
+    let mut ranges: Vec<(u64, u64, usize)> = groups
+        .iter()
+        .enumerate()
+        .flat_map(|(group, payloads)| {
+            payloads.iter().map(move |payload| {
+                let start = payload.start_address as u64;
+                (start, start + payload.memory_size as u64, group)
+            })
+        })
+        .filter(|(start, end, _group)| start < end)
+        .collect();
 
-    let mut extended_segment_address = 0;
-    let mut extended_linear_address = 0;
+    ranges.sort_unstable();
 
-    for line in hex_content.lines() {
-        let bytes = hex::decode(&line[1..]).unwrap_or_else(|_| {
-            panic!(
-                "could not parse hex content in line `{}`, file `{:?}`",
-                line, file
+    ranges
+        .iter()
+        .enumerate()
+        .flat_map(|(index, first)| {
+            ranges[index + 1..]
+                .iter()
+                .take_while(move |second| second.0 < first.1)
+                .map(move |second| (first, second))
+        })
+        .find(|(first, second)| first.2 != second.2)
+        .map(|(first, second)| {
+            (
+                second.0 as u32,
+                first.2.min(second.2),
+                first.2.max(second.2),
             )
-        });
-        let length = bytes.len();
+        })
+}
 
-        let computed_checksum = (&bytes[0..length - 1]
-            .iter()
-            .fold(0u8, |acc, &x| acc.wrapping_add(x))
-            ^ 0xff)
-            .wrapping_add(1u8);
+/// Check that a URI template for detached payloads uses a scheme that
+/// devices can fetch with, and numbers the payloads in its last segment,
+/// which the files of the payloads are named after.
+fn check_uri_template(template: &str) -> Result<(), String> {
+    let scheme = match template.split_once("://") {
+        Some((scheme, _rest)) => scheme,
+        None => return Err(String::from("not an absolute URI")),
+    };
 
-        let included_checksum = bytes[length - 1];
+    if !REMOTE_URI_SCHEMES.contains(&scheme) {
+        return Err(format!(
+            "unsupported scheme `{}`; expected one of {}",
+            scheme,
+            REMOTE_URI_SCHEMES.join(", ")
+        ));
+    }
 
-        assert_eq!(
-            included_checksum, computed_checksum,
-            "checksum mismatch {:#02x} vs. expected {:#02x} in line `{}`, file `{:?}`",
-            included_checksum, computed_checksum, line, file
-        );
+    let (_rest, last_segment) = template.rsplit_once('/').unwrap_or_default();
 
-        let count = bytes[0] as usize;
-        let base_address = (bytes[1] as u32) << 8 | bytes[2] as u32;
-        let record_type: HexRecord = bytes[3].into();
-        let bytes = &bytes[length - count - 1..length - 1];
-
-        match record_type {
-            HexRecord::Data => {
-                for (offset, byte) in bytes.iter().enumerate() {
-                    result.push((
-                        (extended_linear_address << 16)
-                            | (16 * extended_segment_address + base_address + (offset as u32)),
-                        *byte,
-                    ));
-                }
-            }
-            HexRecord::EndOfFile => break,
-            HexRecord::ExtendedSegmentAddress => {
-                assert_eq!(count, 2, "Incorrect extended segment address length");
-                extended_segment_address = u16::from_be_bytes([bytes[0], bytes[1]]) as u32;
-            }
-            HexRecord::ExtendedLinearAddress => {
-                assert_eq!(count, 2, "Incorrect extended linear address length");
-                extended_linear_address = u16::from_be_bytes([bytes[0], bytes[1]]) as u32;
-            }
+    match last_segment.contains("{index}") {
+        true => Ok(()),
+        false => Err(String::from(
+            "`{index}` is needed in the last segment to number the payloads",
+        )),
+    }
+}
+
+/// Read a firmware file in any of the supported file formats.
+fn read_file(file: &PathBuf, base_address: Option<u32>) -> Result<FileContent, PayloadError> {
+    let invalid = |reason| PayloadError::InvalidFile {
+        file: file.clone(),
+        reason,
+    };
+
+    let format = file
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(FileFormat::from_extension)
+        .ok_or_else(|| invalid(String::from("unsupported file format")))?;
+
+    let content = std::fs::read(file).map_err(|_| PayloadError::Unreadable(file.clone()))?;
+
+    match format {
+        FileFormat::IntelHex => Ok(intel_hex::read_hex(
+            file,
+            &String::from_utf8_lossy(&content),
+        )?),
+        FileFormat::SRecord => srec::read_srec(&String::from_utf8_lossy(&content)).map_err(invalid),
+        FileFormat::Elf => elf::read_elf(&content).map_err(invalid),
+        FileFormat::Binary => {
+            let base_address = base_address
+                .ok_or_else(|| invalid(String::from("no base address given for binary file")))?;
+
+            read_binary(content, base_address).map_err(invalid)
         }
     }
+}
+
+/// Place the content of a raw binary file at `base_address`.
+fn read_binary(content: Vec<u8>, base_address: u32) -> Result<FileContent, String> {
+    let end_address = base_address as u64 + content.len() as u64;
 
-    result
+    if end_address > u32::MAX as u64 + 1 {
+        return Err(format!(
+            "does not fit in 32-bit memory at address `{:#x}`",
+            base_address
+        ));
+    }
+
+    let bytes = content
+        .into_iter()
+        .enumerate()
+        .map(|(offset, byte)| (base_address + offset as u32, byte))
+        .collect();
+
+    Ok(FileContent {
+        bytes,
+        entry_point: None,
+    })
 }
 
 /// Find the locations of gaps in the content of a hex file. Gaps are jumps
@@ -250,3 +604,136 @@ fn normalize_memory(hex_content: &[(u32, u8)], fill_value: u8) -> (u32, Vec<u8>)
 
     (first_address, bytes)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Write `content` to a file of its own with `extension`, and return
+    /// its path.
+    fn firmware_file(content: &[u8], extension: &str) -> PathBuf {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "suit-payload-{}-{}.{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed),
+            extension
+        ));
+        std::fs::write(&path, content).unwrap();
+
+        path
+    }
+
+    fn payload(start_address: u32, memory_size: usize) -> Payload {
+        Payload {
+            uri: String::from("p:0"),
+            start_address,
+            size: memory_size,
+            memory_size,
+            bytes: vec![0; memory_size],
+            encryption: None,
+        }
+    }
+
+    #[test]
+    fn places_binaries_at_their_base_address() {
+        let content = read_binary(vec![0x01, 0x02, 0x03], 0x0800_0000).unwrap();
+
+        assert_eq!(
+            content.bytes,
+            vec![
+                (0x0800_0000, 0x01),
+                (0x0800_0001, 0x02),
+                (0x0800_0002, 0x03)
+            ]
+        );
+        assert_eq!(content.entry_point, None);
+
+        assert!(read_binary(vec![0x01, 0x02], 0xffff_fffe).is_ok());
+        assert_eq!(
+            read_binary(vec![0x01, 0x02], 0xffff_ffff).err(),
+            Some(String::from(
+                "does not fit in 32-bit memory at address `0xffffffff`"
+            ))
+        );
+    }
+
+    #[test]
+    fn builds_payloads_of_binaries() {
+        let binary = firmware_file(&[0x01, 0x02, 0x03, 0x04], "bin");
+
+        let (payloads, entry_point) = PayloadBuilder::new()
+            .binary_file(binary.clone(), 0x0001_0000)
+            .compress(false)
+            .build()
+            .unwrap();
+
+        std::fs::remove_file(binary).unwrap();
+
+        assert_eq!(payloads.len(), 1);
+        assert_eq!(payloads[0].start_address, 0x0001_0000);
+        assert_eq!(payloads[0].bytes, [0x01, 0x02, 0x03, 0x04]);
+        assert_eq!(entry_point, None);
+    }
+
+    #[test]
+    fn reports_overlaps_between_binaries_and_hex_files() {
+        // Data record of 0x01..=0x04 at 0x0000, and the end of file.
+        let hex = firmware_file(b":0400000001020304F2\n:00000001FF\n", "hex");
+        let binary = firmware_file(&[0xaa, 0xbb], "bin");
+
+        let build = |allow_overwrites| {
+            PayloadBuilder::new()
+                .file(hex.clone())
+                .binary_file(binary.clone(), 0x0002)
+                .allow_overwrites(allow_overwrites)
+                .compress(false)
+                .build()
+        };
+
+        let overlap = build(false);
+        let overwritten = build(true);
+
+        std::fs::remove_file(&hex).unwrap();
+        std::fs::remove_file(&binary).unwrap();
+
+        match overlap {
+            Err(PayloadError::Overlap {
+                address,
+                first,
+                second,
+            }) => {
+                assert_eq!(address, 0x0002);
+                assert_eq!(first, (hex, 0x03));
+                assert_eq!(second, (binary, 0xaa));
+            }
+            result => panic!("expected an overlap, found {:?}", result),
+        }
+
+        let (payloads, _entry_point) = overwritten.unwrap();
+        assert_eq!(payloads[0].bytes, [0x01, 0x02, 0xaa, 0xbb]);
+    }
+
+    #[test]
+    fn finds_overlaps_between_groups() {
+        let first = payload(0x1000, 0x100);
+        let gapped = payload(0x0000, 0x2000);
+        let adjacent = payload(0x1100, 0x100);
+        let empty = payload(0x1080, 0);
+
+        assert_eq!(find_overlap(&[vec![&first], vec![&adjacent, &empty]]), None);
+        assert_eq!(find_overlap(&[vec![&first, &gapped]]), None);
+
+        assert_eq!(
+            find_overlap(&[vec![&adjacent], vec![&first], vec![&gapped]]),
+            Some((0x1000, 1, 2))
+        );
+        assert_eq!(
+            find_overlap(&[vec![&first, &gapped], vec![&adjacent]]),
+            Some((0x1100, 0, 1))
+        );
+    }
+}
@@ -29,6 +29,8 @@ pub mod default_model;
 /// # Examples
 ///
 /// ```
+/// use suit_manifest_generator::payload::compression::{encode, Model};
+///
 /// let model = Model::<char>::new(3, &[('a', 2), ('b', 3), ('c', 3)]);
 ///
 /// let input = ['a', 'a', 'c', 'b', 'c', 'b', 'c'];
@@ -36,7 +38,7 @@ pub mod default_model;
 /// let encoding = encode(&model, &input);
 /// ```
 ///
-pub fn encode<'a, T>(model: &'a Model<T>, stream: &[T]) -> Vec<u8>
+pub fn encode<T>(model: &Model<T>, stream: &[T]) -> Vec<u8>
 where
     T: Copy + Eq + Hash,
 {
@@ -59,13 +61,18 @@ where
 /// # Examples
 ///
 /// ```
+/// use suit_manifest_generator::payload::compression::{decode, Model};
+///
 /// let model = Model::<char>::new(3, &[('a', 2), ('b', 3), ('c', 3)]);
 ///
-/// let encoding = [75, 218, 19, 0, 178];
+/// let encoding = [8, 109, 144, 96, 53];
 ///
 /// let decoding = decode(&model, &encoding);
+///
+/// assert_eq!(decoding, ['a', 'a', 'c', 'b', 'c', 'b', 'c']);
 /// ```
-pub fn decode<'a, T>(model: &'a Model<T>, stream: &[u8]) -> Vec<T>
+#[allow(dead_code)]
+pub fn decode<T>(model: &Model<T>, stream: &[u8]) -> Vec<T>
 where
     T: Copy + Eq + Hash,
 {
@@ -73,6 +80,27 @@ where
     decoder.into_iter().collect()
 }
 
+/// Decode a `stream` of bytes like [`decode`], but return `None` rather
+/// than panicking if the stream is malformed, or if it would decode to
+/// more than `max_length` symbols.
+pub fn try_decode<T>(model: &Model<T>, stream: &[u8], max_length: usize) -> Option<Vec<T>>
+where
+    T: Copy + Eq + Hash,
+{
+    let mut decoder = Coder::try_from_bytes(model, stream)?;
+    let mut symbols = Vec::new();
+
+    while let Some(symbol) = decoder.try_pop()? {
+        if symbols.len() == max_length {
+            return None;
+        }
+
+        symbols.push(symbol);
+    }
+
+    Some(symbols)
+}
+
 /// Model representing the probability that a certain set of symbols
 /// will appear in some stream of symbols.
 pub struct Model<T>
@@ -104,6 +132,8 @@ where
     ///
     /// # Examples
     /// ```
+    /// use suit_manifest_generator::payload::compression::Model;
+    ///
     /// // Possible symbols are 'a', 'b', 'c', and 'd'.
     /// // Probabilities are quantized at 1/8, 2/8, 3/8, and 2/8, respectively.
     /// // The quantization level is 3 bits.
@@ -206,25 +236,26 @@ where
         }
     }
 
+    #[allow(dead_code)]
     fn from_bytes(model: &'a Model<T>, bytes: &[u8]) -> Self {
+        Self::try_from_bytes(model, bytes).expect("Not enough input bytes")
+    }
+
+    fn try_from_bytes(model: &'a Model<T>, bytes: &[u8]) -> Option<Self> {
         let mut stack: Vec<u8> = bytes.iter().rev().copied().collect();
         let mut segment = 0;
 
         while segment < (1 << (32 - 8)) {
             segment <<= 8;
-            segment |= stack.pop().expect("Not enough input bytes") as u32;
+            segment |= stack.pop()? as u32;
         }
 
-        // Potential for improvement:
-        // Check that end of message is included somewhere,
-        // and return a result Err(...) if it is not
-
-        Self {
+        Some(Self {
             stack,
             segment,
             model,
             empty_message: 1 << (32 - 8),
-        }
+        })
     }
 
     fn push(&mut self, symbol: T) {
@@ -240,8 +271,14 @@ where
     }
 
     fn pop(&mut self) -> Option<T> {
+        self.try_pop().expect("Byte stream incorrectly terminated")
+    }
+
+    /// Pop the next symbol, or `None` if the stream is incorrectly
+    /// terminated.
+    fn try_pop(&mut self) -> Option<Option<T>> {
         if self.segment == self.empty_message {
-            return None;
+            return Some(None);
         }
 
         let prediction = self.segment & ((1 << self.model.precision) - 1);
@@ -251,15 +288,12 @@ where
 
         while s < (1 << (32 - 8)) {
             s <<= 8;
-            s |= self
-                .stack
-                .pop()
-                .expect("Byte stream incorrectly terminated") as u32;
+            s |= self.stack.pop()? as u32;
         }
 
         self.segment = s;
 
-        Some(symbol)
+        Some(Some(symbol))
     }
 
     fn bytes(&self) -> Vec<u8> {
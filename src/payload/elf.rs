@@ -0,0 +1,318 @@
+//! Reader for the loadable segments of ELF executables.
+
+use super::FileContent;
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+const PT_LOAD: u32 = 1;
+
+/// Read the loadable program segments of an ELF file, returning them as
+/// addresses with their corresponding byte values, along with the entry
+/// point of the program.
+///
+/// Segments are placed at their physical (load) address, which differs
+/// from the virtual address for e.g. initialized data copied to RAM at
+/// startup. Only the bytes present in the file are included, so zero
+/// initialized memory (NOBITS sections such as `.bss`) is left out.
+pub fn read_elf(content: &[u8]) -> Result<FileContent, String> {
+    let elf = Elf::new(content)?;
+
+    let mut result = Vec::new();
+
+    for segment in elf.program_headers()? {
+        if segment.kind != PT_LOAD || segment.file_size == 0 {
+            continue;
+        }
+
+        let bytes = elf.bytes(segment.offset, segment.file_size)?;
+
+        let end_address = segment
+            .physical_address
+            .saturating_add(segment.file_size - 1);
+
+        if end_address > u32::MAX as u64 {
+            return Err(format!(
+                "segment at address `{:#x}` does not fit in 32-bit memory",
+                segment.physical_address
+            ));
+        }
+
+        let start_address = segment.physical_address as u32;
+
+        for (offset, byte) in bytes.iter().enumerate() {
+            result.push((start_address + offset as u32, *byte));
+        }
+    }
+
+    Ok(FileContent {
+        bytes: result,
+        entry_point: elf.entry_point()?,
+    })
+}
+
+/// The fields of an ELF program header that are needed to load it.
+struct ProgramHeader {
+    kind: u32,
+    offset: u64,
+    physical_address: u64,
+    file_size: u64,
+}
+
+/// View into the raw content of an ELF file, aware of its word size
+/// and endianness.
+struct Elf<'a> {
+    content: &'a [u8],
+    is_64_bit: bool,
+    is_big_endian: bool,
+}
+
+impl<'a> Elf<'a> {
+    fn new(content: &'a [u8]) -> Result<Self, String> {
+        if !content.starts_with(ELF_MAGIC) || content.len() <= 6 {
+            return Err(String::from("not an ELF file"));
+        }
+
+        let is_64_bit = match content[4] {
+            1 => false,
+            2 => true,
+            class => return Err(format!("unsupported ELF class `{}`", class)),
+        };
+
+        let is_big_endian = match content[5] {
+            1 => false,
+            2 => true,
+            encoding => return Err(format!("unsupported ELF data encoding `{}`", encoding)),
+        };
+
+        Ok(Self {
+            content,
+            is_64_bit,
+            is_big_endian,
+        })
+    }
+
+    /// The entry point of the program; zero means that there is none.
+    fn entry_point(&self) -> Result<Option<u32>, String> {
+        let entry_point = match self.is_64_bit {
+            false => self.word(0x18)?,
+            true => self.double(0x18)?,
+        };
+
+        match entry_point {
+            0 => Ok(None),
+            address => u32::try_from(address)
+                .map(Some)
+                .map_err(|_| format!("entry point `{:#x}` does not fit in 32-bit memory", address)),
+        }
+    }
+
+    fn program_headers(&self) -> Result<Vec<ProgramHeader>, String> {
+        let (offset, entry_size, count) = match self.is_64_bit {
+            false => (self.word(0x1c)?, self.half(0x2a)?, self.half(0x2c)?),
+            true => (self.double(0x20)?, self.half(0x36)?, self.half(0x38)?),
+        };
+
+        self.bytes(offset, count * entry_size)?;
+
+        (0..count)
+            .map(|index| offset + index * entry_size)
+            .map(|header| {
+                Ok(match self.is_64_bit {
+                    false => ProgramHeader {
+                        kind: self.word(header)? as u32,
+                        offset: self.word(header + 0x04)?,
+                        physical_address: self.word(header + 0x0c)?,
+                        file_size: self.word(header + 0x10)?,
+                    },
+                    true => ProgramHeader {
+                        kind: self.word(header)? as u32,
+                        offset: self.double(header + 0x08)?,
+                        physical_address: self.double(header + 0x18)?,
+                        file_size: self.double(header + 0x20)?,
+                    },
+                })
+            })
+            .collect()
+    }
+
+    fn bytes(&self, offset: u64, length: u64) -> Result<&'a [u8], String> {
+        offset
+            .checked_add(length)
+            .filter(|&end| end <= self.content.len() as u64)
+            .map(|end| &self.content[offset as usize..end as usize])
+            .ok_or_else(|| String::from("ELF file is truncated"))
+    }
+
+    fn integer(&self, offset: u64, length: u64) -> Result<u64, String> {
+        let bytes = self.bytes(offset, length)?;
+
+        let fold = |acc: u64, &byte: &u8| (acc << 8) | byte as u64;
+
+        Ok(match self.is_big_endian {
+            true => bytes.iter().fold(0, fold),
+            false => bytes.iter().rev().fold(0, fold),
+        })
+    }
+
+    fn half(&self, offset: u64) -> Result<u64, String> {
+        self.integer(offset, 2)
+    }
+
+    fn word(&self, offset: u64) -> Result<u64, String> {
+        self.integer(offset, 4)
+    }
+
+    fn double(&self, offset: u64) -> Result<u64, String> {
+        self.integer(offset, 8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A loadable segment, or another kind of segment, of a test ELF file.
+    struct Segment<'a> {
+        kind: u32,
+        physical_address: u64,
+        data: &'a [u8],
+    }
+
+    /// Make an ELF file with only the fields that are read filled in.
+    fn elf(
+        is_64_bit: bool,
+        is_big_endian: bool,
+        entry_point: u64,
+        segments: &[Segment],
+    ) -> Vec<u8> {
+        let (header_size, entry_size) = match is_64_bit {
+            false => (0x34, 0x20),
+            true => (0x40, 0x38),
+        };
+
+        let mut content = vec![0; header_size + entry_size * segments.len()];
+
+        let put = |content: &mut Vec<u8>, offset: usize, length: usize, value: u64| {
+            let bytes = value.to_le_bytes();
+            let mut bytes = bytes[..length].to_vec();
+
+            if is_big_endian {
+                bytes.reverse();
+            }
+
+            content[offset..offset + length].copy_from_slice(&bytes);
+        };
+
+        content[..4].copy_from_slice(ELF_MAGIC);
+        content[4] = if is_64_bit { 2 } else { 1 };
+        content[5] = if is_big_endian { 2 } else { 1 };
+
+        match is_64_bit {
+            false => {
+                put(&mut content, 0x18, 4, entry_point);
+                put(&mut content, 0x1c, 4, header_size as u64);
+                put(&mut content, 0x2a, 2, entry_size as u64);
+                put(&mut content, 0x2c, 2, segments.len() as u64);
+            }
+            true => {
+                put(&mut content, 0x18, 8, entry_point);
+                put(&mut content, 0x20, 8, header_size as u64);
+                put(&mut content, 0x36, 2, entry_size as u64);
+                put(&mut content, 0x38, 2, segments.len() as u64);
+            }
+        }
+
+        for (index, segment) in segments.iter().enumerate() {
+            let header = header_size + index * entry_size;
+            let offset = content.len() as u64;
+            let size = segment.data.len() as u64;
+
+            put(&mut content, header, 4, segment.kind as u64);
+
+            match is_64_bit {
+                false => {
+                    put(&mut content, header + 0x04, 4, offset);
+                    put(&mut content, header + 0x0c, 4, segment.physical_address);
+                    put(&mut content, header + 0x10, 4, size);
+                }
+                true => {
+                    put(&mut content, header + 0x08, 8, offset);
+                    put(&mut content, header + 0x18, 8, segment.physical_address);
+                    put(&mut content, header + 0x20, 8, size);
+                }
+            }
+
+            content.extend(segment.data);
+        }
+
+        content
+    }
+
+    fn load(physical_address: u64, data: &[u8]) -> Segment<'_> {
+        Segment {
+            kind: PT_LOAD,
+            physical_address,
+            data,
+        }
+    }
+
+    #[test]
+    fn reads_the_loadable_segments() {
+        for (is_64_bit, is_big_endian) in
+            [(false, false), (false, true), (true, false), (true, true)]
+        {
+            let segments = [
+                load(0x1000, &[1, 2]),
+                Segment {
+                    kind: 4,
+                    physical_address: 0x2000,
+                    data: &[3],
+                },
+                load(0x3000, &[]),
+                load(0x2000_0000, &[4]),
+            ];
+
+            let content = read_elf(&elf(is_64_bit, is_big_endian, 0x1001, &segments)).unwrap();
+
+            assert_eq!(
+                content.bytes,
+                vec![(0x1000, 1), (0x1001, 2), (0x2000_0000, 4)]
+            );
+            assert_eq!(content.entry_point, Some(0x1001));
+        }
+    }
+
+    #[test]
+    fn reads_a_zero_entry_point_as_none() {
+        let content = read_elf(&elf(false, false, 0, &[load(0, &[1])])).unwrap();
+
+        assert_eq!(content.entry_point, None);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let mut unsupported_class = elf(false, false, 0, &[]);
+        unsupported_class[4] = 3;
+
+        let mut truncated = elf(false, false, 0, &[load(0x1000, &[1, 2, 3])]);
+        truncated.pop();
+
+        let cases = [
+            (b"\x7fELX\x01\x01".to_vec(), "not an ELF file"),
+            (unsupported_class, "unsupported ELF class `3`"),
+            (truncated, "ELF file is truncated"),
+            (
+                elf(true, false, 0, &[load(0xffff_ffff, &[1, 2])]),
+                "segment at address `0xffffffff` does not fit in 32-bit memory",
+            ),
+            (
+                elf(true, false, 0x1_0000_0000, &[]),
+                "entry point `0x100000000` does not fit in 32-bit memory",
+            ),
+        ];
+
+        for (content, error) in cases {
+            assert_eq!(read_elf(&content).err().as_deref(), Some(error));
+        }
+    }
+}
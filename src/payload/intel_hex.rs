@@ -0,0 +1,376 @@
+//! Reader for the Intel Hex format.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::FileContent;
+
+/// Column of the first byte of a record; the start code is in column 1.
+const FIRST_BYTE_COLUMN: usize = 2;
+
+/// Amount of bytes in a record that are not data; the byte count,
+/// the two address bytes, the record type and the checksum.
+const RECORD_OVERHEAD: usize = 5;
+
+/// Record type for the Intel Hex format.
+#[derive(Debug, Clone, Copy)]
+pub enum HexRecord {
+    Data,
+    EndOfFile,
+    ExtendedSegmentAddress,
+    StartSegmentAddress,
+    ExtendedLinearAddress,
+    StartLinearAddress,
+}
+
+impl TryFrom<u8> for HexRecord {
+    type Error = u8;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        use HexRecord::*;
+
+        match byte {
+            0 => Ok(Data),
+            1 => Ok(EndOfFile),
+            2 => Ok(ExtendedSegmentAddress),
+            3 => Ok(StartSegmentAddress),
+            4 => Ok(ExtendedLinearAddress),
+            5 => Ok(StartLinearAddress),
+            _ => Err(byte),
+        }
+    }
+}
+
+/// Error found while reading an Intel Hex file, pointing out where in
+/// the file the problem is.
+#[derive(Debug)]
+pub struct HexError {
+    pub file: PathBuf,
+
+    /// Line of the offending record, counted from 1.
+    pub line: usize,
+
+    /// Column of the offending character or byte, counted from 1.
+    pub column: usize,
+
+    /// Type of the offending record, if it could be determined.
+    pub record: Option<HexRecord>,
+
+    pub kind: HexErrorKind,
+}
+
+#[derive(Debug)]
+pub enum HexErrorKind {
+    MissingStartCode,
+    InvalidHexDigit(char),
+    OddDigitCount,
+    RecordTooShort,
+    ByteCountMismatch { count: usize, found: usize },
+    ChecksumMismatch { included: u8, computed: u8 },
+    UnsupportedRecordType(u8),
+    InvalidAddressLength { expected: usize, found: usize },
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use HexErrorKind::*;
+
+        write!(f, "{:?}:{}:{}: ", self.file, self.line, self.column)?;
+
+        match &self.kind {
+            MissingStartCode => write!(f, "record does not start with `:`"),
+            InvalidHexDigit(character) => write!(f, "invalid hex digit `{}`", character),
+            OddDigitCount => write!(f, "record has an odd number of hex digits"),
+            RecordTooShort => write!(f, "record is too short"),
+            ByteCountMismatch { count, found } => write!(
+                f,
+                "byte count says {} data bytes, but the record has {}",
+                count, found
+            ),
+            ChecksumMismatch { included, computed } => write!(
+                f,
+                "checksum mismatch {:#04x} vs. expected {:#04x}",
+                included, computed
+            ),
+            UnsupportedRecordType(record_type) => {
+                write!(f, "unsupported record type `{:02}`", record_type)
+            }
+            InvalidAddressLength { expected, found } => write!(
+                f,
+                "address record must have {} data bytes, but has {}",
+                expected, found
+            ),
+        }?;
+
+        match self.record {
+            Some(record) => write!(f, " in {:?} record", record),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for HexError {}
+
+/// Column of the byte at `index` in a record.
+fn byte_column(index: usize) -> usize {
+    FIRST_BYTE_COLUMN + 2 * index
+}
+
+/// Decode the hex digits of a record, after the start code.
+fn decode_record(digits: &str) -> Result<Vec<u8>, (usize, HexErrorKind)> {
+    if let Some((index, character)) = digits
+        .char_indices()
+        .find(|(_index, character)| !character.is_ascii_hexdigit())
+    {
+        let column = FIRST_BYTE_COLUMN + digits[..index].chars().count();
+        return Err((column, HexErrorKind::InvalidHexDigit(character)));
+    }
+
+    if digits.len() % 2 == 1 {
+        return Err((
+            FIRST_BYTE_COLUMN + digits.len() - 1,
+            HexErrorKind::OddDigitCount,
+        ));
+    }
+
+    Ok(hex::decode(digits).unwrap())
+}
+
+/// Read a file in Intel Hex format, returning its addresses with their
+/// corresponding byte values, and the entry point if one is given.
+///
+/// Blank lines are ignored, and nothing after an end of file record is read.
+pub fn read_hex(file: &Path, hex_content: &str) -> Result<FileContent, HexError> {
+    let mut result = Vec::new();
+    let mut entry_point = None;
+
+    let mut extended_segment_address = 0;
+    let mut extended_linear_address = 0;
+
+    for (line_index, line) in hex_content.lines().enumerate() {
+        let line = line.trim_end();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let error = |column, record, kind| HexError {
+            file: file.to_path_buf(),
+            line: line_index + 1,
+            column,
+            record,
+            kind,
+        };
+
+        let digits = line
+            .strip_prefix(':')
+            .ok_or_else(|| error(1, None, HexErrorKind::MissingStartCode))?;
+
+        let bytes = decode_record(digits).map_err(|(column, kind)| error(column, None, kind))?;
+        let length = bytes.len();
+
+        if length < RECORD_OVERHEAD {
+            return Err(error(
+                byte_column(length),
+                None,
+                HexErrorKind::RecordTooShort,
+            ));
+        }
+
+        let count = bytes[0] as usize;
+        let base_address = (bytes[1] as u32) << 8 | bytes[2] as u32;
+
+        let record = HexRecord::try_from(bytes[3]).map_err(|record_type| {
+            error(
+                byte_column(3),
+                None,
+                HexErrorKind::UnsupportedRecordType(record_type),
+            )
+        })?;
+
+        if count + RECORD_OVERHEAD != length {
+            return Err(error(
+                byte_column(0),
+                Some(record),
+                HexErrorKind::ByteCountMismatch {
+                    count,
+                    found: length - RECORD_OVERHEAD,
+                },
+            ));
+        }
+
+        let computed_checksum = (bytes[0..length - 1]
+            .iter()
+            .fold(0u8, |acc, &x| acc.wrapping_add(x))
+            ^ 0xff)
+            .wrapping_add(1u8);
+
+        let included_checksum = bytes[length - 1];
+
+        if included_checksum != computed_checksum {
+            return Err(error(
+                byte_column(length - 1),
+                Some(record),
+                HexErrorKind::ChecksumMismatch {
+                    included: included_checksum,
+                    computed: computed_checksum,
+                },
+            ));
+        }
+
+        let bytes = &bytes[length - count - 1..length - 1];
+
+        let expect_length = |expected| match count == expected {
+            true => Ok(()),
+            false => Err(error(
+                byte_column(0),
+                Some(record),
+                HexErrorKind::InvalidAddressLength {
+                    expected,
+                    found: count,
+                },
+            )),
+        };
+
+        match record {
+            HexRecord::Data => {
+                for (offset, byte) in bytes.iter().enumerate() {
+                    result.push((
+                        (extended_linear_address << 16)
+                            | (16 * extended_segment_address + base_address + (offset as u32)),
+                        *byte,
+                    ));
+                }
+            }
+            HexRecord::EndOfFile => break,
+            HexRecord::ExtendedSegmentAddress => {
+                expect_length(2)?;
+                extended_segment_address = u16::from_be_bytes([bytes[0], bytes[1]]) as u32;
+            }
+            HexRecord::StartSegmentAddress => {
+                expect_length(4)?;
+                let code_segment = u16::from_be_bytes([bytes[0], bytes[1]]) as u32;
+                let instruction_pointer = u16::from_be_bytes([bytes[2], bytes[3]]) as u32;
+                entry_point = Some(16 * code_segment + instruction_pointer);
+            }
+            HexRecord::ExtendedLinearAddress => {
+                expect_length(2)?;
+                extended_linear_address = u16::from_be_bytes([bytes[0], bytes[1]]) as u32;
+            }
+            HexRecord::StartLinearAddress => {
+                expect_length(4)?;
+                entry_point = Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+            }
+        }
+    }
+
+    Ok(FileContent {
+        bytes: result,
+        entry_point,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Make a record of `record_type` at `address` with its byte count and checksum.
+    fn record(record_type: u8, address: u16, data: &[u8]) -> String {
+        let mut bytes = vec![data.len() as u8];
+        bytes.extend(address.to_be_bytes());
+        bytes.push(record_type);
+        bytes.extend(data);
+
+        let checksum = bytes
+            .iter()
+            .fold(0u8, |acc, &x| acc.wrapping_add(x))
+            .wrapping_neg();
+        bytes.push(checksum);
+
+        format!(":{}", hex::encode_upper(bytes))
+    }
+
+    fn read(content: &str) -> Result<FileContent, HexError> {
+        read_hex(Path::new("test.hex"), content)
+    }
+
+    #[test]
+    fn reads_data_at_extended_addresses() {
+        let content = [
+            record(0, 0x0100, &[0x01, 0x02]),
+            record(2, 0, &[0x10, 0x00]),
+            record(0, 0x0004, &[0x03]),
+            record(2, 0, &[0x00, 0x00]),
+            record(4, 0, &[0x08, 0x00]),
+            record(0, 0xfffe, &[0x04]),
+            String::new(),
+            record(1, 0, &[]),
+            record(0, 0x0000, &[0x05]),
+        ]
+        .join("\n");
+
+        let content = read(&content).unwrap();
+
+        assert_eq!(
+            content.bytes,
+            vec![
+                (0x0100, 0x01),
+                (0x0101, 0x02),
+                (0x0001_0004, 0x03),
+                (0x0800_fffe, 0x04),
+            ]
+        );
+        assert_eq!(content.entry_point, None);
+    }
+
+    #[test]
+    fn reads_start_address_records() {
+        let linear = [record(5, 0, &[0x08, 0x00, 0x01, 0x23]), record(1, 0, &[])].join("\n");
+        let segment = [record(3, 0, &[0x12, 0x34, 0x00, 0x10]), record(1, 0, &[])].join("\n");
+
+        assert_eq!(read(&linear).unwrap().entry_point, Some(0x0800_0123));
+        assert_eq!(read(&segment).unwrap().entry_point, Some(0x0001_2350));
+    }
+
+    #[test]
+    fn reports_the_offending_line_and_column() {
+        let valid = record(0, 0, &[0x01]);
+
+        let cases = [
+            (
+                format!("{}\n{}", valid, &valid[1..]),
+                "\"test.hex\":2:1: record does not start with `:`",
+            ),
+            (
+                String::from(":0100000G01"),
+                "\"test.hex\":1:9: invalid hex digit `G`",
+            ),
+            (
+                String::from(":010000000"),
+                "\"test.hex\":1:10: record has an odd number of hex digits",
+            ),
+            (String::from(":01000000"), "\"test.hex\":1:10: record is too short"),
+            (
+                String::from(":0200000001FD"),
+                "\"test.hex\":1:2: byte count says 2 data bytes, but the record has 1 in Data record",
+            ),
+            (
+                String::from(":0100000001FF"),
+                "\"test.hex\":1:12: checksum mismatch 0xff vs. expected 0xfe in Data record",
+            ),
+            (
+                record(6, 0, &[]),
+                "\"test.hex\":1:8: unsupported record type `06`",
+            ),
+            (
+                record(4, 0, &[0x08]),
+                "\"test.hex\":1:2: address record must have 2 data bytes, but has 1 \
+                    in ExtendedLinearAddress record",
+            ),
+        ];
+
+        for (content, error) in cases {
+            assert_eq!(read(&content).err().unwrap().to_string(), error);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Reader for the Motorola S-record format (S19, S28 and S37 files).
+
+use super::FileContent;
+
+/// Record type for the Motorola S-record format.
+enum SRecord {
+    Header,
+    Data(usize),
+    Count(usize),
+    Termination(usize),
+}
+
+impl SRecord {
+    /// Interpret the type digit following the `S` of a record.
+    fn from_digit(digit: char) -> Option<Self> {
+        use SRecord::*;
+
+        match digit {
+            '0' => Some(Header),
+            '1' => Some(Data(2)),
+            '2' => Some(Data(3)),
+            '3' => Some(Data(4)),
+            '5' => Some(Count(2)),
+            '6' => Some(Count(3)),
+            '7' => Some(Termination(4)),
+            '8' => Some(Termination(3)),
+            '9' => Some(Termination(2)),
+            _ => None,
+        }
+    }
+
+    /// Amount of bytes in the address field of the record.
+    fn address_length(&self) -> usize {
+        match self {
+            SRecord::Header => 2,
+            SRecord::Data(length) | SRecord::Count(length) | SRecord::Termination(length) => {
+                *length
+            }
+        }
+    }
+}
+
+/// Read a file in Motorola S-record format, returning its addresses with
+/// their corresponding byte values, and the entry point given by the
+/// termination record; address zero, as in the customary `S9030000FC`,
+/// means that there is none. Errors describe the offending line.
+pub fn read_srec(srec_content: &str) -> Result<FileContent, String> {
+    let mut result = Vec::new();
+    let mut entry_point = None;
+    let mut data_records = 0;
+
+    for (line_index, line) in srec_content
+        .lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_line_index, line)| !line.is_empty())
+    {
+        let fail = |reason: &str| Err(format!("line {}: {}", line_index + 1, reason));
+
+        let mut characters = line.chars();
+
+        let record_type = match (characters.next(), characters.next()) {
+            (Some('S'), Some(digit)) => SRecord::from_digit(digit),
+            _ => None,
+        };
+
+        let record_type = match record_type {
+            Some(record_type) => record_type,
+            None => return fail("unsupported S-record type"),
+        };
+
+        let bytes = match hex::decode(&line[2..]) {
+            Ok(bytes) => bytes,
+            Err(_) => return fail("could not parse hex content"),
+        };
+
+        let address_length = record_type.address_length();
+
+        if bytes.is_empty() || bytes[0] as usize != bytes.len() - 1 {
+            return fail("byte count does not match record length");
+        }
+
+        if bytes.len() <= address_length + 1 {
+            return fail("record too short for its address");
+        }
+
+        let length = bytes.len();
+
+        let computed_checksum = bytes[0..length - 1]
+            .iter()
+            .fold(0u8, |acc, &x| acc.wrapping_add(x))
+            ^ 0xff;
+
+        let included_checksum = bytes[length - 1];
+
+        if included_checksum != computed_checksum {
+            return fail(&format!(
+                "checksum mismatch {:#04x} vs. expected {:#04x}",
+                included_checksum, computed_checksum
+            ));
+        }
+
+        let address = bytes[1..1 + address_length]
+            .iter()
+            .fold(0u32, |acc, &byte| (acc << 8) | byte as u32);
+        let bytes = &bytes[1 + address_length..length - 1];
+
+        match record_type {
+            SRecord::Header => {}
+            SRecord::Data(_) => {
+                data_records += 1;
+
+                for (offset, byte) in bytes.iter().enumerate() {
+                    match address.checked_add(offset as u32) {
+                        Some(address) => result.push((address, *byte)),
+                        None => return fail("data runs past the end of 32-bit memory"),
+                    }
+                }
+            }
+            SRecord::Count(_) if address != data_records => {
+                return fail(&format!(
+                    "record count {} does not match the {} data records",
+                    address, data_records
+                ));
+            }
+            SRecord::Count(_) => {}
+            SRecord::Termination(_) => {
+                entry_point = Some(address).filter(|&address| address != 0);
+                break;
+            }
+        }
+    }
+
+    Ok(FileContent {
+        bytes: result,
+        entry_point,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Make a record of `record_type` with its byte count and checksum.
+    fn record(record_type: char, fields: &[u8]) -> String {
+        let mut bytes = vec![fields.len() as u8 + 1];
+        bytes.extend(fields);
+
+        let checksum = bytes.iter().fold(0u8, |acc, &x| acc.wrapping_add(x)) ^ 0xff;
+        bytes.push(checksum);
+
+        format!("S{}{}", record_type, hex::encode_upper(bytes))
+    }
+
+    #[test]
+    fn reads_data_records_of_all_address_lengths() {
+        let content = [
+            record('0', &[0x00, 0x00, b'h', b'd', b'r']),
+            record('1', &[0x10, 0x00, 0xaa, 0xbb]),
+            record('2', &[0x01, 0x00, 0x00, 0xcc]),
+            record('3', &[0x08, 0x00, 0x00, 0x00, 0xdd, 0xee]),
+            record('5', &[0x00, 0x03]),
+            record('7', &[0x08, 0x00, 0x00, 0x04]),
+        ]
+        .join("\n");
+
+        let content = read_srec(&content).unwrap();
+
+        assert_eq!(
+            content.bytes,
+            vec![
+                (0x1000, 0xaa),
+                (0x1001, 0xbb),
+                (0x1_0000, 0xcc),
+                (0x0800_0000, 0xdd),
+                (0x0800_0001, 0xee),
+            ]
+        );
+        assert_eq!(content.entry_point, Some(0x0800_0004));
+    }
+
+    #[test]
+    fn reads_the_standard_terminator_as_no_entry_point() {
+        let content = [record('1', &[0x00, 0x00, 0x01]), String::from("S9030000FC")].join("\n");
+
+        assert_eq!(read_srec(&content).unwrap().entry_point, None);
+    }
+
+    #[test]
+    fn reports_the_offending_line() {
+        let valid = record('1', &[0x00, 0x00, 0x01]);
+
+        let mut bad_checksum = valid.clone();
+        bad_checksum.replace_range(bad_checksum.len() - 2.., "00");
+
+        let cases = [
+            (
+                format!("{}\nS4030000FC", valid),
+                "line 2: unsupported S-record type",
+            ),
+            (
+                format!("{}\nS1040000XX", valid),
+                "line 2: could not parse hex content",
+            ),
+            (
+                String::from("S105000001FB"),
+                "line 1: byte count does not match record length",
+            ),
+            (
+                record('3', &[0x00, 0x00, 0x00]),
+                "line 1: record too short for its address",
+            ),
+            (
+                bad_checksum,
+                "line 1: checksum mismatch 0x00 vs. expected 0xfa",
+            ),
+            (
+                format!("{}\n{}", valid, record('5', &[0x00, 0x02])),
+                "line 2: record count 2 does not match the 1 data records",
+            ),
+            (
+                record('3', &[0xff, 0xff, 0xff, 0xff, 0x01, 0x02]),
+                "line 1: data runs past the end of 32-bit memory",
+            ),
+        ];
+
+        for (content, error) in cases {
+            assert_eq!(read_srec(&content).err().as_deref(), Some(error));
+        }
+    }
+}
@@ -0,0 +1,1212 @@
+//! Host-side reference processor, which follows the command sequences
+//! of a manifest the way a device would, against a simulated memory.
+//!
+//! An envelope is processed in the two procedures of a device: the
+//! update procedure runs the common sequence, payload fetch and install,
+//! after which the invoke procedure runs the common sequence, validate,
+//! load and run. Parameters are reset between procedures, whereas the
+//! images written to components are kept by the [`Device`], so that
+//! several envelopes may be processed on it in turn.
+//!
+//! A component holds the image as it was fetched, which is what
+//! image-match conditions check, along with the content the image
+//! occupies in memory; which is the decompressed image for payloads
+//! with the `cp:` scheme. Encrypted payloads are not decrypted, so
+//! fetching one fails. Authentication is not checked; see
+//! [`crate::verify`] for that.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::cbor::{Cbor, DecodeError};
+use crate::digest::Digest;
+use crate::inspect;
+use crate::payload;
+use crate::suit_constant::{self, KeySpace, SuitConstant};
+use crate::uuid::Uuid;
+
+/// Largest content that a compressed payload may decompress to.
+const MAX_CONTENT_SIZE: usize = 64 * 1024 * 1024;
+
+/// Maximum nesting of command sequences in try-each and run-sequence
+/// directives that is run; a device has a bounded stack as well.
+const MAX_SEQUENCE_DEPTH: usize = 16;
+
+/// The image written to a component of the device.
+#[derive(Debug, Clone)]
+pub struct Image {
+    /// Identifier of the component.
+    pub component: Cbor,
+
+    /// Slot of the component that the image is written to, if any.
+    pub slot: Option<u64>,
+
+    /// The image as it was fetched.
+    pub bytes: Vec<u8>,
+
+    /// What the image occupies in memory.
+    pub content: Vec<u8>,
+}
+
+impl Image {
+    /// Address of the component, for components that are identified by
+    /// their address, as those of generated manifests are.
+    pub fn address(&self) -> Option<u32> {
+        match &self.component {
+            Cbor::Array(parts) => match parts.as_slice() {
+                [Cbor::Bstr(address)] => {
+                    Some(u32::from_be_bytes(address.as_slice().try_into().ok()?))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+/// The procedures a device processes a manifest in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Procedure {
+    Update,
+    Invoke,
+}
+
+impl fmt::Display for Procedure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Procedure::Update => write!(f, "update"),
+            Procedure::Invoke => write!(f, "invoke"),
+        }
+    }
+}
+
+/// What a command applies to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Component(usize),
+    Dependency(usize),
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Component(index) => write!(f, "component {}", index),
+            Target::Dependency(index) => write!(f, "dependency {}", index),
+        }
+    }
+}
+
+/// A SUIT report record, made for a command whose reporting policy asks
+/// for its success or failure to be recorded.
+#[derive(Debug, Clone)]
+pub struct Record {
+    /// Where the command is found in the manifest.
+    pub path: String,
+
+    pub command: SuitConstant,
+    pub target: Target,
+    pub success: bool,
+
+    /// The parameters of the target, when the reporting policy asks for
+    /// system information.
+    pub parameters: Option<Vec<(Cbor, Cbor)>>,
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let result = match self.success {
+            true => "succeeded",
+            false => "failed",
+        };
+
+        write!(
+            f,
+            "{}: {} on {} {}",
+            self.path,
+            self.command.name(),
+            self.target,
+            result
+        )?;
+
+        match &self.parameters {
+            Some(parameters) => write!(
+                f,
+                " with {}",
+                inspect::render_parameters(&Cbor::Map(parameters.clone()))
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A component that the device runs.
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub path: String,
+    pub component: usize,
+    pub arguments: Option<Vec<u8>>,
+}
+
+/// The command a procedure ends at, and why it fails.
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub path: String,
+    pub reason: String,
+
+    /// Whether a condition fails, rather than a directive. Only a failing
+    /// condition moves on to the next alternative of a try-each directive,
+    /// or ends just its own sequence when soft failure is set; any other
+    /// failure aborts the procedure.
+    pub is_condition: bool,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.reason)
+    }
+}
+
+/// What happens in a procedure.
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub procedure: Procedure,
+    pub records: Vec<Record>,
+    pub runs: Vec<Run>,
+    pub outcome: Result<(), Failure>,
+}
+
+/// Error produced when an envelope cannot be processed at all.
+#[derive(Debug)]
+pub enum SimulationError {
+    /// The envelope is not valid CBOR.
+    Decode(DecodeError),
+
+    /// The envelope has no manifest, or the manifest is not a map.
+    NoManifest,
+
+    /// The manifest has no common section listing its components.
+    NoComponents,
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimulationError::Decode(error) => write!(f, "could not decode the envelope: {}", error),
+            SimulationError::NoManifest => write!(f, "the envelope does not contain a manifest"),
+            SimulationError::NoComponents => write!(f, "the manifest lists no components"),
+        }
+    }
+}
+
+impl std::error::Error for SimulationError {}
+
+/// A simulated device, which envelopes are processed on.
+#[derive(Debug, Clone, Default)]
+pub struct Device {
+    vendor_identifier: Option<Uuid>,
+    class_identifier: Option<Uuid>,
+    device_identifier: Option<Uuid>,
+    slot: Option<u64>,
+    payload_dir: Option<PathBuf>,
+    images: Vec<Image>,
+
+    /// Envelopes of the dependencies fetched by the device, which are
+    /// kept to process them in later procedures.
+    dependencies: Vec<Vec<u8>>,
+}
+
+impl Device {
+    /// Create a device with empty components, which accepts any vendor,
+    /// class or device identifier, and any slot, unless they are given.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn vendor_identifier(mut self, vendor_identifier: Uuid) -> Self {
+        self.vendor_identifier = Some(vendor_identifier);
+        self
+    }
+
+    pub fn class_identifier(mut self, class_identifier: Uuid) -> Self {
+        self.class_identifier = Some(class_identifier);
+        self
+    }
+
+    pub fn device_identifier(mut self, device_identifier: Uuid) -> Self {
+        self.device_identifier = Some(device_identifier);
+        self
+    }
+
+    /// Only accept `slot` in component slot conditions.
+    pub fn slot(mut self, slot: u64) -> Self {
+        self.slot = Some(slot);
+        self
+    }
+
+    /// Fetch detached payloads from the files in `payload_dir`, each named
+    /// after the last segment of its URI.
+    pub fn payload_dir(mut self, payload_dir: PathBuf) -> Self {
+        self.payload_dir = Some(payload_dir);
+        self
+    }
+
+    /// The images written to the components of the device.
+    pub fn images(&self) -> &[Image] {
+        &self.images
+    }
+
+    /// The memory of the device as a single block, from the lowest to the
+    /// highest address written to, where unwritten bytes are `fill`.
+    /// Images are written in the order they were fetched; along with the
+    /// start address of the block.
+    pub fn memory_image(&self, fill: u8) -> Option<(u32, Vec<u8>)> {
+        let regions: Vec<(u32, &[u8])> = self
+            .images
+            .iter()
+            .filter_map(|image| Some((image.address()?, image.content.as_slice())))
+            .collect();
+
+        let start = regions.iter().map(|(address, _)| *address).min()?;
+        let end = regions
+            .iter()
+            .map(|(address, content)| *address as usize + content.len())
+            .max()?;
+
+        let mut memory = vec![fill; end - start as usize];
+
+        for (address, content) in regions {
+            let offset = (address - start) as usize;
+            memory[offset..offset + content.len()].copy_from_slice(content);
+        }
+
+        Some((start, memory))
+    }
+
+    /// Process the encoded `envelope`, running the procedures that its
+    /// manifest has sequences for.
+    pub fn process(&mut self, envelope: &[u8]) -> Result<Vec<Report>, SimulationError> {
+        let envelope = Envelope::decode(envelope)?;
+
+        let procedures = [
+            (
+                Procedure::Update,
+                [SuitConstant::PayloadFetch, SuitConstant::Install].as_slice(),
+            ),
+            (
+                Procedure::Invoke,
+                [
+                    SuitConstant::Validate,
+                    SuitConstant::Load,
+                    SuitConstant::Run,
+                ]
+                .as_slice(),
+            ),
+        ];
+
+        let mut reports = Vec::new();
+
+        for (procedure, sections) in procedures {
+            if !sections
+                .iter()
+                .any(|section| envelope.has_sequence(*section))
+            {
+                continue;
+            }
+
+            let mut records = Vec::new();
+            let mut runs = Vec::new();
+
+            let outcome = Processor {
+                device: self,
+                envelope: &envelope,
+                prefix: String::new(),
+                records: &mut records,
+                runs: &mut runs,
+            }
+            .procedure(sections);
+
+            reports.push(Report {
+                procedure,
+                records,
+                runs,
+                outcome,
+            });
+        }
+
+        Ok(reports)
+    }
+
+    fn image(&self, component: &Cbor, slot: Option<u64>) -> Option<&Image> {
+        self.images
+            .iter()
+            .find(|image| image.component == *component && image.slot == slot)
+    }
+
+    fn write(&mut self, image: Image) {
+        self.erase(&image.component.clone(), image.slot);
+        self.images.push(image);
+    }
+
+    fn erase(&mut self, component: &Cbor, slot: Option<u64>) {
+        self.images
+            .retain(|image| image.component != *component || image.slot != slot);
+    }
+}
+
+/// The parts of an envelope that processing needs.
+struct Envelope {
+    members: Vec<(Cbor, Cbor)>,
+    manifest_bytes: Vec<u8>,
+    manifest: Vec<(Cbor, Cbor)>,
+    components: Vec<Cbor>,
+    dependencies: Vec<Option<Digest>>,
+    common_sequence: Option<Cbor>,
+}
+
+impl Envelope {
+    fn decode(bytes: &[u8]) -> Result<Self, SimulationError> {
+        let members = match Cbor::deserialize(bytes).map_err(SimulationError::Decode)? {
+            Cbor::Tag(suit_constant::SUIT_ENVELOPE_TAG, content) => match *content {
+                Cbor::Map(members) => members,
+                _ => return Err(SimulationError::NoManifest),
+            },
+            Cbor::Map(members) => members,
+            _ => return Err(SimulationError::NoManifest),
+        };
+
+        let manifest_bytes = match member(&members, &SuitConstant::Manifest.into()) {
+            Some(Cbor::Bstr(manifest)) => manifest.clone(),
+            _ => return Err(SimulationError::NoManifest),
+        };
+
+        let manifest = match Cbor::deserialize(&manifest_bytes) {
+            Ok(Cbor::Map(manifest)) => manifest,
+            _ => return Err(SimulationError::NoManifest),
+        };
+
+        let common =
+            match member(&manifest, &SuitConstant::Common.into()).and_then(Cbor::decode_wrapped) {
+                Some(Cbor::Map(common)) => common,
+                _ => return Err(SimulationError::NoComponents),
+            };
+
+        let components = match member(&common, &SuitConstant::Components.into()) {
+            Some(Cbor::Array(components)) if !components.is_empty() => components.clone(),
+            _ => return Err(SimulationError::NoComponents),
+        };
+
+        let dependencies = match member(&common, &SuitConstant::Dependencies.into()) {
+            Some(Cbor::Array(dependencies)) => dependencies
+                .iter()
+                .map(|dependency| match dependency {
+                    Cbor::Map(dependency) => {
+                        member(dependency, &SuitConstant::DependencyDigest.into())
+                            .and_then(Digest::from_cbor)
+                    }
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let common_sequence = member(&common, &SuitConstant::CommonSequence.into()).cloned();
+
+        Ok(Self {
+            members,
+            manifest_bytes,
+            manifest,
+            components,
+            dependencies,
+            common_sequence,
+        })
+    }
+
+    fn has_sequence(&self, section: SuitConstant) -> bool {
+        member(&self.manifest, &section.into()).is_some()
+    }
+
+    /// The command sequence of `section`; taken from the envelope if it is
+    /// severed from the manifest, provided that it matches the digest the
+    /// manifest keeps in its place.
+    fn sequence(&self, section: SuitConstant) -> Result<Option<Cbor>, String> {
+        match member(&self.manifest, &section.into()) {
+            Some(digest @ Cbor::Array(_))
+                if matches!(section, SuitConstant::PayloadFetch | SuitConstant::Install) =>
+            {
+                let severed = match member(&self.members, &section.into()) {
+                    Some(Cbor::Bstr(severed)) => severed,
+                    Some(_) => return Err(String::from("the severed sequence is not wrapped")),
+                    None => {
+                        return Err(String::from(
+                            "the sequence is severed and not in the envelope",
+                        ))
+                    }
+                };
+
+                match Digest::from_cbor(digest).map(|digest| digest.matches(severed)) {
+                    Some(true) => Ok(Some(Cbor::Bstr(severed.clone()))),
+                    Some(false) => Err(String::from(
+                        "the severed sequence does not match its digest",
+                    )),
+                    None => Err(String::from("malformed or unsupported digest")),
+                }
+            }
+            Some(sequence) => Ok(Some(sequence.clone())),
+            None => Ok(None),
+        }
+    }
+
+    /// The item integrated in the envelope under `uri`.
+    fn integrated(&self, uri: &str) -> Option<&[u8]> {
+        match member(&self.members, &Cbor::Tstr(String::from(uri))) {
+            Some(Cbor::Bstr(bytes)) => Some(bytes),
+            _ => None,
+        }
+    }
+}
+
+/// The state of a procedure being run on a device.
+struct Processor<'a> {
+    device: &'a mut Device,
+    envelope: &'a Envelope,
+    prefix: String,
+    records: &'a mut Vec<Record>,
+    runs: &'a mut Vec<Run>,
+}
+
+/// The parameters and selection, which are restored when an alternative
+/// of a try-each directive fails.
+#[derive(Clone)]
+struct State {
+    components: Vec<Vec<(Cbor, Cbor)>>,
+    dependencies: Vec<Vec<(Cbor, Cbor)>>,
+    selection: Vec<Target>,
+}
+
+impl<'a> Processor<'a> {
+    /// Run the common sequence, followed by the sequences of `sections`.
+    fn procedure(&mut self, sections: &[SuitConstant]) -> Result<(), Failure> {
+        let mut state = State {
+            components: vec![Vec::new(); self.envelope.components.len()],
+            dependencies: vec![Vec::new(); self.envelope.dependencies.len()],
+            selection: vec![Target::Component(0)],
+        };
+
+        if let Some(sequence) = &self.envelope.common_sequence {
+            self.sequence(
+                &mut state,
+                SuitConstant::CommonSequence.name(),
+                sequence,
+                *sections.first().unwrap(),
+                0,
+            )?;
+        }
+
+        for section in sections {
+            let sequence = self
+                .envelope
+                .sequence(*section)
+                .map_err(|reason| self.failure(section.name(), reason))?;
+
+            if let Some(sequence) = sequence {
+                self.sequence(&mut state, section.name(), &sequence, *section, 0)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn failure(&self, path: &str, reason: String) -> Failure {
+        Failure {
+            path: format!("{}{}", self.prefix, path),
+            reason,
+            is_condition: false,
+        }
+    }
+
+    /// Run the commands of `sequence`, found at `path` in `section` and
+    /// nested `depth` levels deep.
+    fn sequence(
+        &mut self,
+        state: &mut State,
+        path: &str,
+        sequence: &Cbor,
+        section: SuitConstant,
+        depth: usize,
+    ) -> Result<(), Failure> {
+        if depth > MAX_SEQUENCE_DEPTH {
+            return Err(self.failure(
+                path,
+                String::from("command sequences are nested too deeply"),
+            ));
+        }
+
+        let items = match sequence.decode_wrapped() {
+            Some(Cbor::Array(items)) if items.len() % 2 == 0 => items,
+            _ => return Err(self.failure(path, String::from("malformed command sequence"))),
+        };
+
+        for (position, pair) in items.chunks(2).enumerate() {
+            let path = format!("{}[{}]", path, position);
+            self.command(state, &path, &pair[0], &pair[1], section, depth)?;
+        }
+
+        Ok(())
+    }
+
+    fn command(
+        &mut self,
+        state: &mut State,
+        path: &str,
+        command: &Cbor,
+        argument: &Cbor,
+        section: SuitConstant,
+        depth: usize,
+    ) -> Result<(), Failure> {
+        use SuitConstant::*;
+
+        let constant = match SuitConstant::lookup(KeySpace::Command, command) {
+            Some(constant) => constant,
+            None => {
+                let name = inspect::render_plain(command);
+                return Err(self.failure(path, format!("unknown command `{}`", name)));
+            }
+        };
+
+        match constant {
+            DirectiveSetComponentIndex => {
+                let indices = self.indices(path, argument, self.envelope.components.len())?;
+                state.selection = indices.into_iter().map(Target::Component).collect();
+                Ok(())
+            }
+            DirectiveSetDependencyIndex => {
+                let indices = self.indices(path, argument, self.envelope.dependencies.len())?;
+                state.selection = indices.into_iter().map(Target::Dependency).collect();
+                Ok(())
+            }
+            DirectiveOverrideParameters => {
+                let parameters = match argument {
+                    Cbor::Map(parameters) => parameters,
+                    _ => {
+                        return Err(self.failure(path, String::from("the parameters are not a map")))
+                    }
+                };
+
+                for target in state.selection.clone() {
+                    let target = parameters_of(state, target);
+
+                    for (key, value) in parameters {
+                        target.retain(|(existing, _)| existing != key);
+                        target.push((key.clone(), value.clone()));
+                    }
+                }
+
+                Ok(())
+            }
+            DirectiveRunSequence => {
+                let result = self.sequence(state, path, argument, section, depth + 1);
+
+                let is_soft_failure = matches!(
+                    state.selection.first(),
+                    Some(target)
+                        if parameter(state, *target, ParameterSoftFailure) == Some(&Cbor::True)
+                );
+
+                match result {
+                    // A condition failing in a nested sequence only ends the
+                    // sequence itself when soft failure is set for the
+                    // current component.
+                    Err(failure) if failure.is_condition && is_soft_failure => Ok(()),
+                    result => result,
+                }
+            }
+            DirectiveTryEach => {
+                let alternatives = match argument {
+                    Cbor::Array(alternatives) => alternatives,
+                    _ => {
+                        return Err(self.failure(path, String::from("try-each needs alternatives")))
+                    }
+                };
+
+                for (index, alternative) in alternatives.iter().enumerate() {
+                    let saved_state = state.clone();
+                    let saved_images = self.device.images.clone();
+
+                    let alternative_path = format!("{}[{}]", path, index);
+
+                    match self.sequence(state, &alternative_path, alternative, section, depth + 1) {
+                        Ok(()) => return Ok(()),
+                        Err(failure) if failure.is_condition => {
+                            *state = saved_state;
+                            self.device.images = saved_images;
+                        }
+                        Err(failure) => return Err(failure),
+                    }
+                }
+
+                // Each of the alternatives is rejected by a condition.
+                Err(Failure {
+                    is_condition: true,
+                    ..self.failure(path, String::from("none of the alternatives succeeds"))
+                })
+            }
+            _ => {
+                let policy = match argument {
+                    Cbor::Uint(policy) => *policy,
+                    _ => return Err(self.failure(path, String::from("malformed reporting policy"))),
+                };
+
+                for target in state.selection.clone() {
+                    let result = self.apply(state, path, constant, target, section);
+                    let success = result.is_ok();
+
+                    let (record, system_info) = match success {
+                        true => (policy & 0b0001 != 0, policy & 0b0100 != 0),
+                        false => (policy & 0b0010 != 0, policy & 0b1000 != 0),
+                    };
+
+                    if record {
+                        self.records.push(Record {
+                            path: format!("{}{}", self.prefix, path),
+                            command: constant,
+                            target,
+                            success,
+                            parameters: match system_info {
+                                true => Some(parameters_of(state, target).clone()),
+                                false => None,
+                            },
+                        });
+                    }
+
+                    result.map_err(|reason| Failure {
+                        is_condition: matches!(
+                            constant,
+                            ConditionVendorIdentifier
+                                | ConditionClassIdentifier
+                                | ConditionDeviceIdentifier
+                                | ConditionImageMatch
+                                | ConditionComponentSlot
+                                | ConditionAbort
+                        ),
+                        ..self.failure(path, reason)
+                    })?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// The indices of a set-index directive, which must be below `count`.
+    fn indices(&self, path: &str, argument: &Cbor, count: usize) -> Result<Vec<usize>, Failure> {
+        let indices: Vec<u64> = match argument {
+            Cbor::True => return Ok((0..count).collect()),
+            Cbor::Uint(index) => vec![*index],
+            Cbor::Array(indices) => indices
+                .iter()
+                .map(|index| match index {
+                    Cbor::Uint(index) => Ok(*index),
+                    _ => Err(self.failure(path, String::from("malformed index"))),
+                })
+                .collect::<Result<_, _>>()?,
+            _ => return Err(self.failure(path, String::from("malformed index"))),
+        };
+
+        match indices.iter().find(|index| **index >= count as u64) {
+            Some(index) => Err(self.failure(path, format!("index {} is out of bounds", index))),
+            None => Ok(indices.into_iter().map(|index| index as usize).collect()),
+        }
+    }
+
+    /// Apply a condition or directive to `target`.
+    fn apply(
+        &mut self,
+        state: &mut State,
+        path: &str,
+        command: SuitConstant,
+        target: Target,
+        section: SuitConstant,
+    ) -> Result<(), String> {
+        use SuitConstant::*;
+
+        let identity = |parameter_key: SuitConstant, expected: Option<Uuid>| match (
+            parameter(state, target, parameter_key),
+            expected,
+        ) {
+            (None, _) => Err(format!("no {} is given", parameter_key.name())),
+            (Some(_), None) => Ok(()),
+            (Some(Cbor::Bstr(given)), Some(expected)) if *given == expected.0 => Ok(()),
+            (Some(_), Some(_)) => Err(format!("the {} does not match", parameter_key.name())),
+        };
+
+        match (command, target) {
+            (ConditionVendorIdentifier, _) => {
+                identity(ParameterVendorIdentifier, self.device.vendor_identifier)
+            }
+            (ConditionClassIdentifier, _) => {
+                identity(ParameterClassIdentifier, self.device.class_identifier)
+            }
+            (ConditionDeviceIdentifier, _) => {
+                identity(ParameterDeviceIdentifier, self.device.device_identifier)
+            }
+            (ConditionAbort, _) => Err(String::from("the manifest aborts")),
+            (ConditionComponentSlot, _) => match (
+                parameter(state, target, ParameterComponentSlot),
+                self.device.slot,
+            ) {
+                (Some(Cbor::Uint(_)), None) => Ok(()),
+                (Some(Cbor::Uint(slot)), Some(accepted)) if *slot == accepted => Ok(()),
+                (Some(Cbor::Uint(slot)), Some(_)) => Err(format!("slot {} is not accepted", slot)),
+                _ => Err(String::from("no component slot is given")),
+            },
+            (ConditionImageMatch, Target::Component(index)) => {
+                let digest = parameter(state, target, ParameterImageDigest)
+                    .ok_or_else(|| String::from("no image digest is given"))?;
+                let digest = Digest::from_cbor(digest)
+                    .ok_or_else(|| String::from("malformed or unsupported image digest"))?;
+
+                let image = self
+                    .device
+                    .image(&self.envelope.components[index], slot(state, target))
+                    .ok_or_else(|| String::from("the component holds no image"))?;
+
+                if let Some(Cbor::Uint(size)) = parameter(state, target, ParameterImageSize) {
+                    if *size != image.bytes.len() as u64 {
+                        return Err(format!(
+                            "the image is {} bytes, but {} bytes are expected",
+                            image.bytes.len(),
+                            size
+                        ));
+                    }
+                }
+
+                match digest.matches(&image.bytes) {
+                    true => Ok(()),
+                    false => Err(String::from("the image does not match its digest")),
+                }
+            }
+            (DirectiveFetch, _) => {
+                let uri = match parameter(state, target, ParameterUri) {
+                    Some(Cbor::Tstr(uri)) => uri.clone(),
+                    _ => return Err(String::from("no URI to fetch from is given")),
+                };
+
+                if parameter(state, target, ParameterEncryptionInfo).is_some() {
+                    return Err(format!("`{}` is encrypted, which is not simulated", uri));
+                }
+
+                let bytes = self.fetch(&uri)?;
+
+                match target {
+                    Target::Component(index) => {
+                        let content = match uri.starts_with("cp:") {
+                            true => payload::decompress(&bytes, MAX_CONTENT_SIZE)
+                                .ok_or_else(|| format!("`{}` cannot be decompressed", uri))?,
+                            false => bytes.clone(),
+                        };
+
+                        self.device.write(Image {
+                            component: self.envelope.components[index].clone(),
+                            slot: slot(state, target),
+                            bytes,
+                            content,
+                        });
+                    }
+                    Target::Dependency(_) => {
+                        if !self.device.dependencies.contains(&bytes) {
+                            self.device.dependencies.push(bytes);
+                        }
+                    }
+                }
+
+                Ok(())
+            }
+            (DirectiveCopy | DirectiveSwap, Target::Component(index)) => {
+                let source = match parameter(state, target, ParameterSourceComponent) {
+                    Some(Cbor::Uint(source))
+                        if (*source as usize) < self.envelope.components.len() =>
+                    {
+                        *source as usize
+                    }
+                    Some(_) => return Err(String::from("the source component is out of bounds")),
+                    None => return Err(String::from("no source component is given")),
+                };
+
+                let source_slot = slot(state, Target::Component(source));
+                let source_image = self
+                    .device
+                    .image(&self.envelope.components[source], source_slot)
+                    .cloned()
+                    .ok_or_else(|| String::from("the source component holds no image"))?;
+
+                let component = self.envelope.components[index].clone();
+                let destination_slot = slot(state, target);
+
+                // Swapping with an empty component leaves the source empty.
+                if command == DirectiveSwap {
+                    match self.device.image(&component, destination_slot).cloned() {
+                        Some(destination_image) => self.device.write(Image {
+                            component: source_image.component.clone(),
+                            slot: source_slot,
+                            ..destination_image
+                        }),
+                        None => self.device.erase(&source_image.component, source_slot),
+                    }
+                }
+
+                self.device.write(Image {
+                    component,
+                    slot: destination_slot,
+                    ..source_image
+                });
+
+                Ok(())
+            }
+            (DirectiveRun, Target::Component(index)) => {
+                let arguments = match parameter(state, target, ParameterRunArgs) {
+                    Some(Cbor::Bstr(arguments)) => Some(arguments.clone()),
+                    _ => None,
+                };
+
+                self.runs.push(self::Run {
+                    path: format!("{}{}", self.prefix, path),
+                    component: index,
+                    arguments,
+                });
+
+                Ok(())
+            }
+            (DirectiveProcessDependency, Target::Dependency(index)) => {
+                self.dependency(index, section)
+            }
+            (_, target) => Err(format!("{} cannot apply to {}", command.name(), target)),
+        }
+    }
+
+    /// Fetch the payload at `uri`, from the envelope if it is integrated,
+    /// and from the payload directory otherwise.
+    fn fetch(&self, uri: &str) -> Result<Vec<u8>, String> {
+        if let Some(bytes) = self.envelope.integrated(uri) {
+            return Ok(bytes.to_vec());
+        }
+
+        let name = uri.rsplit('/').next().unwrap_or_default();
+
+        match &self.device.payload_dir {
+            Some(directory) if !name.is_empty() && uri.contains("://") => {
+                std::fs::read(directory.join(name))
+                    .map_err(|_| format!("`{}` cannot be fetched", uri))
+            }
+            _ => Err(format!("`{}` cannot be fetched", uri)),
+        }
+    }
+
+    /// Process the envelope of the dependency at `index`, as fetched in
+    /// this or an earlier procedure, running its common sequence and its
+    /// sequence for `section`.
+    fn dependency(&mut self, index: usize, section: SuitConstant) -> Result<(), String> {
+        let digest = self.envelope.dependencies[index]
+            .as_ref()
+            .ok_or_else(|| String::from("the dependency has no valid digest"))?;
+
+        let dependency = self
+            .device
+            .dependencies
+            .iter()
+            .filter_map(|envelope| Envelope::decode(envelope).ok())
+            .find(|envelope| digest.matches(&envelope.manifest_bytes))
+            .ok_or_else(|| String::from("no envelope matching the dependency is fetched"))?;
+
+        let mut processor = Processor {
+            device: &mut *self.device,
+            envelope: &dependency,
+            prefix: format!("{}d:{}/", self.prefix, index),
+            records: &mut *self.records,
+            runs: &mut *self.runs,
+        };
+
+        processor
+            .procedure(&[section])
+            .map_err(|failure| format!("the dependency fails at {}", failure))
+    }
+}
+
+/// The parameters of `target`.
+fn parameters_of(state: &mut State, target: Target) -> &mut Vec<(Cbor, Cbor)> {
+    match target {
+        Target::Component(index) => &mut state.components[index],
+        Target::Dependency(index) => &mut state.dependencies[index],
+    }
+}
+
+fn parameter(state: &State, target: Target, key: SuitConstant) -> Option<&Cbor> {
+    let parameters = match target {
+        Target::Component(index) => &state.components[index],
+        Target::Dependency(index) => &state.dependencies[index],
+    };
+
+    member(parameters, &key.into())
+}
+
+/// The slot of `target` that images are written to and read from.
+fn slot(state: &State, target: Target) -> Option<u64> {
+    match parameter(state, target, SuitConstant::ParameterComponentSlot) {
+        Some(Cbor::Uint(slot)) => Some(*slot),
+        _ => None,
+    }
+}
+
+fn member<'m>(entries: &'m [(Cbor, Cbor)], key: &Cbor) -> Option<&'m Cbor> {
+    entries
+        .iter()
+        .find(|(entry, _)| entry == key)
+        .map(|(_, value)| value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::description::Description;
+    use crate::digest::DigestAlgorithm;
+    use crate::envelope::EnvelopeBuilder;
+    use crate::manifest::{
+        Command, ComponentIdentifier, ManifestBuilder, Parameter, ReportingPolicy, SeverableMember,
+    };
+    use crate::payload::Payload;
+
+    fn outcomes(reports: &[Report]) -> Vec<(Procedure, Result<(), String>)> {
+        reports
+            .iter()
+            .map(|report| {
+                let outcome = report.outcome.as_ref().map_err(Failure::to_string);
+                (report.procedure, outcome.map(|_| ()))
+            })
+            .collect()
+    }
+
+    fn payload(index: usize, start_address: u32, bytes: &[u8]) -> Payload {
+        Payload {
+            uri: format!("p:{}", index),
+            start_address,
+            size: bytes.len(),
+            memory_size: bytes.len(),
+            bytes: bytes.to_vec(),
+            encryption: None,
+        }
+    }
+
+    #[test]
+    fn processes_generated_envelopes() {
+        let payloads = vec![
+            payload(0, 0x1000, &[0x01, 0x02, 0x03, 0x04]),
+            payload(1, 0x1008, &[0x05, 0x06]),
+        ];
+
+        let manifest = ManifestBuilder::for_payloads(&payloads, DigestAlgorithm::Sha256).build();
+        let envelope = EnvelopeBuilder::new(manifest)
+            .integrate(payloads)
+            .build()
+            .serialize();
+
+        let mut device = Device::new();
+        let reports = device.process(&envelope).unwrap();
+
+        assert_eq!(
+            outcomes(&reports),
+            vec![(Procedure::Update, Ok(())), (Procedure::Invoke, Ok(()))]
+        );
+        assert_eq!(reports[1].runs.len(), 1);
+        assert_eq!(reports[1].runs[0].component, 0);
+
+        assert_eq!(
+            device.memory_image(0xff),
+            Some((
+                0x1000,
+                vec![0x01, 0x02, 0x03, 0x04, 0xff, 0xff, 0xff, 0xff, 0x05, 0x06]
+            ))
+        );
+    }
+
+    #[test]
+    fn processes_envelopes_of_descriptions_with_default_sequences() {
+        let directory = std::env::temp_dir().join(format!("suit-simulator-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("application.bin"), [0x01, 0x02, 0x03]).unwrap();
+        std::fs::write(directory.join("assets.bin"), [0x04]).unwrap();
+
+        let description = Description::from_toml(
+            r#"
+            [[component]]
+            files = [{ path = "application.bin", address = 0x2000 }]
+
+            [[component]]
+            files = [{ path = "assets.bin", address = 0x3000 }]
+            "#,
+            &directory,
+        )
+        .unwrap();
+
+        let (manifest, payloads) = description.build(None).unwrap();
+        std::fs::remove_dir_all(directory).unwrap();
+
+        let envelope = EnvelopeBuilder::new(manifest.build())
+            .integrate(payloads)
+            .build()
+            .serialize();
+
+        let mut device = Device::new();
+        let reports = device.process(&envelope).unwrap();
+
+        assert_eq!(
+            outcomes(&reports),
+            vec![(Procedure::Update, Ok(())), (Procedure::Invoke, Ok(()))]
+        );
+
+        let contents: Vec<(Option<u32>, &[u8])> = device
+            .images()
+            .iter()
+            .map(|image| (image.address(), image.content.as_slice()))
+            .collect();
+
+        assert_eq!(
+            contents,
+            vec![
+                (Some(0x2000), [0x01, 0x02, 0x03].as_slice()),
+                (Some(0x3000), [0x04].as_slice())
+            ]
+        );
+    }
+
+    #[test]
+    fn moves_on_to_the_next_alternative_only_when_a_condition_fails() {
+        let image = payload(0, 0x1000, &[0x01, 0x02]);
+
+        let fetch = |uri: &str| {
+            vec![
+                Command::DirectiveOverrideParameters(vec![Parameter::Uri(String::from(uri))]),
+                Command::DirectiveFetch(ReportingPolicy::all()),
+            ]
+        };
+
+        let envelope = |first_alternative: Vec<Command>| {
+            let install = vec![Command::DirectiveTryEach(vec![
+                first_alternative,
+                fetch(&image.uri),
+            ])];
+
+            let manifest = ManifestBuilder::new(vec![ComponentIdentifier(0x1000)])
+                .install(install)
+                .build();
+
+            EnvelopeBuilder::new(manifest)
+                .integrate(vec![image.clone()])
+                .build()
+                .serialize()
+        };
+
+        let rejected = envelope(vec![Command::ConditionAbort(ReportingPolicy::all())]);
+        let reports = Device::new().process(&rejected).unwrap();
+        assert_eq!(outcomes(&reports), vec![(Procedure::Update, Ok(()))]);
+
+        let aborted = envelope(fetch("p:1"));
+        let reports = Device::new().process(&aborted).unwrap();
+
+        assert_eq!(reports.len(), 1);
+        let failure = reports[0].outcome.as_ref().unwrap_err();
+        assert_eq!(failure.path, "install[0][0][1]");
+        assert!(!failure.is_condition);
+    }
+
+    #[test]
+    fn rejects_tampered_severed_sequences() {
+        let image = payload(0, 0x1000, &[0x01, 0x02]);
+
+        let install = |commands: Vec<Command>| {
+            let mut sequence = vec![Command::DirectiveOverrideParameters(
+                Parameter::for_fetching(&image),
+            )];
+            sequence.extend(commands);
+            sequence
+        };
+
+        let manifest = ManifestBuilder::new(vec![ComponentIdentifier(0x1000)])
+            .install(install(vec![Command::DirectiveFetch(
+                ReportingPolicy::all(),
+            )]))
+            .sever(vec![SeverableMember::Install])
+            .build();
+
+        let envelope = EnvelopeBuilder::new(manifest)
+            .integrate(vec![image.clone()])
+            .build()
+            .serialize();
+
+        let reports = Device::new().process(&envelope).unwrap();
+        assert_eq!(outcomes(&reports), vec![(Procedure::Update, Ok(()))]);
+
+        // Swap the severed install sequence for one that does nothing.
+        let tampered = match Cbor::deserialize(&envelope).unwrap() {
+            Cbor::Tag(tag, content) => match *content {
+                Cbor::Map(members) => Cbor::Tag(
+                    tag,
+                    Box::new(Cbor::Map(
+                        members
+                            .into_iter()
+                            .map(|(key, value)| match key == SuitConstant::Install.into() {
+                                true => (key, Cbor::from(install(Vec::new())).serialize().into()),
+                                false => (key, value),
+                            })
+                            .collect(),
+                    )),
+                ),
+                _ => panic!("the envelope is not a map"),
+            },
+            _ => panic!("the envelope is not tagged"),
+        };
+
+        let reports = Device::new().process(&tampered.serialize()).unwrap();
+
+        assert_eq!(
+            outcomes(&reports),
+            vec![(
+                Procedure::Update,
+                Err(String::from(
+                    "install: the severed sequence does not match its digest"
+                ))
+            )]
+        );
+    }
+
+    #[test]
+    fn limits_the_nesting_of_sequences() {
+        let nested = |depth| {
+            let mut sequence = vec![Command::DirectiveOverrideParameters(Vec::new())];
+
+            for _ in 0..depth {
+                sequence = vec![Command::DirectiveRunSequence(sequence)];
+            }
+
+            let manifest = ManifestBuilder::new(vec![ComponentIdentifier(0)])
+                .validate(sequence)
+                .build();
+
+            EnvelopeBuilder::new(manifest).build().serialize()
+        };
+
+        let reports = Device::new().process(&nested(MAX_SEQUENCE_DEPTH)).unwrap();
+        assert_eq!(outcomes(&reports), vec![(Procedure::Invoke, Ok(()))]);
+
+        let reports = Device::new()
+            .process(&nested(MAX_SEQUENCE_DEPTH + 1))
+            .unwrap();
+        let path = format!("validate{}", "[0]".repeat(MAX_SEQUENCE_DEPTH + 1));
+
+        assert_eq!(
+            outcomes(&reports),
+            vec![(
+                Procedure::Invoke,
+                Err(format!("{}: command sequences are nested too deeply", path))
+            )]
+        );
+    }
+}
@@ -1,7 +1,11 @@
 use crate::Cbor;
 
 pub const SUIT_ENVELOPE_TAG: u64 = 107;
+pub const COSE_SIGN1_TAG: u64 = 18;
+pub const COSE_MAC0_TAG: u64 = 17;
+pub const COSE_ENCRYPT_TAG: u64 = 96;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum SuitConstant {
     AuthenticationWrapper,
     Manifest,
@@ -12,9 +16,37 @@ pub enum SuitConstant {
     CoseAlgSha512,
     CoseAlgShake256,
 
+    CoseHeaderAlgorithm,
+    CoseHeaderIv,
+    CoseHeaderEphemeralKey,
+    CoseAlgEs256,
+    CoseAlgEs384,
+    CoseAlgEdDsa,
+    CoseAlgHmac256,
+    CoseAlgHmac384,
+    CoseAlgHmac512,
+
+    CoseAlgA128Gcm,
+    CoseAlgA256Gcm,
+    CoseAlgA128Ctr,
+    CoseAlgA256Ctr,
+    CoseAlgA128Kw,
+    CoseAlgA256Kw,
+    CoseAlgEcdhEsA128Kw,
+    CoseAlgEcdhEsA256Kw,
+
+    CoseKeyType,
+    CoseKeyCurve,
+    CoseKeyX,
+    CoseKeyY,
+    CoseKeyTypeEc2,
+    CoseCurveP256,
+    CoseCurveP384,
+
     ManifestVersion,
     ManifestSequenceNumber,
     Common,
+    Dependencies,
     Components,
     CommonSequence,
     ReferenceUri,
@@ -25,6 +57,9 @@ pub enum SuitConstant {
     Run,
     Text,
 
+    DependencyDigest,
+    DependencyPrefix,
+
     ConditionVendorIdentifier,
     ConditionClassIdentifier,
     ConditionImageMatch,
@@ -33,7 +68,9 @@ pub enum SuitConstant {
     ConditionDeviceIdentifier,
 
     DirectiveSetComponentIndex,
+    DirectiveSetDependencyIndex,
     DirectiveTryEach,
+    DirectiveProcessDependency,
     DirectiveOverrideParameters,
     DirectiveFetch,
     DirectiveCopy,
@@ -52,6 +89,7 @@ pub enum SuitConstant {
     ParameterSourceComponent,
     ParameterRunArgs,
     ParameterDeviceIdentifier,
+    ParameterEncryptionInfo,
 
     TextManifestDescription,
     TextUpdateDescription,
@@ -80,9 +118,37 @@ impl From<SuitConstant> for Cbor {
             CoseAlgSha512 => Nint(44),
             CoseAlgShake256 => Nint(45),
 
+            CoseHeaderAlgorithm => Uint(1),
+            CoseHeaderIv => Uint(5),
+            CoseHeaderEphemeralKey => Nint(1),
+            CoseAlgEs256 => Nint(7),
+            CoseAlgEs384 => Nint(35),
+            CoseAlgEdDsa => Nint(8),
+            CoseAlgHmac256 => Uint(5),
+            CoseAlgHmac384 => Uint(6),
+            CoseAlgHmac512 => Uint(7),
+
+            CoseAlgA128Gcm => Uint(1),
+            CoseAlgA256Gcm => Uint(3),
+            CoseAlgA128Ctr => Nint(65534),
+            CoseAlgA256Ctr => Nint(65532),
+            CoseAlgA128Kw => Nint(3),
+            CoseAlgA256Kw => Nint(5),
+            CoseAlgEcdhEsA128Kw => Nint(29),
+            CoseAlgEcdhEsA256Kw => Nint(31),
+
+            CoseKeyType => Uint(1),
+            CoseKeyCurve => Nint(1),
+            CoseKeyX => Nint(2),
+            CoseKeyY => Nint(3),
+            CoseKeyTypeEc2 => Uint(2),
+            CoseCurveP256 => Uint(1),
+            CoseCurveP384 => Uint(2),
+
             ManifestVersion => Uint(1),
             ManifestSequenceNumber => Uint(2),
             Common => Uint(3),
+            Dependencies => Uint(1),
             Components => Uint(2),
             CommonSequence => Uint(4),
             ReferenceUri => Uint(4),
@@ -93,6 +159,9 @@ impl From<SuitConstant> for Cbor {
             Run => Uint(12),
             Text => Uint(13),
 
+            DependencyDigest => Uint(1),
+            DependencyPrefix => Uint(2),
+
             ConditionVendorIdentifier => Uint(1),
             ConditionClassIdentifier => Uint(2),
             ConditionImageMatch => Uint(3),
@@ -101,7 +170,9 @@ impl From<SuitConstant> for Cbor {
             ConditionDeviceIdentifier => Uint(24),
 
             DirectiveSetComponentIndex => Uint(12),
+            DirectiveSetDependencyIndex => Uint(13),
             DirectiveTryEach => Uint(15),
+            DirectiveProcessDependency => Uint(18),
             DirectiveOverrideParameters => Uint(20),
             DirectiveFetch => Uint(21),
             DirectiveCopy => Uint(22),
@@ -120,6 +191,7 @@ impl From<SuitConstant> for Cbor {
             ParameterSourceComponent => Uint(22),
             ParameterRunArgs => Uint(23),
             ParameterDeviceIdentifier => Uint(24),
+            ParameterEncryptionInfo => Uint(18),
 
             TextManifestDescription => Uint(1),
             TextUpdateDescription => Uint(2),
@@ -134,3 +206,224 @@ impl From<SuitConstant> for Cbor {
         }
     }
 }
+
+/// The maps and sequences in which SUIT constants are used as keys.
+/// The same numeric value has a different meaning in each key space.
+#[derive(Debug, Clone, Copy)]
+pub enum KeySpace {
+    Envelope,
+    CoseHeader,
+    CoseAlgorithm,
+    CoseKey,
+    Manifest,
+    Common,
+    Dependency,
+    Command,
+    Parameter,
+    Text,
+    TextComponent,
+}
+
+impl SuitConstant {
+    /// Find the constant that `key` denotes within `key_space`.
+    pub fn lookup(key_space: KeySpace, key: &Cbor) -> Option<SuitConstant> {
+        SuitConstant::members(key_space)
+            .iter()
+            .copied()
+            .find(|constant| Cbor::from(*constant) == *key)
+    }
+
+    /// All constants that belong to `key_space`.
+    pub fn members(key_space: KeySpace) -> &'static [SuitConstant] {
+        use SuitConstant::*;
+
+        match key_space {
+            KeySpace::Envelope => &[AuthenticationWrapper, Manifest, PayloadFetch, Install, Text],
+            KeySpace::CoseHeader => &[CoseHeaderAlgorithm, CoseHeaderIv, CoseHeaderEphemeralKey],
+            KeySpace::CoseAlgorithm => &[
+                CoseAlgSha256,
+                CoseAlgShake128,
+                CoseAlgSha384,
+                CoseAlgSha512,
+                CoseAlgShake256,
+                CoseAlgEs256,
+                CoseAlgEs384,
+                CoseAlgEdDsa,
+                CoseAlgHmac256,
+                CoseAlgHmac384,
+                CoseAlgHmac512,
+                CoseAlgA128Gcm,
+                CoseAlgA256Gcm,
+                CoseAlgA128Ctr,
+                CoseAlgA256Ctr,
+                CoseAlgA128Kw,
+                CoseAlgA256Kw,
+                CoseAlgEcdhEsA128Kw,
+                CoseAlgEcdhEsA256Kw,
+            ],
+            KeySpace::CoseKey => &[CoseKeyType, CoseKeyCurve, CoseKeyX, CoseKeyY],
+            KeySpace::Manifest => &[
+                ManifestVersion,
+                ManifestSequenceNumber,
+                Common,
+                ReferenceUri,
+                PayloadFetch,
+                Install,
+                Validate,
+                Load,
+                Run,
+                Text,
+            ],
+            KeySpace::Common => &[Dependencies, Components, CommonSequence],
+            KeySpace::Dependency => &[DependencyDigest, DependencyPrefix],
+            KeySpace::Command => &[
+                ConditionVendorIdentifier,
+                ConditionClassIdentifier,
+                ConditionImageMatch,
+                ConditionComponentSlot,
+                ConditionAbort,
+                ConditionDeviceIdentifier,
+                DirectiveSetComponentIndex,
+                DirectiveSetDependencyIndex,
+                DirectiveTryEach,
+                DirectiveProcessDependency,
+                DirectiveOverrideParameters,
+                DirectiveFetch,
+                DirectiveCopy,
+                DirectiveRun,
+                DirectiveSwap,
+                DirectiveRunSequence,
+            ],
+            KeySpace::Parameter => &[
+                ParameterVendorIdentifier,
+                ParameterClassIdentifier,
+                ParameterImageDigest,
+                ParameterComponentSlot,
+                ParameterStrictOrder,
+                ParameterSoftFailure,
+                ParameterImageSize,
+                ParameterUri,
+                ParameterSourceComponent,
+                ParameterRunArgs,
+                ParameterDeviceIdentifier,
+                ParameterEncryptionInfo,
+            ],
+            KeySpace::Text => &[
+                TextManifestDescription,
+                TextUpdateDescription,
+                TextManifestJsonSource,
+                TextManifestYamlSource,
+            ],
+            KeySpace::TextComponent => &[
+                TextVendorName,
+                TextModelName,
+                TextVendorDomain,
+                TextModelInfo,
+                TextComponentDescription,
+                TextComponentVersion,
+            ],
+        }
+    }
+
+    /// Symbolic name of the constant, as used when printing manifests.
+    pub fn name(&self) -> &'static str {
+        use SuitConstant::*;
+
+        match self {
+            AuthenticationWrapper => "authentication-wrapper",
+            Manifest => "manifest",
+
+            CoseAlgSha256 => "sha-256",
+            CoseAlgShake128 => "shake128",
+            CoseAlgSha384 => "sha-384",
+            CoseAlgSha512 => "sha-512",
+            CoseAlgShake256 => "shake256",
+
+            CoseHeaderAlgorithm => "alg",
+            CoseHeaderIv => "iv",
+            CoseHeaderEphemeralKey => "ephemeral-key",
+            CoseAlgEs256 => "es256",
+            CoseAlgEs384 => "es384",
+            CoseAlgEdDsa => "eddsa",
+            CoseAlgHmac256 => "hmac-256",
+            CoseAlgHmac384 => "hmac-384",
+            CoseAlgHmac512 => "hmac-512",
+
+            CoseAlgA128Gcm => "a128gcm",
+            CoseAlgA256Gcm => "a256gcm",
+            CoseAlgA128Ctr => "a128ctr",
+            CoseAlgA256Ctr => "a256ctr",
+            CoseAlgA128Kw => "a128kw",
+            CoseAlgA256Kw => "a256kw",
+            CoseAlgEcdhEsA128Kw => "ecdh-es+a128kw",
+            CoseAlgEcdhEsA256Kw => "ecdh-es+a256kw",
+
+            CoseKeyType => "kty",
+            CoseKeyCurve => "crv",
+            CoseKeyX => "x",
+            CoseKeyY => "y",
+            CoseKeyTypeEc2 => "ec2",
+            CoseCurveP256 => "p-256",
+            CoseCurveP384 => "p-384",
+
+            ManifestVersion => "manifest-version",
+            ManifestSequenceNumber => "manifest-sequence-number",
+            Common => "common",
+            Dependencies => "dependencies",
+            Components => "components",
+            CommonSequence => "common-sequence",
+            ReferenceUri => "reference-uri",
+            PayloadFetch => "payload-fetch",
+            Install => "install",
+            Validate => "validate",
+            Load => "load",
+            Run => "run",
+            Text => "text",
+
+            DependencyDigest => "dependency-digest",
+            DependencyPrefix => "dependency-prefix",
+
+            ConditionVendorIdentifier => "condition-vendor-identifier",
+            ConditionClassIdentifier => "condition-class-identifier",
+            ConditionImageMatch => "condition-image-match",
+            ConditionComponentSlot => "condition-component-slot",
+            ConditionAbort => "condition-abort",
+            ConditionDeviceIdentifier => "condition-device-identifier",
+
+            DirectiveSetComponentIndex => "directive-set-component-index",
+            DirectiveSetDependencyIndex => "directive-set-dependency-index",
+            DirectiveTryEach => "directive-try-each",
+            DirectiveProcessDependency => "directive-process-dependency",
+            DirectiveOverrideParameters => "directive-override-parameters",
+            DirectiveFetch => "directive-fetch",
+            DirectiveCopy => "directive-copy",
+            DirectiveRun => "directive-run",
+            DirectiveSwap => "directive-swap",
+            DirectiveRunSequence => "directive-run-sequence",
+
+            ParameterVendorIdentifier => "parameter-vendor-identifier",
+            ParameterClassIdentifier => "parameter-class-identifier",
+            ParameterImageDigest => "parameter-image-digest",
+            ParameterComponentSlot => "parameter-component-slot",
+            ParameterStrictOrder => "parameter-strict-order",
+            ParameterSoftFailure => "parameter-soft-failure",
+            ParameterImageSize => "parameter-image-size",
+            ParameterUri => "parameter-uri",
+            ParameterSourceComponent => "parameter-source-component",
+            ParameterRunArgs => "parameter-run-args",
+            ParameterDeviceIdentifier => "parameter-device-identifier",
+            ParameterEncryptionInfo => "parameter-encryption-info",
+
+            TextManifestDescription => "text-manifest-description",
+            TextUpdateDescription => "text-update-description",
+            TextManifestJsonSource => "text-manifest-json-source",
+            TextManifestYamlSource => "text-manifest-yaml-source",
+            TextVendorName => "text-vendor-name",
+            TextModelName => "text-model-name",
+            TextVendorDomain => "text-vendor-domain",
+            TextModelInfo => "text-model-info",
+            TextComponentDescription => "text-component-description",
+            TextComponentVersion => "text-component-version",
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! RFC 4122 UUIDs, as used to identify vendors, device classes and devices.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::cbor::Cbor;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Uuid(pub [u8; 16]);
+
+impl Uuid {
+    /// Namespace for UUIDs made from fully qualified domain names.
+    pub const NAMESPACE_DNS: Uuid = Uuid([
+        0x6b, 0xa7, 0xb8, 0x10, 0x9d, 0xad, 0x11, 0xd1, 0x80, 0xb4, 0x00, 0xc0, 0x4f, 0xd4, 0x30,
+        0xc8,
+    ]);
+
+    /// Create a name-based UUID (version 5) from a `name` within `namespace`.
+    pub fn new_v5(namespace: &Uuid, name: &[u8]) -> Self {
+        use openssl::sha::Sha1;
+
+        let mut hasher = Sha1::new();
+        hasher.update(&namespace.0);
+        hasher.update(name);
+
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(&hasher.finish()[..16]);
+
+        bytes[6] = (bytes[6] & 0x0f) | 0x50;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+        Self(bytes)
+    }
+
+    /// Vendor identifier derived from the domain name of the vendor, as
+    /// recommended by SUIT.
+    pub fn vendor_identifier(domain: &str) -> Self {
+        Self::new_v5(&Self::NAMESPACE_DNS, domain.as_bytes())
+    }
+
+    /// Class identifier derived from the vendor identifier and the name
+    /// of the device model, as recommended by SUIT.
+    pub fn class_identifier(vendor: &Uuid, model_name: &str) -> Self {
+        Self::new_v5(vendor, model_name.as_bytes())
+    }
+}
+
+impl FromStr for Uuid {
+    type Err = String;
+
+    /// Parse a UUID in its hyphenated string form,
+    /// e.g. `fa6b4a53-d5ad-5fdf-be9d-e663e4d41ffe`.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("`{}` is not a UUID", text);
+
+        let groups: Vec<&str> = text.split('-').collect();
+
+        if groups.iter().map(|group| group.len()).ne([8, 4, 4, 4, 12]) {
+            return Err(invalid());
+        }
+
+        let bytes = hex::decode(groups.concat()).map_err(|_| invalid())?;
+
+        Ok(Self(bytes.try_into().unwrap()))
+    }
+}
+
+impl fmt::Display for Uuid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes = &self.0;
+
+        write!(
+            f,
+            "{}-{}-{}-{}-{}",
+            hex::encode(&bytes[0..4]),
+            hex::encode(&bytes[4..6]),
+            hex::encode(&bytes[6..8]),
+            hex::encode(&bytes[8..10]),
+            hex::encode(&bytes[10..16])
+        )
+    }
+}
+
+impl From<Uuid> for Cbor {
+    fn from(uuid: Uuid) -> Cbor {
+        Cbor::Bstr(uuid.0.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_identifiers_from_names() {
+        let vendor = Uuid::vendor_identifier("example.com");
+        assert_eq!(vendor.to_string(), "cfbff0d1-9375-5685-968c-48ce8b15ae17");
+
+        let class = Uuid::class_identifier(&vendor, "foo");
+        assert_eq!(class.to_string(), "38ede018-f5f7-507b-b1e7-1f20a8a15545");
+    }
+
+    #[test]
+    fn parses_hyphenated_uuids() {
+        let text = "fa6b4a53-d5ad-5fdf-be9d-e663e4d41ffe";
+        let uuid: Uuid = text.parse().unwrap();
+
+        assert_eq!(uuid.0[..4], [0xfa, 0x6b, 0x4a, 0x53]);
+        assert_eq!(uuid.to_string(), text);
+
+        let upper: Uuid = text.to_uppercase().parse().unwrap();
+        assert_eq!(upper, uuid);
+    }
+
+    #[test]
+    fn rejects_malformed_uuids() {
+        for text in [
+            "",
+            "fa6b4a53d5ad5fdfbe9de663e4d41ffe",
+            "fa6b4a53-d5ad-5fdf-be9d-e663e4d41ff",
+            "fa6b4a53-d5ad-5fdf-be9d-e663e4d41ffe0",
+            "fa6b4a53-d5ad-5fdf-be9de663-e4d41ffe",
+            "fa6b4a53-d5ad-5fdf-be9d-e663e4d41ffe-",
+            "fa6b4a53-d5ad-5fdf-be9d-e663e4d41ffg",
+            "{fa6b4a53-d5ad-5fdf-be9d-e663e4d41ffe}",
+        ] {
+            assert_eq!(
+                text.parse::<Uuid>(),
+                Err(format!("`{}` is not a UUID", text))
+            );
+        }
+    }
+}
@@ -0,0 +1,1301 @@
+//! Verification of existing SUIT envelopes.
+//!
+//! An envelope is checked the way a device would before acting on it:
+//! its authentication blocks against the digest of the manifest, the
+//! integrated payloads against the digests and sizes the manifest gives
+//! for them, and the manifest against the structural rules of SUIT.
+//! Problems are collected as findings rather than ending the check at
+//! the first one.
+//!
+//! The command sequences are followed in the order common sequence,
+//! payload fetch, install, validate, load and run, keeping the
+//! parameters of each component, so that a fetched payload is checked
+//! against the image digest that applies to its component. Each
+//! alternative of a try-each directive is followed once, after which the
+//! commands that follow it are checked against the parameters that any
+//! of the alternatives may leave.
+
+use std::fmt;
+
+use crate::cbor::Cbor;
+use crate::cose::{MacKey, VerifyingKey};
+use crate::digest::Digest;
+use crate::inspect;
+use crate::suit_constant::{self, KeySpace, SuitConstant};
+
+/// Maximum nesting of command sequences in try-each and run-sequence
+/// directives that is followed.
+const MAX_SEQUENCE_DEPTH: usize = 16;
+
+/// How serious a finding is; an envelope with errors is rejected,
+/// whereas warnings point out what could not be checked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A problem found in an envelope, along with the path to the part of
+/// the envelope it was found in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.path.is_empty() {
+            true => write!(f, "{}: {}", self.severity, self.message),
+            false => write!(f, "{}: {}: {}", self.severity, self.path, self.message),
+        }
+    }
+}
+
+/// Key to check the authentication blocks of an envelope with.
+#[derive(Debug, Clone)]
+pub enum VerificationKey {
+    Signature(VerifyingKey),
+    Mac(MacKey),
+}
+
+/// Verify the encoded `envelope`, checking its authentication blocks
+/// with `keys`. Integrated dependencies are verified with the same keys.
+pub fn verify(envelope: &[u8], keys: &[VerificationKey]) -> Vec<Finding> {
+    let mut verifier = Verifier {
+        keys,
+        prefix: String::new(),
+        findings: Vec::new(),
+    };
+
+    verifier.envelope(envelope);
+
+    verifier.findings
+}
+
+/// The parameters that apply to a component or dependency. Each holds
+/// the values it may have; several if the alternatives of a try-each
+/// directive give different ones, and none if any of them gives none.
+#[derive(Debug, Clone, Default)]
+struct Parameters {
+    digests: Vec<Digest>,
+    sizes: Vec<u64>,
+    uris: Vec<String>,
+    source_component: Option<usize>,
+    is_encrypted: bool,
+}
+
+impl Parameters {
+    /// Combine these parameters with those that another alternative of a
+    /// try-each directive leaves.
+    fn merge(&mut self, other: &Parameters) {
+        merge_values(&mut self.digests, &other.digests);
+        merge_values(&mut self.sizes, &other.sizes);
+        merge_values(&mut self.uris, &other.uris);
+
+        if other.source_component.is_none() {
+            self.source_component = None;
+        }
+
+        self.is_encrypted |= other.is_encrypted;
+    }
+}
+
+/// Which components or dependencies subsequent commands apply to.
+#[derive(Debug, Clone)]
+enum Selection {
+    Components(Vec<usize>),
+    Dependencies(Vec<usize>),
+}
+
+/// A payload fetched into a component that had no image digest yet;
+/// it is checked once a digest is given for the component.
+#[derive(Debug, Clone, PartialEq)]
+struct PendingFetch {
+    path: String,
+    component: usize,
+    uri: String,
+}
+
+#[derive(Debug, Clone)]
+struct State {
+    components: Vec<Parameters>,
+    dependencies: Vec<Parameters>,
+    selection: Selection,
+    pending: Vec<PendingFetch>,
+}
+
+impl State {
+    /// Combine this state with the one that another alternative of a
+    /// try-each directive leaves.
+    fn merge(&mut self, other: &State) {
+        for (parameters, others) in self.components.iter_mut().zip(&other.components) {
+            parameters.merge(others);
+        }
+
+        for (parameters, others) in self.dependencies.iter_mut().zip(&other.dependencies) {
+            parameters.merge(others);
+        }
+
+        match (&mut self.selection, &other.selection) {
+            (Selection::Components(indices), Selection::Components(others))
+            | (Selection::Dependencies(indices), Selection::Dependencies(others)) => {
+                merge_values(indices, others)
+            }
+            _ => (),
+        }
+
+        for fetch in &other.pending {
+            if !self.pending.contains(fetch) {
+                self.pending.push(fetch.clone());
+            }
+        }
+    }
+}
+
+/// What is known of the envelope while following its command sequences.
+struct Context<'e> {
+    integrated: &'e [(String, Vec<u8>)],
+    referenced: Vec<String>,
+}
+
+struct Verifier<'k> {
+    keys: &'k [VerificationKey],
+    prefix: String,
+    findings: Vec<Finding>,
+}
+
+impl<'k> Verifier<'k> {
+    fn report(&mut self, severity: Severity, path: &str, message: String) {
+        let finding = Finding {
+            severity,
+            path: format!("{}{}", self.prefix, path),
+            message,
+        };
+
+        // The same problem may be found more than once, such as for each
+        // of the URIs that a fetch may use after a try-each directive.
+        if !self.findings.contains(&finding) {
+            self.findings.push(finding);
+        }
+    }
+
+    fn error(&mut self, path: &str, message: String) {
+        self.report(Severity::Error, path, message);
+    }
+
+    fn warning(&mut self, path: &str, message: String) {
+        self.report(Severity::Warning, path, message);
+    }
+
+    fn envelope(&mut self, bytes: &[u8]) {
+        let entries = match Cbor::deserialize(bytes) {
+            Ok(Cbor::Tag(suit_constant::SUIT_ENVELOPE_TAG, content)) => match *content {
+                Cbor::Map(entries) => entries,
+                _ => return self.error("", String::from("the envelope is not a map")),
+            },
+            Ok(Cbor::Map(entries)) => {
+                self.warning("", String::from("the envelope is not tagged"));
+                entries
+            }
+            Ok(_) => return self.error("", String::from("not a SUIT envelope")),
+            Err(error) => {
+                return self.error("", format!("could not decode the envelope: {}", error))
+            }
+        };
+
+        let mut members = Vec::new();
+        let mut integrated = Vec::new();
+
+        for (key, value) in entries {
+            let name = inspect::render_plain(&key);
+
+            let is_duplicate = members.iter().any(|(member, _)| *member == key)
+                || integrated
+                    .iter()
+                    .any(|(uri, _)| matches!(&key, Cbor::Tstr(text) if text == uri));
+
+            if is_duplicate {
+                self.error("", format!("the envelope member `{}` is given twice", name));
+                continue;
+            }
+
+            match (key, value) {
+                (Cbor::Tstr(uri), Cbor::Bstr(bytes)) => integrated.push((uri, bytes)),
+                (Cbor::Tstr(uri), _) => self.error(
+                    &uri,
+                    String::from("an integrated payload must be a byte string"),
+                ),
+                (key, value) => match SuitConstant::lookup(KeySpace::Envelope, &key) {
+                    Some(_) => members.push((key, value)),
+                    None => self.error("", format!("unknown envelope member `{}`", name)),
+                },
+            }
+        }
+
+        let member = |constant: SuitConstant| {
+            members
+                .iter()
+                .find(|(key, _)| *key == Cbor::from(constant))
+                .map(|(_, value)| value)
+        };
+
+        let manifest = match member(SuitConstant::Manifest) {
+            Some(Cbor::Bstr(manifest)) => manifest.clone(),
+            Some(_) => {
+                return self.error(
+                    SuitConstant::Manifest.name(),
+                    String::from("the manifest must be a byte string"),
+                )
+            }
+            None => return self.error("", String::from("the envelope contains no manifest")),
+        };
+
+        match member(SuitConstant::AuthenticationWrapper) {
+            Some(authentication) => self.authentication(authentication, &manifest),
+            None => self.error("", String::from("the envelope is not authenticated")),
+        }
+
+        let manifest = match Cbor::deserialize(&manifest) {
+            Ok(Cbor::Map(entries)) => entries,
+            _ => {
+                return self.error(
+                    SuitConstant::Manifest.name(),
+                    String::from("the manifest is not a map"),
+                )
+            }
+        };
+
+        let mut context = Context {
+            integrated: &integrated,
+            referenced: Vec::new(),
+        };
+
+        let dependencies = self.manifest(&manifest, &members, &mut context);
+
+        for (uri, _bytes) in &integrated {
+            if !context.referenced.contains(uri) {
+                self.warning(uri, String::from("the integrated payload is never fetched"));
+            }
+        }
+
+        for (uri, bytes) in integrated.iter().filter(|(uri, _)| uri.starts_with("d:")) {
+            self.dependency(uri, bytes, &dependencies);
+        }
+    }
+
+    fn authentication(&mut self, authentication: &Cbor, manifest: &[u8]) {
+        let path = SuitConstant::AuthenticationWrapper.name();
+
+        let elements = match authentication.decode_wrapped() {
+            Some(Cbor::Array(elements)) if !elements.is_empty() => elements,
+            _ => return self.error(path, String::from("malformed authentication wrapper")),
+        };
+
+        let digest_bytes = match &elements[0] {
+            Cbor::Bstr(bytes) => bytes.clone(),
+            _ => return self.error(path, String::from("malformed manifest digest")),
+        };
+
+        match Digest::from_cbor(&elements[0]).map(|digest| digest.matches(manifest)) {
+            Some(true) => (),
+            Some(false) => self.error(path, String::from("the manifest digest does not match")),
+            None => self.error(
+                path,
+                String::from("malformed or unsupported manifest digest"),
+            ),
+        }
+
+        let blocks = &elements[1..];
+
+        if blocks.is_empty() {
+            return self.error(path, String::from("the manifest digest is not signed"));
+        }
+
+        let mut is_authenticated = false;
+
+        for (index, block) in blocks.iter().enumerate() {
+            let block_path = format!("{}[{}]", path, index + 1);
+
+            let block = match block.decode_wrapped() {
+                Some(block) => block,
+                None => {
+                    self.error(&block_path, String::from("malformed authentication block"));
+                    continue;
+                }
+            };
+
+            let results: Vec<_> = match &block {
+                Cbor::Tag(suit_constant::COSE_SIGN1_TAG, _) => self
+                    .keys
+                    .iter()
+                    .filter_map(|key| match key {
+                        VerificationKey::Signature(key) => Some(key.verify1(&block, &digest_bytes)),
+                        VerificationKey::Mac(_) => None,
+                    })
+                    .collect(),
+                Cbor::Tag(suit_constant::COSE_MAC0_TAG, _) => self
+                    .keys
+                    .iter()
+                    .filter_map(|key| match key {
+                        VerificationKey::Mac(key) => Some(key.verify_mac0(&block, &digest_bytes)),
+                        VerificationKey::Signature(_) => None,
+                    })
+                    .collect(),
+                _ => {
+                    self.error(
+                        &block_path,
+                        String::from(
+                            "the authentication block is neither COSE_Sign1 nor COSE_Mac0",
+                        ),
+                    );
+                    continue;
+                }
+            };
+
+            // A block that none of the keys verifies is not an error by
+            // itself, as each block may be made by a different authority.
+            if results.iter().any(Result::is_ok) {
+                is_authenticated = true;
+            }
+        }
+
+        match (self.keys.is_empty(), is_authenticated) {
+            (true, _) => self.warning(
+                path,
+                String::from("no key given; the authentication blocks are not checked"),
+            ),
+            (false, true) => (),
+            (false, false) => self.error(
+                path,
+                String::from("no authentication block can be verified with the given keys"),
+            ),
+        }
+    }
+
+    /// Check the manifest, following its command sequences. Returns the
+    /// digests of the manifests it depends on.
+    fn manifest(
+        &mut self,
+        manifest: &[(Cbor, Cbor)],
+        members: &[(Cbor, Cbor)],
+        context: &mut Context,
+    ) -> Vec<Option<Digest>> {
+        use SuitConstant::*;
+
+        let path = Manifest.name();
+
+        for (key, _value) in manifest {
+            if SuitConstant::lookup(KeySpace::Manifest, key).is_none() {
+                let name = inspect::render_plain(key);
+                self.error(path, format!("unknown manifest member `{}`", name));
+            }
+        }
+
+        let member = |constant: SuitConstant| {
+            manifest
+                .iter()
+                .find(|(key, _)| *key == Cbor::from(constant))
+                .map(|(_, value)| value)
+        };
+
+        match member(ManifestVersion) {
+            Some(Cbor::Uint(1)) => (),
+            Some(version) => {
+                let version = inspect::render_plain(version);
+                self.error(path, format!("unsupported manifest version `{}`", version))
+            }
+            None => self.error(path, String::from("the manifest has no version")),
+        }
+
+        match member(ManifestSequenceNumber) {
+            Some(Cbor::Uint(_)) => (),
+            Some(_) => self.error(path, String::from("malformed sequence number")),
+            None => self.error(path, String::from("the manifest has no sequence number")),
+        }
+
+        let common = match member(Common).and_then(Cbor::decode_wrapped) {
+            Some(Cbor::Map(common)) => common,
+            Some(_) => {
+                self.error(
+                    Common.name(),
+                    String::from("the common section is not a map"),
+                );
+                return Vec::new();
+            }
+            None => {
+                self.error(path, String::from("the manifest has no common section"));
+                return Vec::new();
+            }
+        };
+
+        let (component_count, dependencies, common_sequence) = self.common(&common);
+
+        // Severed members are checked against the digest the manifest
+        // keeps in their place.
+        for (key, value) in members {
+            let constant = SuitConstant::lookup(KeySpace::Envelope, key);
+
+            if let Some(constant @ (PayloadFetch | Install | Text)) = constant {
+                match (member(constant), value) {
+                    (Some(digest @ Cbor::Array(_)), Cbor::Bstr(bytes)) => {
+                        match Digest::from_cbor(digest).map(|digest| digest.matches(bytes)) {
+                            Some(true) => (),
+                            Some(false) => self.error(
+                                constant.name(),
+                                String::from("the severed member does not match its digest"),
+                            ),
+                            None => self.error(
+                                constant.name(),
+                                String::from("malformed or unsupported digest"),
+                            ),
+                        }
+                    }
+                    (Some(_), _) => self.error(
+                        constant.name(),
+                        String::from(
+                            "the member is severed, but the manifest does not hold its digest",
+                        ),
+                    ),
+                    (None, _) => self.error(
+                        constant.name(),
+                        String::from("the member is severed, but is not part of the manifest"),
+                    ),
+                }
+            }
+        }
+
+        let mut state = State {
+            components: vec![Parameters::default(); component_count],
+            dependencies: vec![Parameters::default(); dependencies.len()],
+            selection: Selection::Components((0..component_count.min(1)).collect()),
+            pending: Vec::new(),
+        };
+
+        if let Some(commands) =
+            common_sequence.and_then(|sequence| self.commands(CommonSequence.name(), &sequence))
+        {
+            self.sequence(CommonSequence.name(), &commands, &mut state, 0, context);
+        }
+
+        for constant in [PayloadFetch, Install, Validate, Load, Run] {
+            let sequence = match member(constant) {
+                Some(Cbor::Array(_)) if matches!(constant, PayloadFetch | Install) => {
+                    match members.iter().find(|(key, _)| *key == Cbor::from(constant)) {
+                        Some((_, severed)) => severed,
+                        None => {
+                            self.warning(
+                                constant.name(),
+                                String::from("the member is severed and not in the envelope; it is not checked"),
+                            );
+                            continue;
+                        }
+                    }
+                }
+                Some(sequence) => sequence,
+                None => continue,
+            };
+
+            if let Some(commands) = self.commands(constant.name(), sequence) {
+                self.sequence(constant.name(), &commands, &mut state, 0, context);
+            }
+        }
+
+        for fetch in state.pending {
+            self.error(
+                &fetch.path,
+                format!(
+                    "component {} is given no image digest to check `{}` against",
+                    fetch.component, fetch.uri
+                ),
+            );
+        }
+
+        dependencies
+    }
+
+    /// Check the common section; returns the number of components, the
+    /// digests of the dependencies and the common sequence.
+    fn common(&mut self, common: &[(Cbor, Cbor)]) -> (usize, Vec<Option<Digest>>, Option<Cbor>) {
+        use SuitConstant::*;
+
+        let path = Common.name();
+
+        let mut component_count = 0;
+        let mut dependencies = Vec::new();
+        let mut common_sequence = None;
+
+        for (key, value) in common {
+            match SuitConstant::lookup(KeySpace::Common, key) {
+                Some(Components) => match value {
+                    Cbor::Array(components) if !components.is_empty() => {
+                        component_count = components.len();
+
+                        for (index, component) in components.iter().enumerate() {
+                            let is_valid = match component {
+                                Cbor::Array(parts) => {
+                                    parts.iter().all(|part| matches!(part, Cbor::Bstr(_)))
+                                }
+                                _ => false,
+                            };
+
+                            if !is_valid {
+                                self.error(
+                                    Components.name(),
+                                    format!("component {} has a malformed identifier", index),
+                                );
+                            }
+                        }
+                    }
+                    _ => self.error(path, String::from("the manifest lists no components")),
+                },
+                Some(Dependencies) => match value {
+                    Cbor::Array(entries) => {
+                        for (index, entry) in entries.iter().enumerate() {
+                            let digest = match entry {
+                                Cbor::Map(members) => members
+                                    .iter()
+                                    .find(|(key, _)| *key == Cbor::from(DependencyDigest))
+                                    .and_then(|(_, digest)| Digest::from_cbor(digest)),
+                                _ => None,
+                            };
+
+                            if digest.is_none() {
+                                self.error(
+                                    Dependencies.name(),
+                                    format!("dependency {} has no valid digest", index),
+                                );
+                            }
+
+                            dependencies.push(digest);
+                        }
+                    }
+                    _ => self.error(path, String::from("malformed dependencies")),
+                },
+                Some(CommonSequence) => common_sequence = Some(value.clone()),
+                _ => {
+                    let name = inspect::render_plain(key);
+                    self.error(path, format!("unknown common member `{}`", name));
+                }
+            }
+        }
+
+        if !common.iter().any(|(key, _)| *key == Cbor::from(Components)) {
+            self.error(path, String::from("the manifest lists no components"));
+        }
+
+        (component_count, dependencies, common_sequence)
+    }
+
+    /// Decode a command sequence into pairs of a command and its argument.
+    fn commands(&mut self, path: &str, sequence: &Cbor) -> Option<Vec<(Cbor, Cbor)>> {
+        match sequence.decode_wrapped() {
+            Some(Cbor::Array(items)) if items.len() % 2 == 0 => Some(
+                items
+                    .chunks(2)
+                    .map(|pair| (pair[0].clone(), pair[1].clone()))
+                    .collect(),
+            ),
+            _ => {
+                self.error(path, String::from("malformed command sequence"));
+                None
+            }
+        }
+    }
+
+    /// Follow the `commands` of a sequence nested `depth` levels deep,
+    /// updating `state`.
+    fn sequence(
+        &mut self,
+        path: &str,
+        commands: &[(Cbor, Cbor)],
+        state: &mut State,
+        depth: usize,
+        context: &mut Context,
+    ) {
+        use SuitConstant::*;
+
+        if depth > MAX_SEQUENCE_DEPTH {
+            return self.error(
+                path,
+                String::from("command sequences are nested too deeply to be checked"),
+            );
+        }
+
+        for (position, (command, argument)) in commands.iter().enumerate() {
+            let path = format!("{}[{}]", path, position);
+
+            match SuitConstant::lookup(KeySpace::Command, command) {
+                Some(DirectiveSetComponentIndex) => {
+                    if let Some(indices) =
+                        self.indices(&path, argument, state.components.len(), "component")
+                    {
+                        state.selection = Selection::Components(indices);
+                    }
+                }
+                Some(DirectiveSetDependencyIndex) => {
+                    if let Some(indices) =
+                        self.indices(&path, argument, state.dependencies.len(), "dependency")
+                    {
+                        state.selection = Selection::Dependencies(indices);
+                    }
+                }
+                Some(DirectiveOverrideParameters) => {
+                    self.override_parameters(&path, argument, state, context)
+                }
+                Some(DirectiveFetch) => self.fetch(&path, state, context),
+                Some(DirectiveCopy | DirectiveSwap) => match &state.selection {
+                    Selection::Components(indices) => {
+                        if indices
+                            .iter()
+                            .any(|index| state.components[*index].source_component.is_none())
+                        {
+                            self.error(&path, String::from("no source component is given"));
+                        }
+                    }
+                    Selection::Dependencies(_) => self.error(
+                        &path,
+                        String::from("a dependency cannot be the destination"),
+                    ),
+                },
+                Some(ConditionImageMatch) => match &state.selection {
+                    Selection::Components(indices) => {
+                        if indices
+                            .iter()
+                            .any(|index| state.components[*index].digests.is_empty())
+                        {
+                            self.error(&path, String::from("no image digest to match is given"));
+                        }
+                    }
+                    Selection::Dependencies(_) => (),
+                },
+                Some(DirectiveProcessDependency) => {
+                    if let Selection::Components(_) = state.selection {
+                        self.error(&path, String::from("no dependency is selected"));
+                    }
+                }
+                Some(DirectiveRunSequence) => {
+                    if let Some(commands) = self.commands(&path, argument) {
+                        self.sequence(&path, &commands, state, depth + 1, context);
+                    }
+                }
+                Some(DirectiveTryEach) => {
+                    let alternatives = match argument {
+                        Cbor::Array(alternatives) if !alternatives.is_empty() => alternatives,
+                        _ => {
+                            self.error(&path, String::from("try-each needs alternatives"));
+                            continue;
+                        }
+                    };
+
+                    // Each alternative is followed from its own copy of the
+                    // state, and the commands that follow the directive from
+                    // what any of the alternatives may leave.
+                    let mut merged: Option<State> = None;
+
+                    for (index, alternative) in alternatives.iter().enumerate() {
+                        let alternative_path = format!("{}[{}]", path, index);
+
+                        if let Some(commands) = self.commands(&alternative_path, alternative) {
+                            let mut branch = state.clone();
+                            self.sequence(
+                                &alternative_path,
+                                &commands,
+                                &mut branch,
+                                depth + 1,
+                                context,
+                            );
+
+                            match &mut merged {
+                                Some(merged) => merged.merge(&branch),
+                                None => merged = Some(branch),
+                            }
+                        }
+                    }
+
+                    if let Some(merged) = merged {
+                        *state = merged;
+                    }
+                }
+                Some(_) => (),
+                None => {
+                    let name = inspect::render_plain(command);
+                    self.error(&path, format!("unknown command `{}`", name));
+                }
+            }
+        }
+    }
+
+    /// Check the argument of a set-index directive against the number of
+    /// components or dependencies.
+    fn indices(
+        &mut self,
+        path: &str,
+        argument: &Cbor,
+        count: usize,
+        kind: &str,
+    ) -> Option<Vec<usize>> {
+        let indices = match argument {
+            Cbor::Uint(index) => vec![*index],
+            Cbor::True => return Some((0..count).collect()),
+            Cbor::Array(indices) => {
+                let indices: Option<Vec<u64>> = indices
+                    .iter()
+                    .map(|index| match index {
+                        Cbor::Uint(index) => Some(*index),
+                        _ => None,
+                    })
+                    .collect();
+
+                match indices {
+                    Some(indices) => indices,
+                    None => {
+                        self.error(path, format!("malformed {} index", kind));
+                        return None;
+                    }
+                }
+            }
+            _ => {
+                self.error(path, format!("malformed {} index", kind));
+                return None;
+            }
+        };
+
+        match indices.iter().find(|index| **index >= count as u64) {
+            Some(index) => {
+                self.error(
+                    path,
+                    format!(
+                        "{} index {} is out of bounds; there are {}",
+                        kind, index, count
+                    ),
+                );
+                None
+            }
+            None => Some(indices.into_iter().map(|index| index as usize).collect()),
+        }
+    }
+
+    fn override_parameters(
+        &mut self,
+        path: &str,
+        argument: &Cbor,
+        state: &mut State,
+        context: &mut Context,
+    ) {
+        use SuitConstant::*;
+
+        let entries = match argument {
+            Cbor::Map(entries) => entries,
+            _ => return self.error(path, String::from("the parameters are not a map")),
+        };
+
+        let mut parameters = Vec::new();
+
+        for (key, value) in entries {
+            let constant = SuitConstant::lookup(KeySpace::Parameter, key);
+
+            let is_valid = match (constant, value) {
+                (Some(ParameterImageDigest), digest) => match Digest::from_cbor(digest) {
+                    Some(digest) => {
+                        parameters.push(Parameter::Digest(digest));
+                        true
+                    }
+                    None => false,
+                },
+                (Some(ParameterImageSize), Cbor::Uint(size)) => {
+                    parameters.push(Parameter::Size(*size));
+                    true
+                }
+                (Some(ParameterUri), Cbor::Tstr(uri)) => {
+                    parameters.push(Parameter::Uri(uri.clone()));
+                    true
+                }
+                (Some(ParameterSourceComponent), Cbor::Uint(index)) => {
+                    match (*index as usize) < state.components.len() {
+                        true => {
+                            parameters.push(Parameter::Source(*index as usize));
+                        }
+                        false => {
+                            self.error(path, format!("source component {} is out of bounds", index))
+                        }
+                    }
+                    true
+                }
+                (Some(ParameterEncryptionInfo), _) => {
+                    parameters.push(Parameter::Encrypted);
+                    true
+                }
+                (Some(ParameterImageSize | ParameterUri | ParameterSourceComponent), _) => false,
+                (Some(_), _) => true,
+                (None, _) => {
+                    let name = inspect::render_plain(key);
+                    self.error(path, format!("unknown parameter `{}`", name));
+                    continue;
+                }
+            };
+
+            if !is_valid {
+                let name = constant.map(|constant| constant.name()).unwrap_or_default();
+                self.error(path, format!("malformed parameter `{}`", name));
+            }
+        }
+
+        let (targets, is_component) = match &mut state.selection {
+            Selection::Components(indices) => (indices.clone(), true),
+            Selection::Dependencies(indices) => (indices.clone(), false),
+        };
+
+        for index in targets {
+            let target = match is_component {
+                true => &mut state.components[index],
+                false => &mut state.dependencies[index],
+            };
+
+            for parameter in &parameters {
+                match parameter {
+                    Parameter::Digest(digest) => target.digests = vec![digest.clone()],
+                    Parameter::Size(size) => target.sizes = vec![*size],
+                    Parameter::Uri(uri) => target.uris = vec![uri.clone()],
+                    Parameter::Source(source) => target.source_component = Some(*source),
+                    Parameter::Encrypted => target.is_encrypted = true,
+                }
+            }
+
+            if !is_component || target.digests.is_empty() {
+                continue;
+            }
+
+            let target = target.clone();
+            let (ready, pending) = std::mem::take(&mut state.pending)
+                .into_iter()
+                .partition(|fetch: &PendingFetch| fetch.component == index);
+            state.pending = pending;
+
+            for fetch in ready {
+                self.payload(&fetch.path, &fetch.uri, &target, context);
+            }
+        }
+    }
+
+    fn fetch(&mut self, path: &str, state: &mut State, context: &mut Context) {
+        let (indices, is_component) = match &state.selection {
+            Selection::Components(indices) => (indices.clone(), true),
+            Selection::Dependencies(indices) => (indices.clone(), false),
+        };
+
+        for index in indices {
+            let target = match is_component {
+                true => &state.components[index],
+                false => &state.dependencies[index],
+            };
+
+            if target.uris.is_empty() {
+                self.error(path, String::from("no URI to fetch from is given"));
+                continue;
+            }
+
+            let target = target.clone();
+
+            for uri in &target.uris {
+                // Integrated dependencies are checked against the digests of
+                // the common section once the sequences have been followed.
+                if !is_component {
+                    self.reference(path, uri, context);
+                    continue;
+                }
+
+                match target.digests.is_empty() {
+                    false => self.payload(path, uri, &target, context),
+                    true => {
+                        let fetch = PendingFetch {
+                            path: String::from(path),
+                            component: index,
+                            uri: uri.clone(),
+                        };
+
+                        if !state.pending.contains(&fetch) {
+                            state.pending.push(fetch);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Note that `uri` is fetched; returns the integrated payload it
+    /// refers to, if any.
+    fn reference<'e>(
+        &mut self,
+        path: &str,
+        uri: &str,
+        context: &mut Context<'e>,
+    ) -> Option<&'e [u8]> {
+        if !context
+            .referenced
+            .iter()
+            .any(|referenced| referenced == uri)
+        {
+            context.referenced.push(String::from(uri));
+        }
+
+        let integrated = context
+            .integrated
+            .iter()
+            .find(|(integrated, _)| integrated == uri)
+            .map(|(_, bytes)| bytes.as_slice());
+
+        if integrated.is_none() && is_integrated_uri(uri) {
+            self.error(path, format!("`{}` is not in the envelope", uri));
+        }
+
+        integrated
+    }
+
+    /// Check the payload fetched from `uri` against the digest and size
+    /// given for its component.
+    fn payload(&mut self, path: &str, uri: &str, parameters: &Parameters, context: &mut Context) {
+        let bytes = match self.reference(path, uri, context) {
+            Some(bytes) => bytes,
+            None if is_integrated_uri(uri) => return,
+            None => {
+                return self.warning(
+                    path,
+                    format!("`{}` is detached; its digest is not checked", uri),
+                )
+            }
+        };
+
+        if parameters.is_encrypted {
+            return self.warning(
+                path,
+                format!("`{}` is encrypted; its digest is not checked", uri),
+            );
+        }
+
+        if !parameters.digests.is_empty()
+            && !parameters
+                .digests
+                .iter()
+                .any(|digest| digest.matches(bytes))
+        {
+            self.error(path, format!("the digest of `{}` does not match", uri));
+        }
+
+        if !parameters.sizes.is_empty() && !parameters.sizes.contains(&(bytes.len() as u64)) {
+            let sizes: Vec<String> = parameters.sizes.iter().map(u64::to_string).collect();
+
+            self.error(
+                path,
+                format!(
+                    "the size of `{}` is {} bytes, but {} bytes are expected",
+                    uri,
+                    bytes.len(),
+                    sizes.join(" or ")
+                ),
+            );
+        }
+    }
+
+    /// Verify an integrated dependency, which must be the manifest of one
+    /// of the `dependencies` of the common section.
+    fn dependency(&mut self, uri: &str, envelope: &[u8], dependencies: &[Option<Digest>]) {
+        let manifest = match Cbor::deserialize(envelope) {
+            Ok(Cbor::Tag(suit_constant::SUIT_ENVELOPE_TAG, content)) => match *content {
+                Cbor::Map(entries) => entries,
+                _ => Vec::new(),
+            },
+            Ok(Cbor::Map(entries)) => entries,
+            _ => Vec::new(),
+        }
+        .into_iter()
+        .find_map(|(key, value)| match value {
+            Cbor::Bstr(manifest) if key == Cbor::from(SuitConstant::Manifest) => Some(manifest),
+            _ => None,
+        });
+
+        if let Some(manifest) = manifest {
+            let is_declared = dependencies
+                .iter()
+                .flatten()
+                .any(|digest| digest.matches(&manifest));
+
+            if !is_declared {
+                self.error(
+                    uri,
+                    String::from("the integrated envelope is not one of the dependencies"),
+                );
+            }
+        }
+
+        let mut nested = Verifier {
+            keys: self.keys,
+            prefix: format!("{}{}/", self.prefix, uri),
+            findings: Vec::new(),
+        };
+
+        nested.envelope(envelope);
+
+        for finding in nested.findings {
+            if !self.findings.contains(&finding) {
+                self.findings.push(finding);
+            }
+        }
+    }
+}
+
+/// A parameter value that is kept while following the sequences.
+#[derive(Debug, Clone)]
+enum Parameter {
+    Digest(Digest),
+    Size(u64),
+    Uri(String),
+    Source(usize),
+    Encrypted,
+}
+
+/// Combine the values a parameter may have after one alternative of a
+/// try-each directive with those of another; it is only known to be given
+/// if both alternatives give it.
+fn merge_values<T: Clone + PartialEq>(values: &mut Vec<T>, others: &[T]) {
+    if others.is_empty() {
+        values.clear();
+    }
+
+    if values.is_empty() {
+        return;
+    }
+
+    for value in others {
+        if !values.contains(value) {
+            values.push(value.clone());
+        }
+    }
+}
+
+/// Whether `uri` refers to an item integrated in the envelope.
+fn is_integrated_uri(uri: &str) -> bool {
+    ["p:", "cp:", "d:"]
+        .iter()
+        .any(|scheme| uri.starts_with(scheme))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cose::{MacAlgorithm, SigningKey};
+    use crate::digest::DigestAlgorithm;
+    use crate::envelope::EnvelopeBuilder;
+    use crate::manifest::{self, Command, ComponentIdentifier, ManifestBuilder, ReportingPolicy};
+    use crate::payload::Payload;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+
+    /// Write `content` to a file of its own, for the keys that are only
+    /// loaded from files, and return its path.
+    fn key_file(content: &[u8]) -> std::path::PathBuf {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "suit-verify-{}-{}.key",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&path, content).unwrap();
+
+        path
+    }
+
+    fn mac_key() -> MacKey {
+        let path = key_file(&[0x5a; 32]);
+        let key = MacKey::from_file(&path, MacAlgorithm::Hmac256).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        key
+    }
+
+    fn payload(index: usize, start_address: u32, bytes: &[u8]) -> Payload {
+        Payload {
+            uri: format!("p:{}", index),
+            start_address,
+            size: bytes.len(),
+            memory_size: bytes.len(),
+            bytes: bytes.to_vec(),
+            encryption: None,
+        }
+    }
+
+    fn errors(findings: &[Finding]) -> Vec<String> {
+        findings
+            .iter()
+            .filter(|finding| finding.severity == Severity::Error)
+            .map(Finding::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn accepts_generated_envelopes() {
+        let payloads = vec![
+            payload(0, 0x0800_0000, &[0x01, 0x02, 0x03, 0x04]),
+            payload(1, 0x0801_0000, &[0x05, 0x06]),
+        ];
+
+        let manifest = ManifestBuilder::for_payloads(&payloads, DigestAlgorithm::Sha256).build();
+        let envelope = EnvelopeBuilder::new(manifest)
+            .mac_with(mac_key())
+            .integrate(payloads)
+            .build()
+            .serialize();
+
+        let findings = verify(&envelope, &[VerificationKey::Mac(mac_key())]);
+        assert_eq!(findings, Vec::new());
+    }
+
+    #[test]
+    fn accepts_signed_envelopes() {
+        let key =
+            EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap()).unwrap();
+
+        let private = key_file(&key.private_key_to_pem().unwrap());
+        let public = key_file(&key.public_key_to_pem().unwrap());
+        let signing_key = SigningKey::from_pem_file(&private).unwrap();
+        let verifying_key = VerifyingKey::from_pem_file(&public).unwrap();
+        std::fs::remove_file(private).unwrap();
+        std::fs::remove_file(public).unwrap();
+
+        let payloads = vec![payload(0, 0x1000, &[0xaa; 16])];
+
+        let manifest = ManifestBuilder::for_payloads(&payloads, DigestAlgorithm::Sha256).build();
+        let envelope = EnvelopeBuilder::new(manifest)
+            .sign_with(signing_key)
+            .integrate(payloads)
+            .build()
+            .serialize();
+
+        let findings = verify(&envelope, &[VerificationKey::Signature(verifying_key)]);
+        assert_eq!(findings, Vec::new());
+
+        assert_eq!(
+            errors(&verify(&envelope, &[VerificationKey::Mac(mac_key())])),
+            vec!["error: authentication-wrapper: no authentication block can be verified with the given keys"]
+        );
+    }
+
+    #[test]
+    fn rejects_tampered_payloads() {
+        let payloads = vec![payload(0, 0x1000, &[0x01, 0x02, 0x03, 0x04])];
+        let tampered = vec![payload(0, 0x1000, &[0x01, 0x02, 0x03, 0x05, 0x06])];
+
+        let manifest = ManifestBuilder::for_payloads(&payloads, DigestAlgorithm::Sha256).build();
+        let envelope = EnvelopeBuilder::new(manifest)
+            .mac_with(mac_key())
+            .integrate(tampered)
+            .build()
+            .serialize();
+
+        let findings = verify(&envelope, &[VerificationKey::Mac(mac_key())]);
+
+        assert_eq!(
+            errors(&findings),
+            vec![
+                "error: install[2]: the digest of `p:0` does not match",
+                "error: install[2]: the size of `p:0` is 5 bytes, but 4 bytes are expected",
+            ]
+        );
+    }
+
+    /// Build an envelope with a single component, whose integrated
+    /// payload holds `bytes` and which is fetched in `load`.
+    fn envelope_with_validate(validate: Vec<Command>, bytes: &[u8]) -> Vec<u8> {
+        let payload = payload(0, 0, bytes);
+
+        let load = manifest::SequenceBuilder::new()
+            .set_component_index(0)
+            .override_parameters(manifest::Parameter::for_fetching(&payload))
+            .command(Command::DirectiveFetch(ReportingPolicy::all()))
+            .build();
+
+        let manifest = ManifestBuilder::new(vec![ComponentIdentifier(0)])
+            .validate(validate)
+            .load(load)
+            .build();
+
+        EnvelopeBuilder::new(manifest)
+            .mac_with(mac_key())
+            .integrate(vec![payload])
+            .build()
+            .serialize()
+    }
+
+    #[test]
+    fn checks_payloads_against_each_try_each_alternative() {
+        let digest = |bytes: &[u8]| {
+            vec![Command::DirectiveOverrideParameters(vec![
+                manifest::Parameter::ImageDigest(DigestAlgorithm::Sha256.apply(bytes)),
+            ])]
+        };
+
+        let validate = manifest::SequenceBuilder::new()
+            .set_component_index(0)
+            .try_each(vec![digest(b"first"), digest(b"second")])
+            .command(Command::ConditionImageMatch(ReportingPolicy::all()))
+            .build();
+
+        let keys = [VerificationKey::Mac(mac_key())];
+
+        for bytes in [b"first".as_slice(), b"second"] {
+            let envelope = envelope_with_validate(validate.clone(), bytes);
+            assert_eq!(errors(&verify(&envelope, &keys)), Vec::<String>::new());
+        }
+
+        let envelope = envelope_with_validate(validate, b"third");
+        assert_eq!(
+            errors(&verify(&envelope, &keys)),
+            vec!["error: load[2]: the digest of `p:0` does not match"]
+        );
+    }
+
+    #[test]
+    fn limits_the_nesting_of_sequences() {
+        let nested = |depth| {
+            let mut sequence = vec![Command::DirectiveOverrideParameters(vec![
+                manifest::Parameter::ImageDigest(DigestAlgorithm::Sha256.apply(b"image")),
+            ])];
+
+            for _ in 0..depth {
+                sequence = vec![Command::DirectiveTryEach(vec![sequence])];
+            }
+
+            sequence
+        };
+
+        let keys = [VerificationKey::Mac(mac_key())];
+
+        let envelope = envelope_with_validate(nested(MAX_SEQUENCE_DEPTH), b"image");
+        assert_eq!(errors(&verify(&envelope, &keys)), Vec::<String>::new());
+
+        let envelope = envelope_with_validate(nested(MAX_SEQUENCE_DEPTH + 1), b"image");
+        let errors = errors(&verify(&envelope, &keys));
+
+        // The image digest is given past the limit, so it is never seen.
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].ends_with("command sequences are nested too deeply to be checked"));
+        assert_eq!(
+            errors[1],
+            "error: load[2]: component 0 is given no image digest to check `p:0` against"
+        );
+    }
+}